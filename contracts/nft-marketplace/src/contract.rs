@@ -1,15 +1,19 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    from_slice, to_binary, to_vec, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response,
-    StdError, StdResult,
+    from_slice, to_binary, to_vec, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Order, Reply,
+    Response, StdError, StdResult, SubMsgResult,
 };
 use cw2::set_contract_version;
+use cw_storage_plus::Map;
 
 use crate::error::ContractError;
+use crate::execute::HOOK_REPLY_ID;
 use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
-use crate::order_state::orders;
-use crate::state::{contract, Config, ConfigOld, ListingStatus};
+use crate::order_state::{orders_old, OrderComponents};
+use crate::state::{
+    contract, sealed_bid_commitment, Config, ConfigOld, ListingStatus, DEFAULT_REWARD_MULTIPLIER,
+};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:nft-marketplace";
@@ -26,6 +30,8 @@ pub fn instantiate(
     let conf = Config {
         owner: msg.owner,
         vaura_address: Addr::unchecked("aura0"),
+        pull_payments: false,
+        referral_fee_bps: 0,
     };
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     contract().config.save(deps.storage, &conf)?;
@@ -48,6 +54,7 @@ pub fn execute(
             contract_address,
             token_id,
             auction_config,
+            reserved_for,
         } => contract().execute_list_nft(
             deps,
             _env,
@@ -55,16 +62,35 @@ pub fn execute(
             api.addr_validate(&contract_address)?,
             token_id,
             auction_config,
+            reserved_for
+                .map(|buyer| api.addr_validate(&buyer))
+                .transpose()?,
         ),
         ExecuteMsg::Buy {
             contract_address,
             token_id,
+            referrer,
         } => contract().execute_buy(
             deps,
             _env,
             info,
             api.addr_validate(&contract_address)?,
             token_id,
+            referrer
+                .map(|referrer| api.addr_validate(&referrer))
+                .transpose()?,
+        ),
+        ExecuteMsg::SweepCollection {
+            contract_address,
+            max_items,
+            max_price_per_item,
+        } => contract().execute_sweep_collection(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            max_items,
+            max_price_per_item,
         ),
         ExecuteMsg::Cancel {
             contract_address,
@@ -91,23 +117,351 @@ pub fn execute(
             nft,
             funds_amount,
             end_time,
-        } => contract().execute_offer_nft(deps, _env, info, nft, funds_amount, end_time),
-        ExecuteMsg::AcceptNftOffer {
             offerer,
+        } => contract().execute_offer_nft(
+            deps,
+            _env,
+            info,
             nft,
             funds_amount,
+            end_time,
+            offerer
+                .map(|offerer| api.addr_validate(&offerer))
+                .transpose()?,
+        ),
+        ExecuteMsg::AcceptNftOffer {
+            order_id,
+            funds_amount,
+            referrer,
         } => contract().execute_accept_nft_offer(
             deps,
             _env,
             info,
-            api.addr_validate(&offerer)?,
-            nft,
+            order_id,
             funds_amount,
+            referrer
+                .map(|referrer| api.addr_validate(&referrer))
+                .transpose()?,
         ),
-        ExecuteMsg::CancelOffer { nfts } => contract().execute_cancel_offer(deps, _env, info, nfts),
+        ExecuteMsg::CancelOffer { order_ids } => {
+            contract().execute_cancel_offer(deps, _env, info, order_ids)
+        }
         ExecuteMsg::EditVauraToken { token_address } => {
             contract().execute_edit_vaura_token(deps, _env, info, token_address)
         }
+        ExecuteMsg::CreateOrder {
+            offer,
+            consideration,
+            start_time,
+            end_time,
+        } => contract().execute_create_order(
+            deps,
+            _env,
+            info,
+            offer,
+            consideration,
+            start_time,
+            end_time,
+        ),
+        ExecuteMsg::FulfillOrder { order_id } => {
+            contract().execute_fulfill_order(deps, _env, info, order_id)
+        }
+        ExecuteMsg::CancelOrder { order_id } => {
+            contract().execute_cancel_order(deps, _env, info, order_id)
+        }
+        ExecuteMsg::FulfillSignedOrder {
+            order,
+            signature,
+            public_key,
+        } => {
+            contract().execute_fulfill_signed_order(deps, _env, info, order, signature, public_key)
+        }
+        ExecuteMsg::CancelSignedOrders { orders } => {
+            contract().execute_cancel_signed_orders(deps, _env, info, orders)
+        }
+        ExecuteMsg::IncrementCounter {} => contract().execute_increment_counter(deps, _env, info),
+        ExecuteMsg::ListBundle {
+            nfts,
+            auction_config,
+        } => contract().execute_list_bundle(deps, _env, info, nfts, auction_config),
+        ExecuteMsg::BuyBundle {
+            bundle_id,
+            referrer,
+        } => contract().execute_buy_bundle(
+            deps,
+            _env,
+            info,
+            bundle_id,
+            referrer
+                .map(|referrer| api.addr_validate(&referrer))
+                .transpose()?,
+        ),
+        ExecuteMsg::CancelBundle { bundle_id } => {
+            contract().execute_cancel_bundle(deps, _env, info, bundle_id)
+        }
+        ExecuteMsg::CreateReverseAuction { nft, end_time } => {
+            contract().execute_create_reverse_auction(deps, _env, info, nft, end_time)
+        }
+        ExecuteMsg::SubmitAsk {
+            auction_id,
+            token_id,
+            price,
+        } => contract().execute_submit_ask(deps, _env, info, auction_id, token_id, price),
+        ExecuteMsg::SettleReverseAuction { auction_id } => {
+            contract().execute_settle_reverse_auction(deps, _env, info, auction_id)
+        }
+        ExecuteMsg::CancelReverseAuction { auction_id } => {
+            contract().execute_cancel_reverse_auction(deps, _env, info, auction_id)
+        }
+        ExecuteMsg::CommitBid {
+            contract_address,
+            token_id,
+            commitment,
+        } => contract().execute_commit_bid(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+            commitment,
+        ),
+        ExecuteMsg::RevealBid {
+            contract_address,
+            token_id,
+            amount,
+            salt,
+        } => contract().execute_reveal_bid(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+            amount,
+            salt,
+        ),
+        ExecuteMsg::SettleSealedBid {
+            contract_address,
+            token_id,
+        } => contract().execute_settle_sealed_bid(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        ),
+        ExecuteMsg::EditPullPayments { enabled } => {
+            contract().execute_edit_pull_payments(deps, _env, info, enabled)
+        }
+        ExecuteMsg::EditReferralFee { referral_fee_bps } => {
+            contract().execute_edit_referral_fee(deps, _env, info, referral_fee_bps)
+        }
+        ExecuteMsg::Withdraw { asset } => contract().execute_withdraw(deps, _env, info, asset),
+        ExecuteMsg::SetAutoAccept {
+            contract_address,
+            token_id,
+            min_price,
+        } => contract().execute_set_auto_accept(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+            min_price,
+        ),
+        ExecuteMsg::GrantPermission {
+            delegate,
+            contract_address,
+            actions,
+            spending_cap,
+            expires,
+        } => contract().execute_grant_permission(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&delegate)?,
+            api.addr_validate(&contract_address)?,
+            actions,
+            spending_cap,
+            expires,
+        ),
+        ExecuteMsg::RevokePermission {
+            delegate,
+            contract_address,
+        } => contract().execute_revoke_permission(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&delegate)?,
+            api.addr_validate(&contract_address)?,
+        ),
+        ExecuteMsg::RemoveAutoAccept {
+            contract_address,
+            token_id,
+        } => contract().execute_remove_auto_accept(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        ),
+        ExecuteMsg::SetCollectionRoyalty {
+            contract_address,
+            recipients,
+        } => contract().execute_set_collection_royalty(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            recipients,
+        ),
+        ExecuteMsg::RemoveCollectionRoyalty { contract_address } => contract()
+            .execute_remove_collection_royalty(
+                deps,
+                _env,
+                info,
+                api.addr_validate(&contract_address)?,
+            ),
+        ExecuteMsg::EditRewardsConfig {
+            token,
+            epoch_duration,
+            rewards_per_epoch,
+            distribution,
+            eligible_assets,
+        } => contract().execute_edit_rewards_config(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&token)?,
+            epoch_duration,
+            rewards_per_epoch,
+            distribution,
+            eligible_assets,
+        ),
+        ExecuteMsg::SetRewardMultiplier {
+            contract_address,
+            multiplier,
+        } => contract().execute_set_reward_multiplier(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            multiplier,
+        ),
+        ExecuteMsg::ClaimRewards { epoch } => {
+            contract().execute_claim_rewards(deps, _env, info, epoch)
+        }
+        ExecuteMsg::ListForRent {
+            contract_address,
+            token_id,
+            price_per_day,
+            max_days,
+        } => contract().execute_list_for_rent(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+            price_per_day,
+            max_days,
+        ),
+        ExecuteMsg::Rent {
+            contract_address,
+            token_id,
+            days,
+        } => contract().execute_rent(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+            days,
+        ),
+        ExecuteMsg::ReclaimRental {
+            contract_address,
+            token_id,
+        } => contract().execute_reclaim_rental(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        ),
+        ExecuteMsg::PayInstallment {
+            contract_address,
+            token_id,
+        } => contract().execute_pay_installment(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        ),
+        ExecuteMsg::ForfeitInstallments {
+            contract_address,
+            token_id,
+        } => contract().execute_forfeit_installments(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        ),
+        ExecuteMsg::CreateGroupBuy {
+            contract_address,
+            token_id,
+            vault,
+            deadline,
+        } => contract().execute_create_group_buy(
+            deps,
+            _env,
+            info,
+            api.addr_validate(&contract_address)?,
+            token_id,
+            api.addr_validate(&vault)?,
+            deadline,
+        ),
+        ExecuteMsg::ContributeGroupBuy { group_buy_id } => {
+            contract().execute_contribute_group_buy(deps, _env, info, group_buy_id)
+        }
+        ExecuteMsg::RefundGroupBuy { group_buy_id } => {
+            contract().execute_refund_group_buy(deps, _env, info, group_buy_id)
+        }
+        ExecuteMsg::RequestLoan {
+            nft,
+            principal,
+            repayment,
+            duration,
+        } => contract().execute_request_loan(deps, _env, info, nft, principal, repayment, duration),
+        ExecuteMsg::CancelLoanRequest { loan_id } => {
+            contract().execute_cancel_loan_request(deps, _env, info, loan_id)
+        }
+        ExecuteMsg::FundLoan { loan_id } => contract().execute_fund_loan(deps, _env, info, loan_id),
+        ExecuteMsg::RepayLoan { loan_id } => {
+            contract().execute_repay_loan(deps, _env, info, loan_id)
+        }
+        ExecuteMsg::ForecloseLoan { loan_id } => {
+            contract().execute_foreclose_loan(deps, _env, info, loan_id)
+        }
+        ExecuteMsg::AddHook { contract_address } => {
+            contract().execute_add_hook(deps, _env, info, api.addr_validate(&contract_address)?)
+        }
+        ExecuteMsg::RemoveHook { contract_address } => {
+            contract().execute_remove_hook(deps, _env, info, api.addr_validate(&contract_address)?)
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        // the error of a hook is only recorded, so that a hook cannot block the trades
+        HOOK_REPLY_ID => match msg.result {
+            SubMsgResult::Err(err) => Ok(Response::new()
+                .add_attribute("method", "hook_failed")
+                .add_attribute("error", err)),
+            SubMsgResult::Ok(_) => Ok(Response::new()),
+        },
+        id => Err(ContractError::UnknownReplyId { id }),
     }
 }
 
@@ -123,10 +477,45 @@ pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, C
     let conf = Config {
         owner: config.owner,
         vaura_address: Addr::unchecked("aura0"),
+        pull_payments: false,
+        referral_fee_bps: 0,
     };
     deps.storage.set(b"config", &to_vec(&conf)?);
 
-    contract().offers = orders();
+    // offers were stored by (offerer, nft) before, we move them to the new storage with an order id
+    let old_offers = orders_old()
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    orders_old().clear(deps.storage);
+    Map::<Vec<u8>, u32>::new("orders__user_address").clear(deps.storage);
+    Map::<Vec<u8>, u32>::new("orders__nft_identifier").clear(deps.storage);
+
+    for (_, old_offer) in old_offers {
+        let order_id = contract().generate_order_id(deps.storage)?;
+        let offer = OrderComponents {
+            order_type: old_offer.order_type,
+            order_id,
+            offerer: old_offer.offerer,
+            offer: old_offer.offer,
+            consideration: old_offer.consideration,
+            start_time: old_offer.start_time,
+            end_time: old_offer.end_time,
+            counter: 0,
+        };
+        contract().offers.save(deps.storage, order_id, &offer)?;
+    }
+
+    // the seller, reserved_for and price indexes of the listings are rebuilt
+    Map::<Vec<u8>, u32>::new("listings__seller").clear(deps.storage);
+    Map::<Vec<u8>, u32>::new("listings__reserved_for").clear(deps.storage);
+    Map::<Vec<u8>, u32>::new("listings__price").clear(deps.storage);
+    let listings = contract()
+        .listings
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (key, listing) in listings {
+        contract().listings.save(deps.storage, key, &listing)?;
+    }
 
     Ok(Response::default())
 }
@@ -143,6 +532,7 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             limit,
         } => to_binary(&contract().query_listings_by_contract_address(
             deps,
+            _env,
             ListingStatus::Ongoing {}.name(),
             api.addr_validate(&contract_address)?,
             start_after,
@@ -151,10 +541,85 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::Listing {
             contract_address,
             token_id,
-        } => to_binary(&contract().query_listing(
+            viewer,
+        } => to_binary(
+            &contract().query_listing(
+                deps,
+                _env,
+                api.addr_validate(&contract_address)?,
+                token_id,
+                viewer
+                    .map(|viewer| api.addr_validate(&viewer))
+                    .transpose()?,
+            )?,
+        ),
+        QueryMsg::ListingWithInfo {
+            contract_address,
+            token_id,
+            viewer,
+        } => to_binary(
+            &contract().query_listing_with_info(
+                deps,
+                _env,
+                api.addr_validate(&contract_address)?,
+                token_id,
+                viewer
+                    .map(|viewer| api.addr_validate(&viewer))
+                    .transpose()?,
+            )?,
+        ),
+        QueryMsg::ListingsWithInfoByContractAddress {
+            contract_address,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_listings_with_info_by_contract_address(
             deps,
+            _env,
             api.addr_validate(&contract_address)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::SimulateBuy {
+            contract_address,
             token_id,
+            buyer,
+            referrer,
+        } => to_binary(
+            &contract().query_simulate_buy(
+                deps,
+                _env,
+                api.addr_validate(&contract_address)?,
+                token_id,
+                buyer.map(|buyer| api.addr_validate(&buyer)).transpose()?,
+                referrer
+                    .map(|referrer| api.addr_validate(&referrer))
+                    .transpose()?,
+            )?,
+        ),
+        QueryMsg::SimulateAcceptOffer { order_id } => {
+            to_binary(&contract().query_simulate_accept_offer(deps, _env, order_id)?)
+        }
+        QueryMsg::PrivateListingsBySeller {
+            seller,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_private_listings_by_seller(
+            deps,
+            _env,
+            api.addr_validate(&seller)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ReservedListings {
+            buyer,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_reserved_listings(
+            deps,
+            _env,
+            api.addr_validate(&buyer)?,
+            start_after,
+            limit,
         )?),
         // return all supported auction contracts
         QueryMsg::AuctionContracts {} => to_binary(&contract().query_auction_contracts(deps)?),
@@ -168,37 +633,234 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             code_id,
             auction_config,
         )?),
-        QueryMsg::Offer {
+        QueryMsg::Offer { order_id } => to_binary(&contract().query_offer(deps, order_id)?),
+        QueryMsg::NftOffers {
             contract_address,
             token_id,
-            offerer,
-        } => to_binary(&contract().query_offer(
+            start_after,
+            limit,
+        } => to_binary(&contract().query_nft_offers(
             deps,
             api.addr_validate(&contract_address)?,
             token_id,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::UserOffers {
+            offerer,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_user_offers(
+            deps,
             api.addr_validate(&offerer)?,
+            start_after,
+            limit,
         )?),
-        QueryMsg::NftOffers {
+        QueryMsg::Order { order_id } => to_binary(&contract().query_order(deps, order_id)?),
+        QueryMsg::NftOrders {
             contract_address,
             token_id,
-            start_after_offerer,
+            start_after,
             limit,
-        } => to_binary(&contract().query_nft_offers(
+        } => to_binary(&contract().query_nft_orders(
             deps,
             api.addr_validate(&contract_address)?,
             token_id,
-            start_after_offerer,
+            start_after,
             limit,
         )?),
-        QueryMsg::UserOffers {
+        QueryMsg::UserOrders {
             offerer,
-            start_after_nft,
+            start_after,
             limit,
-        } => to_binary(&contract().query_user_offers(
+        } => to_binary(&contract().query_user_orders(
             deps,
             api.addr_validate(&offerer)?,
-            start_after_nft,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::SignedOrderHash { order } => {
+            to_binary(&contract().query_signed_order_hash(_env, order)?)
+        }
+        QueryMsg::SignedOrderStatus { order_hash } => {
+            to_binary(&contract().query_signed_order_status(deps, order_hash)?)
+        }
+        QueryMsg::Counter { user } => {
+            to_binary(&contract().query_counter(deps, api.addr_validate(&user)?)?)
+        }
+        QueryMsg::Bundle { bundle_id } => to_binary(&contract().query_bundle(deps, bundle_id)?),
+        QueryMsg::BundlesBySeller {
+            seller,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_bundles_by_seller(
+            deps,
+            api.addr_validate(&seller)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ReverseAuction { auction_id } => {
+            to_binary(&contract().query_reverse_auction(deps, auction_id)?)
+        }
+        QueryMsg::ReverseAuctions { start_after, limit } => {
+            to_binary(&contract().query_reverse_auctions(deps, start_after, limit)?)
+        }
+        QueryMsg::SealedBids {
+            contract_address,
+            token_id,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_sealed_bids(
+            deps,
+            api.addr_validate(&contract_address)?,
+            token_id,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::AutoAccept {
+            owner,
+            contract_address,
+            token_id,
+        } => to_binary(&contract().auto_accept_price(
+            deps.storage,
+            &api.addr_validate(&owner)?,
+            &api.addr_validate(&contract_address)?,
+            &token_id.unwrap_or_default(),
+        )?),
+        QueryMsg::RewardsConfig {} => to_binary(&contract().rewards_config.may_load(deps.storage)?),
+        QueryMsg::RewardMultiplier { contract_address } => to_binary(
+            &contract()
+                .reward_multipliers
+                .may_load(deps.storage, &api.addr_validate(&contract_address)?)?
+                .unwrap_or(DEFAULT_REWARD_MULTIPLIER),
+        ),
+        QueryMsg::UserRewards {
+            user,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_user_rewards(
+            deps,
+            api.addr_validate(&user)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Hooks {} => {
+            to_binary(&contract().hooks.may_load(deps.storage)?.unwrap_or_default())
+        }
+        QueryMsg::Rental {
+            contract_address,
+            token_id,
+        } => to_binary(&contract().query_rental(
+            deps,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        )?),
+        QueryMsg::RentalUser {
+            contract_address,
+            token_id,
+        } => to_binary(&contract().query_rental_user(
+            deps,
+            _env,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        )?),
+        QueryMsg::InstallmentPlan {
+            contract_address,
+            token_id,
+        } => to_binary(&contract().query_installment_plan(
+            deps,
+            api.addr_validate(&contract_address)?,
+            token_id,
+        )?),
+        QueryMsg::GroupBuy { group_buy_id } => {
+            to_binary(&contract().group_buys.load(deps.storage, group_buy_id)?)
+        }
+        QueryMsg::GroupBuyContributions {
+            group_buy_id,
+            start_after,
+            limit,
+        } => to_binary(
+            &contract().query_group_buy_contributions(
+                deps,
+                group_buy_id,
+                start_after
+                    .map(|contributor| api.addr_validate(&contributor))
+                    .transpose()?,
+                limit,
+            )?,
+        ),
+        QueryMsg::Loan { loan_id } => to_binary(&contract().loans.load(deps.storage, loan_id)?),
+        QueryMsg::LoansByBorrower {
+            borrower,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_loans_by_borrower(
+            deps,
+            api.addr_validate(&borrower)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::LoansByLender {
+            lender,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_loans_by_lender(
+            deps,
+            api.addr_validate(&lender)?,
+            start_after,
             limit,
         )?),
+        QueryMsg::LoansByCollection {
+            contract_address,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_loans_by_collection(
+            deps,
+            api.addr_validate(&contract_address)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::CollectionRoyalty { contract_address } => to_binary(
+            &contract()
+                .royalty_registry
+                .may_load(deps.storage, &api.addr_validate(&contract_address)?)?,
+        ),
+        QueryMsg::Delegation {
+            owner,
+            delegate,
+            contract_address,
+        } => to_binary(&contract().delegations.may_load(
+            deps.storage,
+            (
+                api.addr_validate(&owner)?,
+                api.addr_validate(&delegate)?,
+                api.addr_validate(&contract_address)?,
+            ),
+        )?),
+        QueryMsg::DelegationsByOwner {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&contract().query_delegations_by_owner(
+            deps,
+            api.addr_validate(&owner)?,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ReferralEarnings { referrer } => {
+            to_binary(&contract().query_referral_earnings(deps, api.addr_validate(&referrer)?)?)
+        }
+        QueryMsg::PendingBalance { address } => {
+            to_binary(&contract().query_pending_balance(deps, api.addr_validate(&address)?)?)
+        }
+        QueryMsg::SealedBidCommitment {
+            bidder,
+            amount,
+            salt,
+        } => to_binary(&sealed_bid_commitment(
+            &api.addr_validate(&bidder)?,
+            amount,
+            &salt,
+        )),
     }
 }
@@ -1,51 +1,251 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Addr, StdError, Timestamp, Uint128};
+use cw721::Expiration;
 use thiserror::Error;
 
+// each error message starts with a stable code, so that clients can match on it
+// E1xx are general errors, E2xx are errors of listings and auctions,
+// E3xx are errors of offers and orders, E4xx are errors of delegations, E5xx are errors of rewards,
+// E6xx are errors of loans
+// the codes of existing errors must not change, new errors take new codes
 #[derive(Error, Debug)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
-    #[error("Unauthorized")]
+    #[error("E100: Unauthorized")]
     Unauthorized {},
 
-    #[error("Already Exists")]
+    #[error("E101: Already Exists")]
     AlreadyExists {},
 
-    #[error("Listing Not Active")]
-    ListingNotActive {},
-
-    #[error("Insufficient Funds")]
+    #[error("E102: Insufficient Funds")]
     InsufficientFunds {},
 
-    #[error("Custom Error val: {val:?}")]
-    CustomError { val: String },
-    // Add any other custom errors you like here.
-    // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
-    #[error("Offer item must be a nft")]
-    OfferEmpty {},
+    #[error("E103: Amount must be greater than zero")]
+    InvalidAmount {},
 
-    #[error("Cannot offer your own NFT")]
-    CannotOfferOwnNFT {},
+    #[error("E104: Require never expired approval")]
+    ApprovalMustNeverExpire {},
 
-    #[error("Nft not found")]
+    #[error("E105: Nft not found")]
     NftNotFound {},
 
-    #[error("You can offer by one token only")]
-    OfferByOneTokenOnly {},
+    #[error("E106: Collection item is not supported")]
+    CollectionItemNotSupported {},
+
+    #[error("E107: Royalty {royalty} exceeds the price {price}")]
+    RoyaltyExceedsPrice { royalty: Uint128, price: Uint128 },
+
+    #[error("E108: No pending balance")]
+    NoPendingBalance {},
+
+    #[error("E109: VAura address not set")]
+    VauraAddressNotSet {},
+
+    #[error("E110: Invalid end time")]
+    InvalidEndTime {},
+
+    #[error("E111: Royalty rates must be positive and sum to at most 10000 bps")]
+    InvalidRoyaltyRates {},
+
+    #[error("E112: Hook not found")]
+    HookNotFound {},
+
+    #[error("E113: Unknown reply id {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("E114: Referral fee must be at most 10000 bps")]
+    InvalidReferralFee {},
+
+    #[error("E115: Referrer cannot be the buyer or the seller")]
+    InvalidReferrer {},
+
+    #[error("E116: Referral fee {fee} exceeds the proceeds {proceeds}")]
+    ReferralFeeExceedsProceeds { fee: Uint128, proceeds: Uint128 },
+
+    #[error("E200: Listing Not Active")]
+    ListingNotActive {},
+
+    #[error("E201: Invalid auction config")]
+    InvalidAuctionConfig {},
+
+    #[error("E202: Owner cannot buy")]
+    OwnerCannotBuy {},
+
+    #[error("E203: Auction not started: {start_time}")]
+    AuctionNotStarted { start_time: Expiration },
+
+    #[error("E204: Auction ended: {end_time} {block_time}")]
+    AuctionEnded {
+        end_time: Expiration,
+        block_time: Timestamp,
+    },
+
+    #[error("E205: Auction not ended: {end_time}")]
+    AuctionNotEnded { end_time: Expiration },
+
+    #[error("E206: Auction has bids")]
+    AuctionHasBids {},
+
+    #[error("E207: Cannot reserve listing for the seller")]
+    CannotReserveForSeller {},
+
+    #[error("E208: Max items must be between 1 and 30, got {max_items}")]
+    InvalidSweepSize { max_items: u32 },
+
+    #[error("E209: No listing to sweep")]
+    NothingToSweep {},
+
+    #[error("E210: A bundle must contain 2 to 30 nfts, got {size}")]
+    InvalidBundleSize { size: usize },
+
+    #[error("E211: Duplicate nft {token_id} of {contract_address} in bundle")]
+    DuplicateNft {
+        contract_address: Addr,
+        token_id: String,
+    },
+
+    #[error("E212: Nft {token_id} of {contract_address} is no longer owned by the seller")]
+    NftNotOwnedBySeller {
+        contract_address: Addr,
+        token_id: String,
+    },
+
+    #[error("E213: Ask {ask} exceeds the max price {max_price}")]
+    AskAboveMaxPrice { ask: Uint128, max_price: Uint128 },
+
+    #[error("E214: Ask {ask} must be lower than the current ask {best_ask}")]
+    AskNotLower { ask: Uint128, best_ask: Uint128 },
+
+    #[error("E215: Cannot cancel an auction with an ask")]
+    AuctionHasAsk {},
+
+    #[error("E216: Bidding phase ended: {bidding_end}")]
+    BiddingEnded { bidding_end: Expiration },
+
+    #[error("E217: Owner cannot bid")]
+    OwnerCannotBid {},
+
+    #[error("E218: Not in reveal phase")]
+    NotInRevealPhase {},
+
+    #[error("E219: Bid does not match the commitment")]
+    CommitmentMismatch {},
+
+    #[error("E220: Reveal phase not ended: {reveal_end}")]
+    RevealNotEnded { reveal_end: Expiration },
 
-    #[error("Offer token type invalid")]
+    #[error("E221: Rental duration must be between 1 and {max_days} days, got {days}")]
+    InvalidRentalDuration { days: u64, max_days: u64 },
+
+    #[error("E222: Nft is rented until {expires}")]
+    NftRented { expires: Timestamp },
+
+    #[error("E223: Group buy is not open")]
+    GroupBuyNotOpen {},
+
+    #[error("E224: Group buy ended: {deadline}")]
+    GroupBuyEnded { deadline: Expiration },
+
+    #[error("E225: Group buy can be refunded only after the deadline or once the listing is gone")]
+    GroupBuyNotRefundable {},
+
+    #[error("E226: Installment was due at {due}")]
+    InstallmentOverdue { due: Timestamp },
+
+    #[error("E227: Installment is not due until {due}")]
+    InstallmentNotOverdue { due: Timestamp },
+
+    #[error("E228: Sealed bid auction already has {max_bids} bids")]
+    TooManySealedBids { max_bids: u32 },
+
+    #[error("E300: Offer and consideration must not be empty")]
+    OfferEmpty {},
+
+    #[error("E301: Cannot offer your own NFT")]
+    CannotOfferOwnNFT {},
+
+    #[error("E302: Offer token type invalid")]
     OfferTokenTypeInvalid {},
 
-    #[error("Offer token allowance insufficient")]
+    #[error("E303: Offer token allowance insufficient")]
     InsufficientAllowance {},
 
-    #[error("Offer token balance insufficient")]
+    #[error("E304: Offer token balance insufficient")]
     InsufficientBalance {},
 
-    #[error("Invalid end time")]
-    InvalidEndTime {},
+    #[error("E305: Order is expired")]
+    OrderExpired {},
 
-    #[error("VAura address not set")]
-    VauraAddressNotSet {},
+    #[error("E306: Order not started")]
+    OrderNotStarted {},
+
+    #[error("E307: Invalid signature")]
+    InvalidSignature {},
+
+    #[error("E308: Order is already filled or cancelled")]
+    OrderAlreadyUsed {},
+
+    #[error("E309: Order is invalidated by the counter of the offerer")]
+    OrderInvalidated {},
+
+    #[error("E310: Collection offer is not supported")]
+    CollectionOfferNotSupported {},
+
+    #[error("E311: Consideration is not NFT")]
+    ConsiderationNotNft {},
+
+    #[error("E312: Number of orders is greater than {max}")]
+    TooManyOrders { max: usize },
+
+    #[error("E313: Offer {order_id} does not exist")]
+    OfferNotFound { order_id: u64 },
+
+    #[error("E314: Order must contain a nft")]
+    OrderWithoutNft {},
+
+    #[error("E315: Signed order cannot offer native tokens")]
+    NativeOfferInSignedOrder {},
+
+    #[error("E316: Offerer cannot fulfill own order")]
+    CannotFulfillOwnOrder {},
+
+    #[error("E317: Item type does not match the asset")]
+    ItemTypeMismatch {},
+
+    #[error("E318: Asset is not a payment asset")]
+    NotPaymentAsset {},
+
+    #[error("E400: Invalid delegation")]
+    InvalidDelegation {},
+
+    #[error("E401: Spending cap {spending_cap} exceeded")]
+    SpendingCapExceeded { spending_cap: Uint128 },
+
+    #[error("E500: Rewards are not configured")]
+    RewardsNotConfigured {},
+
+    #[error("E501: Invalid rewards config")]
+    InvalidRewardsConfig {},
+
+    #[error("E502: Epoch {epoch} not ended")]
+    EpochNotEnded { epoch: u64 },
+
+    #[error("E503: No rewards to claim")]
+    NoRewardsToClaim {},
+
+    #[error("E600: Invalid loan terms")]
+    InvalidLoanTerms {},
+
+    #[error("E601: Loan is already funded")]
+    LoanAlreadyFunded {},
+
+    #[error("E602: Loan is not funded")]
+    LoanNotFunded {},
+
+    #[error("E603: Loan is not due until {due}")]
+    LoanNotDue { due: Timestamp },
+
+    #[error("E604: Loan was due at {due}")]
+    LoanOverdue { due: Timestamp },
 }
@@ -1,22 +1,48 @@
 use crate::order_state::{
-    consideration_item, offer_item, order_key, Asset, ItemType, OrderComponents, OrderType,
-    PaymentAsset, CW20, NFT,
+    consideration_item, offer_item, order_hash, Asset, ConsiderationItem, ItemType, OfferItem,
+    OrderComponents, OrderKey, OrderType, PaymentAsset, PaymentAssetInfo, SignedOrderStatus, CW20,
+    NATIVE, NFT,
 };
 use crate::{
+    msg::{HookAction, HookExecuteMsg, ListingHookMsg, OfferHookMsg, SaleHookMsg, TransferKind},
     state::{
-        listing_key, AuctionConfig, AuctionContract, Listing, ListingStatus, MarketplaceContract,
+        contract, listing_key, sealed_bid_commitment, Ask, AuctionConfig, AuctionContract, Bundle,
+        BundleKey, Config, DelegatedAction, Delegation, DelegationKey, GroupBuy, GroupBuyKey,
+        GroupBuyStatus, InstallmentPlan, Listing, ListingStatus, Loan, LoanStatus,
+        MarketplaceContract, ReverseAuction, ReverseAuctionKey, RewardAsset, RewardDistribution,
+        RewardsConfig, RoyaltyRecipient, SealedBid, DEFAULT_REWARD_MULTIPLIER,
     },
     ContractError,
 };
 use cosmwasm_std::{
-    to_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, Env, MessageInfo, QueryRequest, Response,
-    StdResult, Uint128, WasmMsg, WasmQuery,
+    to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Order,
+    QueryRequest, Response, StdError, StdResult, Storage, SubMsg, Timestamp, Uint128, WasmMsg,
+    WasmQuery,
 };
 use cw20::{AllowanceResponse, Cw20ExecuteMsg, Cw20QueryMsg};
 use cw2981_royalties::{
     msg::RoyaltiesInfoResponse, ExecuteMsg as Cw2981ExecuteMsg, QueryMsg as Cw2981QueryMsg,
 };
 use cw721::{Cw721QueryMsg, Expiration as Cw721Expiration};
+use cw721_base::msg::MinterResponse;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+// the id of the replies of the hook submessages
+pub const HOOK_REPLY_ID: u64 = 1;
+
+// the gas a hook can use, so that a hook cannot consume the gas of the trade
+pub const HOOK_GAS_LIMIT: u64 = 300_000;
+
+// the rentals are paid by the day
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+// the longest rental, so that the end of a rental cannot overflow
+pub const MAX_RENTAL_DAYS: u64 = 365;
+
+// the most bids of a sealed bid auction, since its settlement goes through all of them
+pub const MAX_SEALED_BIDS: u32 = 50;
 
 impl MarketplaceContract<'static> {
     pub fn validate_auction_config(&self, auction_config: &AuctionConfig) -> bool {
@@ -25,6 +51,7 @@ impl MarketplaceContract<'static> {
                 price,
                 start_time,
                 end_time,
+                price_schedule,
             } => {
                 if price.amount.is_zero() {
                     // since price is Uint128, it cannot be negative, we only
@@ -38,8 +65,48 @@ impl MarketplaceContract<'static> {
                 {
                     return false;
                 }
+                // the steps must have increasing times within the listing period
+                let mut last_time = None;
+                for step in price_schedule {
+                    if step.price.is_zero()
+                        || last_time.is_some_and(|time| step.time <= time)
+                        || !step_within_period(step.time, start_time, end_time)
+                    {
+                        return false;
+                    }
+                    last_time = Some(step.time);
+                }
                 true
             }
+            AuctionConfig::SealedBid {
+                min_price,
+                bidding_end,
+                reveal_end,
+            } => {
+                // the reveal phase must end, otherwise the auction cannot be settled
+                !min_price.amount.is_zero()
+                    && *reveal_end != Cw721Expiration::Never {}
+                    && bidding_end < reveal_end
+            }
+            AuctionConfig::Installments {
+                price,
+                deposit,
+                installments,
+                interval,
+                forfeit_bps,
+            } => {
+                // each installment must be at least 1
+                !deposit.is_zero()
+                    && *deposit < price.amount
+                    && *installments > 0
+                    && price.amount - *deposit >= Uint128::from(*installments)
+                    && *interval > 0
+                    && *forfeit_bps <= 10_000
+            }
+            AuctionConfig::Rental {
+                price_per_day,
+                max_days,
+            } => !price_per_day.amount.is_zero() && *max_days > 0 && *max_days <= MAX_RENTAL_DAYS,
             AuctionConfig::Other {
                 auction: _,
                 config: _,
@@ -57,6 +124,7 @@ impl MarketplaceContract<'static> {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn execute_list_nft(
         self,
         deps: DepsMut,
@@ -65,58 +133,36 @@ impl MarketplaceContract<'static> {
         contract_address: Addr,
         token_id: String,
         auction_config: AuctionConfig,
+        reserved_for: Option<Addr>,
     ) -> Result<Response, ContractError> {
-        // check if user is the owner of the token
-        let query_owner_msg = Cw721QueryMsg::OwnerOf {
-            token_id: token_id.clone(),
-            include_expired: Some(false),
-        };
-        let owner_response: StdResult<cw721::OwnerOfResponse> =
-            deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-                contract_addr: contract_address.to_string(),
-                msg: to_binary(&query_owner_msg)?,
-            }));
-        match owner_response {
-            Ok(owner) => {
-                if owner.owner != info.sender {
-                    return Err(ContractError::Unauthorized {});
-                }
-            }
-            Err(_) => {
-                return Err(ContractError::Unauthorized {});
+        // a delegate lists the nft on behalf of its owner
+        let seller = match query_nft_owner(&deps, &contract_address, &token_id) {
+            Ok(owner) if owner != info.sender => {
+                self.use_delegation(
+                    deps.storage,
+                    &env,
+                    (owner.clone(), info.sender.clone(), contract_address.clone()),
+                    DelegatedAction::List,
+                    Uint128::zero(),
+                )?;
+                owner
             }
-        }
-
-        // check that user approves this contract to manage this token
-        // for now, we require never expired approval
-        let query_approval_msg = Cw721QueryMsg::Approval {
-            token_id: token_id.clone(),
-            spender: env.contract.address.to_string(),
-            include_expired: Some(true),
+            _ => info.sender.clone(),
         };
-        let approval_response: StdResult<cw721::ApprovalResponse> =
-            deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-                contract_addr: contract_address.to_string(),
-                msg: to_binary(&query_approval_msg)?,
-            }));
-
-        // check if approval is never expired
-        match approval_response {
-            Ok(approval) => match approval.approval.expires {
-                Cw721Expiration::Never {} => {}
-                _ => return Err(ContractError::Unauthorized {}),
-            },
-            Err(_) => {
-                return Err(ContractError::CustomError {
-                    val: "Require never expired approval".to_string(),
-                });
-            }
+
+        // check if seller is the owner of the token and approves this contract to manage it
+        check_listing_approval(deps.as_ref(), &env, &seller, &contract_address, &token_id)?;
+
+        // the seller cannot reserve the listing for themselves
+        if reserved_for.as_ref() == Some(&seller) {
+            return Err(ContractError::CannotReserveForSeller {});
         }
 
-        if !self.validate_auction_config(&auction_config) {
-            return Err(ContractError::CustomError {
-                val: "Invalid auction config".to_string(),
-            });
+        // the nfts for rent must be escrowed with ListForRent
+        if !self.validate_auction_config(&auction_config)
+            || matches!(auction_config, AuctionConfig::Rental { .. })
+        {
+            return Err(ContractError::InvalidAuctionConfig {});
         }
 
         // add a nft to listings
@@ -124,12 +170,19 @@ impl MarketplaceContract<'static> {
             contract_address: contract_address.clone(),
             token_id: token_id.clone(),
             auction_config,
-            seller: info.sender,
+            counter: self.user_counter(deps.storage, &seller)?,
+            seller,
             buyer: None,
             status: ListingStatus::Ongoing {},
+            reserved_for,
         };
         let listing_key = listing_key(&contract_address, &token_id);
 
+        // a sealed bid auction cannot be updated once it has bids
+        if self.has_sealed_bids(deps.storage, &listing_key) {
+            return Err(ContractError::AuctionHasBids {});
+        }
+
         // we will override the listing if it already exists, so that we can update the auction config
         let new_listing = self.listings.update(
             deps.storage,
@@ -137,18 +190,33 @@ impl MarketplaceContract<'static> {
             |_old| -> Result<Listing, ContractError> { Ok(listing) },
         )?;
 
+        let hook_msgs = self.hook_msgs(
+            deps.storage,
+            HookExecuteMsg::ListingHook(ListingHookMsg {
+                action: HookAction::Created,
+                contract_address: new_listing.contract_address.clone(),
+                token_id: new_listing.token_id.clone(),
+                seller: new_listing.seller.clone(),
+            }),
+        )?;
+
         // println!("Listing: {:?}", _listing);
         let auction_config_str = serde_json::to_string(&new_listing.auction_config);
         match auction_config_str {
-            Ok(auction_config_str) => Ok(Response::new()
-                .add_attribute("method", "list_nft")
-                .add_attribute("contract_address", new_listing.contract_address)
-                .add_attribute("token_id", new_listing.token_id)
-                .add_attribute("auction_config", auction_config_str)
-                .add_attribute("seller", new_listing.seller.to_string())),
-            Err(_) => Err(ContractError::CustomError {
-                val: ("Auction Config Error".to_string()),
-            }),
+            Ok(auction_config_str) => {
+                let mut res = Response::new()
+                    .add_submessages(hook_msgs)
+                    .add_attribute("method", "list_nft")
+                    .add_attribute("contract_address", new_listing.contract_address)
+                    .add_attribute("token_id", new_listing.token_id)
+                    .add_attribute("auction_config", auction_config_str)
+                    .add_attribute("seller", new_listing.seller.to_string());
+                if let Some(reserved_for) = new_listing.reserved_for {
+                    res = res.add_attribute("reserved_for", reserved_for);
+                }
+                Ok(res)
+            }
+            Err(_) => Err(ContractError::InvalidAuctionConfig {}),
         }
     }
 
@@ -159,23 +227,36 @@ impl MarketplaceContract<'static> {
         info: MessageInfo,
         contract_address: Addr,
         token_id: String,
+        referrer: Option<Addr>,
     ) -> Result<Response, ContractError> {
-        // get the listing
+        // get the listing at its current price
         let listing_key = listing_key(&contract_address, &token_id);
-        let mut listing = self.listings.load(deps.storage, listing_key.clone())?;
+        let mut listing = self
+            .listings
+            .load(deps.storage, listing_key.clone())?
+            .with_active_price(&env.block);
 
         // check if listing is active
         if !listing.is_active() {
             return Err(ContractError::ListingNotActive {});
         }
 
-        // check if buyer is the same as seller
-        if info.sender == listing.seller {
-            return Err(ContractError::CustomError {
-                val: ("Owner cannot buy".to_string()),
-            });
+        // check if the seller has invalidated the listing
+        if !self.is_listing_valid(deps.storage, &listing)? {
+            return Err(ContractError::OrderInvalidated {});
         }
 
+        check_buyer(&listing, &info.sender, referrer.as_ref())?;
+
+        // the seller must still own the nft, it may have been sold or escrowed since it was listed
+        check_listing_approval(
+            deps.as_ref(),
+            &env,
+            &listing.seller,
+            &listing.contract_address,
+            &listing.token_id,
+        )?;
+
         listing.buyer = Some(info.sender.clone());
 
         // remove the listing
@@ -183,126 +264,95 @@ impl MarketplaceContract<'static> {
 
         match &listing.auction_config {
             AuctionConfig::FixedPrice { .. } => {
-                self.process_buy_fixed_price(deps, env, info, &listing)
+                self.process_buy_fixed_price(deps, env, info, &listing, referrer)
+            }
+            AuctionConfig::Installments { .. } => {
+                self.process_buy_installments(deps, env, info, listing, referrer)
             }
             _ => {
                 // TODO where should we store auction_contract? in auction_config or as in a list
                 // get auction contract and validate bid
-                Err(ContractError::CustomError {
-                    val: ("Invalid Auction Config".to_string()),
-                })
+                Err(ContractError::InvalidAuctionConfig {})
             }
         }
     }
 
     fn process_buy_fixed_price(
-        self,
+        &self,
         deps: DepsMut,
         env: Env,
         info: MessageInfo,
         listing: &Listing,
+        referrer: Option<Addr>,
     ) -> Result<Response, ContractError> {
         match &listing.auction_config {
             AuctionConfig::FixedPrice {
                 price,
                 start_time,
                 end_time,
+                ..
             } => {
-                // check if current block is after start_time
-                if start_time.is_some() && !start_time.unwrap().is_expired(&env.block) {
-                    return Err(ContractError::CustomError {
-                        val: ("Auction not started".to_string()),
-                    });
-                }
+                check_fixed_price_period(start_time, end_time, &env)?;
 
-                if end_time.is_some() && end_time.unwrap().is_expired(&env.block) {
-                    return Err(ContractError::CustomError {
-                        val: format!("Auction ended: {} {}", end_time.unwrap(), env.block.time),
-                    });
-                }
                 // check if enough funds
-                if info.funds.is_empty() || info.funds[0] != *price {
+                if info.funds.len() != 1 || info.funds[0] != *price {
                     return Err(ContractError::InsufficientFunds {});
                 }
 
-                // get cw2981 royalties info
-                let royalty_query_msg = Cw2981QueryMsg::Extension {
-                    msg: cw2981_royalties::msg::Cw2981QueryMsg::RoyaltyInfo {
-                        token_id: listing.token_id.clone(),
-                        sale_price: price.amount,
-                    },
-                };
-                let royalty_info_rsp: Result<RoyaltiesInfoResponse, cosmwasm_std::StdError> =
-                    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-                        contract_addr: listing.contract_address.to_string(),
-                        msg: to_binary(&royalty_query_msg)?,
-                    }));
-
-                let (creator, royalty_amount): (Option<Addr>, Option<Uint128>) =
-                    match royalty_info_rsp {
-                        Ok(RoyaltiesInfoResponse {
-                            address,
-                            royalty_amount,
-                        }) => {
-                            if address.is_empty() || royalty_amount == Uint128::zero() {
-                                (None, None)
-                            } else {
-                                (
-                                    Some(deps.api.addr_validate(&address)?),
-                                    Some(royalty_amount),
-                                )
-                            }
-                        }
-                        Err(_) => (None, None),
-                    };
-
                 // message to transfer nft to buyer
                 let transfer_nft_msg = WasmMsg::Execute {
                     contract_addr: listing.contract_address.to_string(),
                     msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
-                        recipient: listing.buyer.clone().unwrap().into_string(),
+                        recipient: info.sender.to_string(),
                         token_id: listing.token_id.clone(),
                     })?,
                     funds: vec![],
                 };
                 let mut res = Response::new().add_message(transfer_nft_msg);
 
+                // transfer the royalty to the creator, the referral fee and the remaining funds
                 let config = self.config.load(deps.storage)?;
-
-                // there is no royalty, creator is the seller, or royalty amount is 0
-                if creator.is_none()
-                    || *creator.as_ref().unwrap() == listing.seller
-                    || royalty_amount.is_none()
-                    || royalty_amount.unwrap().is_zero()
+                for (kind, recipient, coin) in
+                    sale_payouts(deps.as_ref(), &config, listing, price, referrer.as_ref())?
                 {
-                    // transfer all funds to seller
-                    let transfer_token_msg = BankMsg::Send {
-                        to_address: listing.seller.to_string(),
-                        amount: info.funds,
-                    };
-                    res = res.add_message(transfer_token_msg);
-                } else if let (Some(creator), Some(royalty_amount)) = (creator, royalty_amount) {
-                    // transfer royalty to minter
-                    let transfer_token_minter_msg = BankMsg::Send {
-                        to_address: creator.to_string(),
-                        amount: vec![Coin {
-                            denom: price.denom.clone(),
-                            amount: royalty_amount,
-                        }],
-                    };
+                    if kind == TransferKind::ReferralFee {
+                        self.add_referral_earnings(
+                            deps.storage,
+                            &recipient,
+                            &PaymentAsset::Native {
+                                denom: coin.denom.clone(),
+                                amount: coin.amount.u128(),
+                            },
+                        )?;
+                        res = res.add_attribute("referrer", recipient.to_string());
+                    }
+                    res = res.add_messages(self.send_native(deps.storage, &recipient, coin)?);
+                }
 
-                    // transfer remaining funds to seller
-                    let transfer_token_seller_msg = BankMsg::Send {
-                        to_address: config.owner.to_string(),
-                        amount: vec![Coin {
+                self.accrue_rewards(
+                    deps.storage,
+                    &env,
+                    &listing.contract_address,
+                    [&listing.seller, &info.sender],
+                    &[PaymentAsset::Native {
+                        denom: price.denom.clone(),
+                        amount: price.amount.u128(),
+                    }],
+                )?;
+
+                res = res.add_submessages(self.hook_msgs(
+                    deps.storage,
+                    HookExecuteMsg::SaleHook(SaleHookMsg {
+                        contract_address: listing.contract_address.clone(),
+                        token_id: listing.token_id.clone(),
+                        seller: listing.seller.clone(),
+                        buyer: info.sender.clone(),
+                        price: PaymentAsset::Native {
                             denom: price.denom.clone(),
-                            amount: price.amount - royalty_amount,
-                        }],
-                    };
-                    res = res
-                        .add_message(transfer_token_minter_msg)
-                        .add_message(transfer_token_seller_msg);
-                }
+                            amount: price.amount.u128(),
+                        },
+                    }),
+                )?);
 
                 res = res
                     .add_attribute("method", "buy")
@@ -312,10 +362,356 @@ impl MarketplaceContract<'static> {
 
                 Ok(res)
             }
-            _ => Err(ContractError::CustomError {
-                val: ("Invalid Auction Config".to_string()),
-            }),
+            _ => Err(ContractError::InvalidAuctionConfig {}),
+        }
+    }
+
+    // the buyer pays the deposit and the nft is escrowed until the final installment
+    fn process_buy_installments(
+        &self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        listing: Listing,
+        referrer: Option<Addr>,
+    ) -> Result<Response, ContractError> {
+        let (price, deposit, interval) = match &listing.auction_config {
+            AuctionConfig::Installments {
+                price,
+                deposit,
+                interval,
+                ..
+            } => (price, *deposit, *interval),
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
+
+        // check if enough funds
+        if info.funds.len() != 1
+            || info.funds[0]
+                != (Coin {
+                    denom: price.denom.clone(),
+                    amount: deposit,
+                })
+        {
+            return Err(ContractError::InsufficientFunds {});
+        }
+
+        let transfer_nft_msg = WasmMsg::Execute {
+            contract_addr: listing.contract_address.to_string(),
+            msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                recipient: env.contract.address.to_string(),
+                token_id: listing.token_id.clone(),
+            })?,
+            funds: vec![],
+        };
+
+        let plan = InstallmentPlan {
+            buyer: info.sender.clone(),
+            paid: deposit,
+            installments_paid: 0,
+            next_due: env.block.time.plus_seconds(interval),
+            referrer,
+            listing,
+        };
+        self.installment_plans.save(
+            deps.storage,
+            listing_key(&plan.listing.contract_address, &plan.listing.token_id),
+            &plan,
+        )?;
+
+        Ok(Response::new()
+            .add_message(transfer_nft_msg)
+            .add_attribute("method", "buy_installments")
+            .add_attribute(
+                "contract_address",
+                plan.listing.contract_address.to_string(),
+            )
+            .add_attribute("token_id", plan.listing.token_id.to_string())
+            .add_attribute("buyer", info.sender)
+            .add_attribute("next_due", plan.next_due.to_string()))
+    }
+
+    // the final installment completes the purchase, the total is split like a fixed price sale
+    pub fn execute_pay_installment(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+    ) -> Result<Response, ContractError> {
+        let plan_key = listing_key(&contract_address, &token_id);
+        let mut plan = self
+            .installment_plans
+            .load(deps.storage, plan_key.clone())?;
+        if plan.buyer != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        if env.block.time >= plan.next_due {
+            return Err(ContractError::InstallmentOverdue { due: plan.next_due });
+        }
+
+        let (price, deposit, installments, interval) = match &plan.listing.auction_config {
+            AuctionConfig::Installments {
+                price,
+                deposit,
+                installments,
+                interval,
+                ..
+            } => (price.clone(), *deposit, *installments, *interval),
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
+
+        // the final installment pays the remainder of the division
+        plan.installments_paid += 1;
+        let amount = if plan.installments_paid == installments {
+            price.amount - plan.paid
+        } else {
+            (price.amount - deposit) / Uint128::from(installments)
+        };
+        if info.funds.len() != 1
+            || info.funds[0]
+                != (Coin {
+                    denom: price.denom.clone(),
+                    amount,
+                })
+        {
+            return Err(ContractError::InsufficientFunds {});
+        }
+        plan.paid += amount;
+
+        let res = Response::new()
+            .add_attribute("method", "pay_installment")
+            .add_attribute("contract_address", contract_address.to_string())
+            .add_attribute("token_id", token_id)
+            .add_attribute("installments_paid", plan.installments_paid.to_string());
+
+        if plan.installments_paid < installments {
+            plan.next_due = plan.next_due.plus_seconds(interval);
+            self.installment_plans.save(deps.storage, plan_key, &plan)?;
+            return Ok(res.add_attribute("next_due", plan.next_due.to_string()));
+        }
+
+        // the nft is sent from the escrow and the total is paid out
+        self.installment_plans.remove(deps.storage, plan_key);
+        let listing = Listing {
+            auction_config: AuctionConfig::FixedPrice {
+                price: price.clone(),
+                start_time: None,
+                end_time: None,
+                price_schedule: vec![],
+            },
+            buyer: Some(plan.buyer.clone()),
+            ..plan.listing
+        };
+        let buy_res = self.process_buy_fixed_price(
+            deps,
+            env,
+            MessageInfo {
+                sender: plan.buyer,
+                funds: vec![price],
+            },
+            &listing,
+            plan.referrer,
+        )?;
+
+        // the attributes of the purchase which are not set by the installment, such as the buyer
+        let attributes: Vec<_> = buy_res
+            .attributes
+            .into_iter()
+            .filter(|attr| res.attributes.iter().all(|set| set.key != attr.key))
+            .collect();
+        Ok(res
+            .add_submessages(buy_res.messages)
+            .add_attributes(attributes)
+            .add_events(buy_res.events))
+    }
+
+    // anyone can end a purchase with installments once an installment is missed
+    pub fn execute_forfeit_installments(
+        self,
+        deps: DepsMut,
+        env: Env,
+        _info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+    ) -> Result<Response, ContractError> {
+        let plan_key = listing_key(&contract_address, &token_id);
+        let plan = self
+            .installment_plans
+            .load(deps.storage, plan_key.clone())?;
+        if env.block.time < plan.next_due {
+            return Err(ContractError::InstallmentNotOverdue { due: plan.next_due });
+        }
+        let (denom, forfeit_bps) = match &plan.listing.auction_config {
+            AuctionConfig::Installments {
+                price, forfeit_bps, ..
+            } => (price.denom.clone(), *forfeit_bps),
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
+
+        self.installment_plans.remove(deps.storage, plan_key);
+
+        let forfeit = plan.paid.multiply_ratio(forfeit_bps, 10_000u64);
+        let refund = plan.paid - forfeit;
+        let mut res = Response::new().add_message(WasmMsg::Execute {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                recipient: plan.listing.seller.to_string(),
+                token_id: token_id.clone(),
+            })?,
+            funds: vec![],
+        });
+        for (recipient, amount) in [(&plan.listing.seller, forfeit), (&plan.buyer, refund)] {
+            if !amount.is_zero() {
+                res = res.add_messages(self.send_native(
+                    deps.storage,
+                    recipient,
+                    Coin {
+                        denom: denom.clone(),
+                        amount,
+                    },
+                )?);
+            }
+        }
+
+        Ok(res
+            .add_attribute("method", "forfeit_installments")
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("token_id", token_id)
+            .add_attribute("forfeit", forfeit.to_string())
+            .add_attribute("refund", refund.to_string()))
+    }
+
+    // buy the cheapest listings of a collection, up to max_items listings priced at most max_price_per_item
+    // the listings which cannot be bought are skipped and the unspent funds are refunded
+    pub fn execute_sweep_collection(
+        self,
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        max_items: u32,
+        max_price_per_item: Uint128,
+    ) -> Result<Response, ContractError> {
+        if max_items == 0 || max_items > 30 {
+            return Err(ContractError::InvalidSweepSize { max_items });
         }
+
+        // only a single native token is accepted
+        if info.funds.len() != 1 {
+            return Err(ContractError::InsufficientFunds {});
+        }
+        let denom = info.funds[0].denom.clone();
+        let mut remaining = info.funds[0].amount;
+
+        // the listings are walked from the lowest price they can reach, so the walk stops at the
+        // first listing above the max price, the active price of each listing is checked on the way
+        let mut purchases = vec![];
+        {
+            let deps = deps.as_ref();
+            let listings = self
+                .listings
+                .idx
+                .price
+                .sub_prefix((contract_address.clone(), denom.clone()))
+                .range(deps.storage, None, None, Order::Ascending);
+            for item in listings {
+                if purchases.len() == max_items as usize {
+                    break;
+                }
+                let (_, listing) = item?;
+                match listing.min_fixed_price() {
+                    Some(price) if price.amount <= max_price_per_item => {}
+                    _ => break,
+                }
+
+                let listing = listing.with_active_price(&env.block);
+                let price = match &listing.auction_config {
+                    AuctionConfig::FixedPrice {
+                        price,
+                        start_time,
+                        end_time,
+                        ..
+                    } => {
+                        if price.amount > max_price_per_item
+                            || price.amount > remaining
+                            || (start_time.is_some() && !start_time.unwrap().is_expired(&env.block))
+                            || (end_time.is_some() && end_time.unwrap().is_expired(&env.block))
+                        {
+                            continue;
+                        }
+                        price.clone()
+                    }
+                    _ => continue,
+                };
+
+                // skip the listings which cannot be bought by the sender
+                if !listing.is_active()
+                    || listing.is_private()
+                    || listing.seller == info.sender
+                    || !self.is_listing_valid(deps.storage, &listing)?
+                    || check_listing_approval(
+                        deps,
+                        &env,
+                        &listing.seller,
+                        &listing.contract_address,
+                        &listing.token_id,
+                    )
+                    .is_err()
+                {
+                    continue;
+                }
+
+                remaining -= price.amount;
+                purchases.push((listing, price));
+            }
+        }
+
+        let mut res = Response::new();
+        let items_bought = purchases.len() as u32;
+        for (mut listing, price) in purchases {
+            listing.buyer = Some(info.sender.clone());
+            self.listings.remove(
+                deps.storage,
+                listing_key(&listing.contract_address, &listing.token_id),
+            )?;
+
+            let buy_res = self.process_buy_fixed_price(
+                deps.branch(),
+                env.clone(),
+                MessageInfo {
+                    sender: info.sender.clone(),
+                    funds: vec![price],
+                },
+                &listing,
+                None,
+            )?;
+            res = res
+                .add_submessages(buy_res.messages)
+                .add_attribute("token_id", listing.token_id);
+        }
+
+        if items_bought == 0 {
+            return Err(ContractError::NothingToSweep {});
+        }
+
+        // refund the unspent funds
+        res = res.add_messages(self.send_native(
+            deps.storage,
+            &info.sender,
+            Coin {
+                denom,
+                amount: remaining,
+            },
+        )?);
+
+        Ok(res
+            .add_attribute("method", "sweep_collection")
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("buyer", info.sender)
+            .add_attribute("items_bought", items_bought.to_string())
+            .add_attribute("refund", remaining.to_string()))
     }
 
     pub fn execute_cancel(
@@ -338,396 +734,3070 @@ impl MarketplaceContract<'static> {
             }
         }
 
-        // if a listing is not expired, only seller can cancel
+        // if a listing is not expired, only seller or their delegate can cancel
         if (!listing.is_expired(&env.block)) && (listing.seller != info.sender) {
-            return Err(ContractError::Unauthorized {});
+            self.use_delegation(
+                deps.storage,
+                &env,
+                (
+                    listing.seller.clone(),
+                    info.sender.clone(),
+                    contract_address.clone(),
+                ),
+                DelegatedAction::Cancel,
+                Uint128::zero(),
+            )?;
+        }
+
+        // a sealed bid auction with bids must be settled to release the deposits
+        if self.has_sealed_bids(deps.storage, &listing_key) {
+            return Err(ContractError::AuctionHasBids {});
         }
 
         // we will remove the cancelled listing
         self.listings.remove(deps.storage, listing_key)?;
 
+        let hook_msgs = self.hook_msgs(
+            deps.storage,
+            HookExecuteMsg::ListingHook(ListingHookMsg {
+                action: HookAction::Cancelled,
+                contract_address: contract_address.clone(),
+                token_id: token_id.clone(),
+                seller: listing.seller,
+            }),
+        )?;
+
         Ok(Response::new()
+            .add_submessages(hook_msgs)
             .add_attribute("method", "cancel")
             .add_attribute("contract_address", contract_address)
             .add_attribute("token_id", token_id)
             .add_attribute("cancelled_at", env.block.time.to_string()))
     }
 
-    // function to add a new auction contract
-    pub fn execute_add_auction_contract(
+    // list several nfts for sale as a single lot
+    pub fn execute_list_bundle(
         self,
-        _deps: DepsMut,
-        _env: Env,
-        _info: MessageInfo,
-        _auction_contract: AuctionContract,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        nfts: Vec<NFT>,
+        auction_config: AuctionConfig,
     ) -> Result<Response, ContractError> {
-        // check if auction contract already exists
+        // a bundle contains at least 2 nfts and at most 30 nfts
+        if nfts.len() < 2 || nfts.len() > 30 {
+            return Err(ContractError::InvalidBundleSize { size: nfts.len() });
+        }
 
-        // add auction contract
+        // only fixed price without schedule is supported for bundles
+        if !matches!(&auction_config, AuctionConfig::FixedPrice { price_schedule, .. } if price_schedule.is_empty())
+            || !self.validate_auction_config(&auction_config)
+        {
+            return Err(ContractError::InvalidAuctionConfig {});
+        }
 
-        // save config
-        Ok(Response::new().add_attribute("method", "add_auction_contract"))
-    }
+        for (index, nft) in nfts.iter().enumerate() {
+            let token_id = match &nft.token_id {
+                Some(token_id) => token_id,
+                None => {
+                    return Err(ContractError::CollectionItemNotSupported {});
+                }
+            };
+            if nfts[..index].contains(nft) {
+                return Err(ContractError::DuplicateNft {
+                    contract_address: nft.contract_address.clone(),
+                    token_id: token_id.clone(),
+                });
+            }
 
-    // function to remove an auction contract
-    pub fn execute_remove_auction_contract(
-        self,
-        _deps: DepsMut,
-        _env: Env,
-        _info: MessageInfo,
-        _contract_address: Addr,
-    ) -> Result<Response, ContractError> {
-        // check if auction contract exists
+            // check if user is the owner of the token and approves this contract to manage it
+            let contract_address = deps.api.addr_validate(nft.contract_address.as_str())?;
+            check_listing_approval(
+                deps.as_ref(),
+                &env,
+                &info.sender,
+                &contract_address,
+                token_id,
+            )?;
+        }
 
-        // remove auction contract
+        let bundle_id = self.generate_order_id(deps.storage)?;
+        let bundle = Bundle {
+            bundle_id,
+            nfts,
+            auction_config,
+            counter: self.user_counter(deps.storage, &info.sender)?,
+            seller: info.sender,
+        };
+        self.bundles.save(deps.storage, bundle_id, &bundle)?;
 
-        // save config
-        Ok(Response::new().add_attribute("method", "remove_auction_contract"))
+        Ok(Response::new()
+            .add_attribute("method", "list_bundle")
+            .add_attribute("bundle_id", bundle_id.to_string())
+            .add_attribute("nfts", bundle.nfts.len().to_string())
+            .add_attribute("seller", bundle.seller.to_string()))
     }
 
-    // function to add new offer nft using ordering style
-    // the 'offer' of offer_nft will contain the information of price
-    // the 'consideration' of offer_nft will contain the information of nft
-    pub fn execute_offer_nft(
+    // buy all nfts of a bundle, the royalties are split proportionally between the nfts
+    pub fn execute_buy_bundle(
         self,
         deps: DepsMut,
         env: Env,
         info: MessageInfo,
-        nft: NFT,
-        funds_amount: u128,
-        end_time: Cw721Expiration,
+        bundle_id: BundleKey,
+        referrer: Option<Addr>,
     ) -> Result<Response, ContractError> {
-        // load config
-        let config = self.config.load(deps.storage)?;
-        // check ig the vaura_address is set (the default value is equal to "aura0")
-        if config.vaura_address == Addr::unchecked("aura0") {
-            return Err(ContractError::VauraAddressNotSet {});
-        }
+        let bundle = self.bundles.load(deps.storage, bundle_id)?;
 
-        // check if the end time is valid
-        if end_time.is_expired(&env.block) {
-            return Err(ContractError::InvalidEndTime {});
+        // check if the seller has invalidated the bundle
+        if !self.is_bundle_valid(deps.storage, &bundle)? {
+            return Err(ContractError::OrderInvalidated {});
         }
-        // ***********
-        // OFFERING FUNDS
-        // ***********
-        // load config
-        let config = self.config.load(deps.storage)?;
-
-        let token_address = config.vaura_address;
-        let amount = funds_amount;
-
-        // check that the allowance of the cw20 offer token is enough
-        let allowance_response: AllowanceResponse = deps
-            .querier
-            .query_wasm_smart(
-                &token_address,
-                &Cw20QueryMsg::Allowance {
-                    owner: info.sender.to_string(),
-                    spender: env.contract.address.to_string(),
-                },
-            )
-            .unwrap();
 
-        // check if the allowance is greater or equal the offer amount
-        if allowance_response.allowance < Uint128::from(amount) {
-            return Err(ContractError::InsufficientAllowance {});
+        // check if buyer is the same as seller
+        if info.sender == bundle.seller {
+            return Err(ContractError::OwnerCannotBuy {});
+        }
+        if referrer.as_ref() == Some(&info.sender) || referrer.as_ref() == Some(&bundle.seller) {
+            return Err(ContractError::InvalidReferrer {});
         }
 
-        // *******************
-        // CONSIDERATION ITEMS
-        // *******************
-        let contract_address = nft.contract_address;
-        let token_id = nft.token_id;
-        if let Some(token_id) = token_id {
-            // query the owner of the nft to check if the nft exist
-            let owner_response: StdResult<cw721::OwnerOfResponse> = deps.querier.query_wasm_smart(
-                &contract_address,
-                &Cw721QueryMsg::OwnerOf {
-                    token_id: token_id.clone(),
-                    include_expired: Some(false),
-                },
-            );
-
-            match owner_response {
-                Ok(owner) => {
-                    if owner.owner == info.sender {
-                        return Err(ContractError::CustomError {
-                            val: ("Cannot offer owned nft".to_string()),
-                        });
-                    }
+        let price = match &bundle.auction_config {
+            AuctionConfig::FixedPrice {
+                price,
+                start_time,
+                end_time,
+                ..
+            } => {
+                // check if current block is after start_time
+                if start_time.is_some() && !start_time.unwrap().is_expired(&env.block) {
+                    return Err(ContractError::AuctionNotStarted {
+                        start_time: start_time.unwrap(),
+                    });
                 }
-                Err(_) => {
-                    return Err(ContractError::CustomError {
-                        val: ("Nft not exist".to_string()),
+                if end_time.is_some() && end_time.unwrap().is_expired(&env.block) {
+                    return Err(ContractError::AuctionEnded {
+                        end_time: end_time.unwrap(),
+                        block_time: env.block.time,
                     });
                 }
+                price
             }
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
 
-            // generate order key for order components based on user address, contract address and token id
-            let order_key = order_key(&info.sender, &contract_address, &token_id);
+        // check if enough funds
+        if info.funds.len() != 1 || info.funds[0] != *price {
+            return Err(ContractError::InsufficientFunds {});
+        }
 
-            // the offer item will contain the infomation of cw20 token
-            let offer_item = offer_item(
-                &ItemType::CW20,
-                &Asset::Cw20(CW20 {
-                    contract_address: token_address,
-                    amount,
-                }),
-                &0u128,
-                &0u128,
-            );
+        // remove the bundle
+        self.bundles.remove(deps.storage, bundle_id)?;
 
-            // the consideration item will contain the infomation of nft
-            let consideration_item = consideration_item(
-                &ItemType::CW721,
-                &Asset::Nft(NFT {
-                    contract_address,
-                    token_id: Some(token_id),
-                }),
-                &0u128,
-                &0u128,
-                &info.sender,
-            );
+        let mut res = Response::new();
 
-            // generate order components
-            let order_offer = OrderComponents {
-                order_type: OrderType::OFFER, // The type of offer must be OFFER
-                order_id: order_key.clone(),
-                offerer: info.sender,
-                offer: [offer_item].to_vec(),
-                consideration: [consideration_item].to_vec(),
-                start_time: None,
-                end_time: Some(end_time),
+        // each nft is sold at its share of the price, with the payouts of a single listing
+        let config = self.config.load(deps.storage)?;
+        let mut payouts: BTreeMap<Addr, Uint128> = BTreeMap::new();
+        let mut referral_fee = Uint128::zero();
+        let mut hook_msgs = vec![];
+        let item_prices = bundle_item_prices(&bundle.nfts, price.amount);
+        for (nft, sale_price) in bundle.nfts.iter().zip(item_prices) {
+            let token_id = nft.token_id.clone().unwrap_or_default();
+
+            // the bundle is invalid if the seller does not own all of its nfts anymore
+            if check_nft_owner(&deps, &nft.contract_address, &token_id, &bundle.seller).is_err() {
+                return Err(ContractError::NftNotOwnedBySeller {
+                    contract_address: nft.contract_address.clone(),
+                    token_id: token_id.clone(),
+                });
+            }
+
+            for (kind, recipient, coin) in nft_sale_payouts(
+                deps.as_ref(),
+                &config,
+                &nft.contract_address,
+                &token_id,
+                &bundle.seller,
+                &Coin {
+                    denom: price.denom.clone(),
+                    amount: sale_price,
+                },
+                referrer.as_ref(),
+            )? {
+                if kind == TransferKind::ReferralFee {
+                    referral_fee += coin.amount;
+                } else {
+                    *payouts.entry(recipient).or_default() += coin.amount;
+                }
+            }
+            let sale_price = PaymentAsset::Native {
+                denom: price.denom.clone(),
+                amount: sale_price.u128(),
             };
+            self.accrue_rewards(
+                deps.storage,
+                &env,
+                &nft.contract_address,
+                [&bundle.seller, &info.sender],
+                std::slice::from_ref(&sale_price),
+            )?;
+            hook_msgs.extend(self.hook_msgs(
+                deps.storage,
+                HookExecuteMsg::SaleHook(SaleHookMsg {
+                    contract_address: nft.contract_address.clone(),
+                    token_id: token_id.clone(),
+                    seller: bundle.seller.clone(),
+                    buyer: info.sender.clone(),
+                    price: sale_price,
+                }),
+            )?);
+
+            // message to transfer nft to buyer
+            res = res.add_message(WasmMsg::Execute {
+                contract_addr: nft.contract_address.to_string(),
+                msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                    recipient: info.sender.to_string(),
+                    token_id,
+                })?,
+                funds: vec![],
+            });
+        }
 
-            // we will override the order if it already exists
-            let new_offer = self.offers.update(
+        // transfer the royalties to the creators, the referral fee and the remaining funds to the seller
+        for (recipient, amount) in payouts {
+            res = res.add_messages(self.send_native(
+                deps.storage,
+                &recipient,
+                Coin {
+                    denom: price.denom.clone(),
+                    amount,
+                },
+            )?);
+        }
+        if let Some(referrer) = referrer.filter(|_| !referral_fee.is_zero()) {
+            self.add_referral_earnings(
                 deps.storage,
-                order_key,
-                |_old| -> Result<OrderComponents, ContractError> { Ok(order_offer) },
+                &referrer,
+                &PaymentAsset::Native {
+                    denom: price.denom.clone(),
+                    amount: referral_fee.u128(),
+                },
             )?;
+            res = res
+                .add_messages(self.send_native(
+                    deps.storage,
+                    &referrer,
+                    Coin {
+                        denom: price.denom.clone(),
+                        amount: referral_fee,
+                    },
+                )?)
+                .add_attribute("referrer", referrer);
+        }
 
-            let offer_str = serde_json::to_string(&new_offer.offer);
-            let consideration_str = serde_json::to_string(&new_offer.consideration);
+        Ok(res
+            .add_submessages(hook_msgs)
+            .add_attribute("method", "buy_bundle")
+            .add_attribute("bundle_id", bundle_id.to_string())
+            .add_attribute("buyer", info.sender))
+    }
 
-            // return success
-            Ok(Response::new()
-                .add_attribute("method", "create_offer")
-                .add_attribute("order_type", "OFFER")
-                .add_attribute("offerer", new_offer.offerer)
-                .add_attribute("offer", offer_str.unwrap())
-                .add_attribute("consideration", consideration_str.unwrap())
-                .add_attribute("end_time", new_offer.end_time.unwrap().to_string()))
-        } else {
-            // if the token_id is not exist, then this order is offer for a collection of nft
-            // we will handle this in the next version => return error for now
-            Err(ContractError::CustomError {
-                val: ("Collection offer is not supported".to_string()),
-            })
+    pub fn execute_cancel_bundle(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        bundle_id: BundleKey,
+    ) -> Result<Response, ContractError> {
+        let bundle = self.bundles.load(deps.storage, bundle_id)?;
+
+        // if a bundle is not expired, only seller can cancel
+        if (!bundle.is_expired(&env.block)) && (bundle.seller != info.sender) {
+            return Err(ContractError::Unauthorized {});
         }
+
+        self.bundles.remove(deps.storage, bundle_id)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "cancel_bundle")
+            .add_attribute("bundle_id", bundle_id.to_string())
+            .add_attribute("cancelled_at", env.block.time.to_string()))
     }
 
-    // function to accept offer nft using ordering style
-    pub fn execute_accept_nft_offer(
+    // create a reverse auction, the sender escrows the max price they are willing to pay for the nft
+    pub fn execute_create_reverse_auction(
         self,
         deps: DepsMut,
         env: Env,
         info: MessageInfo,
-        offerer: Addr,
         nft: NFT,
-        funds_amount: u128,
+        end_time: Cw721Expiration,
     ) -> Result<Response, ContractError> {
-        let contract_address = nft.contract_address;
-        let token_id = nft.token_id;
-        // if the token_id is exist, then this order is offer for a specific nft
-        if let Some(token_id) = token_id {
-            // generate order key for order components based on user address, contract address and token id
-            let order_key = order_key(&offerer, &contract_address, &token_id);
+        deps.api.addr_validate(nft.contract_address.as_str())?;
+
+        // check if the end time is valid
+        if end_time.is_expired(&env.block) {
+            return Err(ContractError::InvalidEndTime {});
+        }
+
+        // the escrowed funds are the max price
+        if info.funds.len() != 1 || info.funds[0].amount.is_zero() {
+            return Err(ContractError::InsufficientFunds {});
+        }
+
+        let auction_id = self.generate_order_id(deps.storage)?;
+        let auction = ReverseAuction {
+            auction_id,
+            buyer: info.sender,
+            nft,
+            max_price: info.funds[0].clone(),
+            end_time,
+            best_ask: None,
+        };
+        self.reverse_auctions
+            .save(deps.storage, auction_id, &auction)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "create_reverse_auction")
+            .add_attribute("auction_id", auction_id.to_string())
+            .add_attribute("buyer", auction.buyer)
+            .add_attribute("max_price", auction.max_price.to_string()))
+    }
+
+    // submit an ask to a reverse auction, the ask must be lower than the current lowest ask
+    pub fn execute_submit_ask(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        auction_id: ReverseAuctionKey,
+        token_id: String,
+        price: Uint128,
+    ) -> Result<Response, ContractError> {
+        let mut auction = self.reverse_auctions.load(deps.storage, auction_id)?;
+
+        if auction.end_time.is_expired(&env.block) {
+            return Err(ContractError::AuctionEnded {
+                end_time: auction.end_time,
+                block_time: env.block.time,
+            });
+        }
 
-            // get order components
-            let order_components = self.offers.load(deps.storage, order_key.clone())?;
+        // the nft must match the requested nft
+        if auction.nft.token_id.is_some() && auction.nft.token_id.as_ref() != Some(&token_id) {
+            return Err(ContractError::NftNotFound {});
+        }
 
-            // if the end time of the offer is expired, then return error
-            if order_components.end_time.unwrap().is_expired(&env.block) {
-                return Err(ContractError::CustomError {
-                    val: ("Offer is expired".to_string()),
+        if price.is_zero() {
+            return Err(ContractError::InvalidAmount {});
+        }
+        if price > auction.max_price.amount {
+            return Err(ContractError::AskAboveMaxPrice {
+                ask: price,
+                max_price: auction.max_price.amount,
+            });
+        }
+        // an ask whose nft can no longer be transferred is replaced by any valid ask
+        if let Some(best_ask) = settleable_ask(deps.as_ref(), &env, &auction) {
+            if price >= best_ask.price {
+                return Err(ContractError::AskNotLower {
+                    ask: price,
+                    best_ask: best_ask.price,
                 });
             }
-            match &order_components.consideration[0].item {
-                // match if the consideration item is Nft
-                Asset::Nft(NFT {
-                    contract_address,
-                    token_id,
-                }) => {
-                    // query the owner of the nft
-                    let owner: cw721::OwnerOfResponse = deps
-                        .querier
-                        .query_wasm_smart(
-                            contract_address,
-                            &Cw721QueryMsg::OwnerOf {
-                                token_id: token_id.clone().unwrap(),
-                                include_expired: Some(false),
-                            },
-                        )
-                        .unwrap();
+        }
 
-                    // if the nft is not belong to the info.sender, then return error
-                    if owner.owner != info.sender {
-                        return Err(ContractError::Unauthorized {});
-                    }
+        // check if user is the owner of the token and approves this contract to manage it
+        check_listing_approval(
+            deps.as_ref(),
+            &env,
+            &info.sender,
+            &auction.nft.contract_address,
+            &token_id,
+        )?;
 
-                    let mut res: Response = Response::new();
+        auction.best_ask = Some(Ask {
+            seller: info.sender.clone(),
+            token_id: token_id.clone(),
+            price,
+        });
+        self.reverse_auctions
+            .save(deps.storage, auction_id, &auction)?;
 
-                    // ***********************
-                    // TRANSFER CW20 TO SENDER
-                    // ***********************
-                    // convert Asset to PaymentAsset
-                    let payment_item = PaymentAsset::from(order_components.offer[0].item.clone());
+        Ok(Response::new()
+            .add_attribute("method", "submit_ask")
+            .add_attribute("auction_id", auction_id.to_string())
+            .add_attribute("seller", info.sender)
+            .add_attribute("token_id", token_id)
+            .add_attribute("price", price.to_string()))
+    }
 
-                    // execute cw20 transfer msg from offerer to info.sender
-                    match &payment_item {
-                        PaymentAsset::Cw20 {
-                            contract_address: _,
-                            amount,
-                        } => {
-                            if funds_amount < *amount {
-                                return Err(ContractError::CustomError {
-                                    val: ("Insufficient funds".to_string()),
-                                });
-                            }
-                            let payment_messages = self.payment_with_royalty(
-                                &deps,
-                                contract_address.clone(),
-                                token_id.as_ref().unwrap().clone(),
-                                payment_item.clone(),
-                                offerer,
-                                info.sender,
-                            );
-
-                            // loop through all payment messages and add item to response to execute
-                            for payment_message in payment_messages {
-                                res = res.add_message(payment_message);
-                            }
-                        }
-                        _ => {
-                            return Err(ContractError::CustomError {
-                                val: ("Invalid Offer funding type".to_string()),
-                            });
-                        }
-                    }
+    // settle a reverse auction after its end_time, anyone can settle it
+    // the lowest ask is paid with royalties and the unspent funds are refunded to the buyer
+    pub fn execute_settle_reverse_auction(
+        self,
+        mut deps: DepsMut,
+        env: Env,
+        _info: MessageInfo,
+        auction_id: ReverseAuctionKey,
+    ) -> Result<Response, ContractError> {
+        let auction = self.reverse_auctions.load(deps.storage, auction_id)?;
 
-                    // ***********************
-                    // TRANSFER NFT TO OFFERER
-                    // ***********************
-                    // message to transfer nft to offerer
-                    let transfer_nft_msg = WasmMsg::Execute {
-                        contract_addr: contract_address.clone().to_string(),
-                        msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
-                            recipient: order_components.offerer.clone().to_string(),
-                            token_id: token_id.clone().unwrap(),
-                        })?,
-                        funds: vec![],
-                    };
+        if !auction.end_time.is_expired(&env.block) {
+            return Err(ContractError::AuctionNotEnded {
+                end_time: auction.end_time,
+            });
+        }
 
-                    // add transfer nft message to response to execute
-                    res = res.add_message(transfer_nft_msg);
+        self.reverse_auctions.remove(deps.storage, auction_id);
 
-                    // After the offer is accepted, we will delete the order
-                    self.offers.remove(deps.storage, order_key)?;
+        let mut res = Response::new()
+            .add_attribute("method", "settle_reverse_auction")
+            .add_attribute("auction_id", auction_id.to_string());
 
-                    Ok(res
-                        .add_attribute("method", "execute_accept_nft_offer")
-                        .add_attribute("owner", owner.owner)
-                        .add_attribute("offerer", order_components.offerer)
-                        .add_attribute("nft_contract_address", contract_address.to_string())
-                        .add_attribute("token_id", token_id.clone().unwrap()))
+        // the ask is settled only if the seller still owns the nft and approves this contract,
+        // otherwise it is dropped and the buyer is refunded in full
+        let mut refund = auction.max_price.amount;
+        match settleable_ask(deps.as_ref(), &env, &auction).cloned() {
+            Some(ask) => {
+                let sale_price = PaymentAsset::Native {
+                    denom: auction.max_price.denom.clone(),
+                    amount: ask.price.u128(),
+                };
+                self.accrue_rewards(
+                    deps.storage,
+                    &env,
+                    &auction.nft.contract_address,
+                    [&ask.seller, &auction.buyer],
+                    std::slice::from_ref(&sale_price),
+                )?;
+                let hook_msgs = self.hook_msgs(
+                    deps.storage,
+                    HookExecuteMsg::SaleHook(SaleHookMsg {
+                        contract_address: auction.nft.contract_address.clone(),
+                        token_id: ask.token_id.clone(),
+                        seller: ask.seller.clone(),
+                        buyer: auction.buyer.clone(),
+                        price: sale_price,
+                    }),
+                )?;
+                res = res
+                    .add_message(WasmMsg::Execute {
+                        contract_addr: auction.nft.contract_address.to_string(),
+                        msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                            recipient: auction.buyer.to_string(),
+                            token_id: ask.token_id.clone(),
+                        })?,
+                        funds: vec![],
+                    })
+                    .add_messages(self.native_sale_payment_msgs(
+                        &mut deps,
+                        &auction.nft.contract_address,
+                        &ask.token_id,
+                        &ask.seller,
+                        &Coin {
+                            denom: auction.max_price.denom.clone(),
+                            amount: ask.price,
+                        },
+                    )?)
+                    .add_submessages(hook_msgs)
+                    .add_attribute("seller", ask.seller)
+                    .add_attribute("token_id", ask.token_id)
+                    .add_attribute("price", ask.price.to_string());
+                refund -= ask.price;
+            }
+            None => {
+                if auction.best_ask.is_some() {
+                    res = res.add_attribute("ask_dropped", "true");
                 }
-                // if the consideration item is not Nft, then return error
-                _ => Err(ContractError::CustomError {
-                    val: ("Consideration is not NFT".to_string()),
-                }),
             }
-        } else {
-            Err(ContractError::CustomError {
-                val: ("Collection offer is not supported".to_string()),
-            })
         }
+
+        res = res.add_messages(self.send_native(
+            deps.storage,
+            &auction.buyer,
+            Coin {
+                denom: auction.max_price.denom,
+                amount: refund,
+            },
+        )?);
+
+        Ok(res)
     }
 
-    pub fn execute_cancel_offer(
-        &self,
+    // cancel a reverse auction without any settleable ask, the escrowed funds are refunded to the buyer
+    pub fn execute_cancel_reverse_auction(
+        self,
         deps: DepsMut,
         env: Env,
         info: MessageInfo,
-        nfts: Vec<NFT>,
+        auction_id: ReverseAuctionKey,
     ) -> Result<Response, ContractError> {
-        // if the number of nfts is greater than 50, then return error
-        if nfts.len() > 50 {
-            return Err(ContractError::CustomError {
-                val: ("Number of NFTs is greater than 50".to_string()),
-            });
-        }
-
-        // loop through all nfts
-        for nft in nfts {
-            // generate order key based on the sender address, nft.contract_address and nft.token_id
-            let order_key = order_key(&info.sender, &nft.contract_address, &nft.token_id.unwrap());
-
-            // check if the order exists
-            if !self.offers.has(deps.storage, order_key.clone()) {
-                return Err(ContractError::CustomError {
-                    val: ("Offer does not exist".to_string()),
-                });
-            }
+        let auction = self.reverse_auctions.load(deps.storage, auction_id)?;
 
-            // we will remove the cancelled offer
-            self.offers.remove(deps.storage, order_key)?;
+        if auction.buyer != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        if settleable_ask(deps.as_ref(), &env, &auction).is_some() {
+            return Err(ContractError::AuctionHasAsk {});
         }
 
+        self.reverse_auctions.remove(deps.storage, auction_id);
+
         Ok(Response::new()
-            .add_attribute("method", "cancel_all_offer")
-            .add_attribute("user", info.sender.to_string())
-            .add_attribute("cancelled_at", env.block.time.to_string()))
+            .add_messages(self.send_native(deps.storage, &auction.buyer, auction.max_price)?)
+            .add_attribute("method", "cancel_reverse_auction")
+            .add_attribute("auction_id", auction_id.to_string()))
     }
 
-    pub fn execute_edit_vaura_token(
-        &self,
+    // commit a sealed bid with a deposit covering the bid amount
+    pub fn execute_commit_bid(
+        self,
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
-        token_address: String,
+        contract_address: Addr,
+        token_id: String,
+        commitment: Binary,
     ) -> Result<Response, ContractError> {
-        // get owner
-        let mut conf = self.config.load(deps.storage)?;
+        let listing_key = listing_key(&contract_address, &token_id);
+        let listing = self.listings.load(deps.storage, listing_key.clone())?;
 
-        // check if the sender is the owner
-        if conf.owner != info.sender {
-            return Err(ContractError::Unauthorized {});
+        if !listing.is_active() || !self.is_listing_valid(deps.storage, &listing)? {
+            return Err(ContractError::ListingNotActive {});
+        }
+        let (min_price, bidding_end) = match &listing.auction_config {
+            AuctionConfig::SealedBid {
+                min_price,
+                bidding_end,
+                ..
+            } => (min_price, bidding_end),
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
+        if bidding_end.is_expired(&env.block) {
+            return Err(ContractError::BiddingEnded {
+                bidding_end: *bidding_end,
+            });
+        }
+        if info.sender == listing.seller {
+            return Err(ContractError::OwnerCannotBid {});
         }
 
-        // update vaura address in config
-        conf.vaura_address = Addr::unchecked(&token_address);
-        // conf.vaura_address = deps.api.addr_validate(&token_address)?;
+        // the deposit must be at least the min price
+        if info.funds.len() != 1
+            || info.funds[0].denom != min_price.denom
+            || info.funds[0].amount < min_price.amount
+        {
+            return Err(ContractError::InsufficientFunds {});
+        }
 
-        // save config
-        self.config.save(deps.storage, &conf)?;
+        let bid_key = (contract_address, token_id, info.sender.clone());
+        if self.sealed_bids.has(deps.storage, bid_key.clone()) {
+            return Err(ContractError::AlreadyExists {});
+        }
+        let bids = self
+            .sealed_bids
+            .prefix(listing_key)
+            .keys(deps.storage, None, None, Order::Ascending)
+            .take(MAX_SEALED_BIDS as usize)
+            .count();
+        if bids == MAX_SEALED_BIDS as usize {
+            return Err(ContractError::TooManySealedBids {
+                max_bids: MAX_SEALED_BIDS,
+            });
+        }
+        let bid = SealedBid {
+            bidder: info.sender,
+            commitment,
+            deposit: info.funds[0].clone(),
+            committed_at: env.block.height,
+            revealed_amount: None,
+        };
+        self.sealed_bids.save(deps.storage, bid_key, &bid)?;
 
         Ok(Response::new()
-            .add_attribute("method", "edit_vaura_token")
-            .add_attribute("vaura_token_address", token_address))
+            .add_attribute("method", "commit_bid")
+            .add_attribute("contract_address", listing.contract_address)
+            .add_attribute("token_id", listing.token_id)
+            .add_attribute("bidder", bid.bidder)
+            .add_attribute("deposit", bid.deposit.to_string()))
     }
 
-    // function to process payment transfer with royalty
-    fn payment_with_royalty(
-        &self,
-        deps: &DepsMut,
-        nft_contract_address: Addr,
-        nft_id: String,
-        token: PaymentAsset,
+    // reveal a sealed bid, the amount and the salt must match the commitment
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_reveal_bid(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+        amount: Uint128,
+        salt: String,
+    ) -> Result<Response, ContractError> {
+        let listing = self
+            .listings
+            .load(deps.storage, listing_key(&contract_address, &token_id))?;
+        match &listing.auction_config {
+            AuctionConfig::SealedBid {
+                bidding_end,
+                reveal_end,
+                ..
+            } => {
+                if !bidding_end.is_expired(&env.block) || reveal_end.is_expired(&env.block) {
+                    return Err(ContractError::NotInRevealPhase {});
+                }
+            }
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        }
+
+        let bid_key = (contract_address, token_id, info.sender.clone());
+        let mut bid = self.sealed_bids.load(deps.storage, bid_key.clone())?;
+        if bid.revealed_amount.is_some() {
+            return Err(ContractError::AlreadyExists {});
+        }
+        if sealed_bid_commitment(&info.sender, amount, &salt) != bid.commitment {
+            return Err(ContractError::CommitmentMismatch {});
+        }
+        if amount > bid.deposit.amount {
+            return Err(ContractError::InsufficientFunds {});
+        }
+
+        bid.revealed_amount = Some(amount);
+        self.sealed_bids.save(deps.storage, bid_key, &bid)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "reveal_bid")
+            .add_attribute("contract_address", listing.contract_address)
+            .add_attribute("token_id", listing.token_id)
+            .add_attribute("bidder", info.sender)
+            .add_attribute("amount", amount.to_string()))
+    }
+
+    // settle a sealed bid auction after the reveal phase, anyone can settle it
+    // the highest revealed bid wins at the second highest price, or the min price if it is the only bid
+    // revealed deposits are credited to the pending balances of their bidders, to be withdrawn by each bidder,
+    // and unrevealed deposits are slashed to the seller
+    // if the nft can no longer be transferred, the auction is closed and every deposit is credited back
+    pub fn execute_settle_sealed_bid(
+        self,
+        mut deps: DepsMut,
+        env: Env,
+        _info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+    ) -> Result<Response, ContractError> {
+        let listing_key = listing_key(&contract_address, &token_id);
+        let listing = self.listings.load(deps.storage, listing_key.clone())?;
+        let min_price = match &listing.auction_config {
+            AuctionConfig::SealedBid {
+                min_price,
+                reveal_end,
+                ..
+            } => {
+                if !reveal_end.is_expired(&env.block) {
+                    return Err(ContractError::RevealNotEnded {
+                        reveal_end: *reveal_end,
+                    });
+                }
+                min_price.clone()
+            }
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
+
+        let bids = self
+            .sealed_bids
+            .prefix(listing_key.clone())
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, bid)| bid))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        self.listings.remove(deps.storage, listing_key.clone())?;
+        for bid in &bids {
+            self.sealed_bids.remove(
+                deps.storage,
+                (
+                    listing_key.0.clone(),
+                    listing_key.1.clone(),
+                    bid.bidder.clone(),
+                ),
+            );
+        }
+
+        // the valid bids are sorted by amount, the earlier bid wins a tie
+        let mut revealed: Vec<&SealedBid> = bids
+            .iter()
+            .filter(|bid| matches!(bid.revealed_amount, Some(amount) if amount >= min_price.amount))
+            .collect();
+        revealed.sort_by(|a, b| {
+            b.revealed_amount
+                .cmp(&a.revealed_amount)
+                .then(a.committed_at.cmp(&b.committed_at))
+        });
+
+        let mut res = Response::new()
+            .add_attribute("method", "settle_sealed_bid")
+            .add_attribute("contract_address", contract_address.to_string())
+            .add_attribute("token_id", token_id.clone());
+
+        // the auction is settled only if the listing is valid and the seller still owns the nft
+        // and approves this contract to manage it
+        let transferable = self.is_listing_valid(deps.storage, &listing)?
+            && check_listing_approval(
+                deps.as_ref(),
+                &env,
+                &listing.seller,
+                &contract_address,
+                &token_id,
+            )
+            .is_ok();
+        if !transferable {
+            res = res.add_attribute("refunded", "true");
+        }
+        let winner = revealed.first().copied().filter(|_| transferable);
+        if let Some(winner) = winner {
+            let price = revealed
+                .get(1)
+                .and_then(|bid| bid.revealed_amount)
+                .unwrap_or(min_price.amount);
+
+            res = res
+                .add_message(WasmMsg::Execute {
+                    contract_addr: contract_address.to_string(),
+                    msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                        recipient: winner.bidder.to_string(),
+                        token_id: token_id.clone(),
+                    })?,
+                    funds: vec![],
+                })
+                .add_messages(self.native_sale_payment_msgs(
+                    &mut deps,
+                    &contract_address,
+                    &token_id,
+                    &listing.seller,
+                    &Coin {
+                        denom: min_price.denom.clone(),
+                        amount: price,
+                    },
+                )?)
+                .add_attribute("winner", winner.bidder.to_string())
+                .add_attribute("price", price.to_string());
+            let sale_price = PaymentAsset::Native {
+                denom: min_price.denom.clone(),
+                amount: price.u128(),
+            };
+            self.accrue_rewards(
+                deps.storage,
+                &env,
+                &contract_address,
+                [&listing.seller, &winner.bidder],
+                std::slice::from_ref(&sale_price),
+            )?;
+            res = res.add_submessages(self.hook_msgs(
+                deps.storage,
+                HookExecuteMsg::SaleHook(SaleHookMsg {
+                    contract_address: contract_address.clone(),
+                    token_id: token_id.clone(),
+                    seller: listing.seller.clone(),
+                    buyer: winner.bidder.clone(),
+                    price: sale_price,
+                }),
+            )?);
+            res = res.add_messages(self.send_native(
+                deps.storage,
+                &winner.bidder,
+                Coin {
+                    denom: min_price.denom.clone(),
+                    amount: winner.deposit.amount - price,
+                },
+            )?);
+        }
+
+        // credit the revealed deposits and slash the unrevealed deposits to the seller,
+        // unless the seller cannot deliver the nft
+        // a bidder which cannot receive funds cannot block the settlement, it withdraws its deposit later
+        let mut slashed = Uint128::zero();
+        for bid in &bids {
+            if winner.map(|winner| winner.bidder == bid.bidder) == Some(true) {
+                continue;
+            }
+            if bid.revealed_amount.is_some() || !transferable {
+                self.credit_native(deps.storage, &bid.bidder, &bid.deposit)?;
+            } else {
+                slashed += bid.deposit.amount;
+            }
+        }
+        res = res.add_messages(self.send_native(
+            deps.storage,
+            &listing.seller,
+            Coin {
+                denom: min_price.denom,
+                amount: slashed,
+            },
+        )?);
+
+        Ok(res)
+    }
+
+    // function to add a new auction contract
+    pub fn execute_add_auction_contract(
+        self,
+        _deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        _auction_contract: AuctionContract,
+    ) -> Result<Response, ContractError> {
+        // check if auction contract already exists
+
+        // add auction contract
+
+        // save config
+        Ok(Response::new().add_attribute("method", "add_auction_contract"))
+    }
+
+    // function to remove an auction contract
+    pub fn execute_remove_auction_contract(
+        self,
+        _deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        _contract_address: Addr,
+    ) -> Result<Response, ContractError> {
+        // check if auction contract exists
+
+        // remove auction contract
+
+        // save config
+        Ok(Response::new().add_attribute("method", "remove_auction_contract"))
+    }
+
+    // function to add new offer nft using ordering style
+    // the 'offer' of offer_nft will contain the information of price
+    // the 'consideration' of offer_nft will contain the information of nft
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_offer_nft(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        nft: NFT,
+        funds_amount: u128,
+        end_time: Cw721Expiration,
+        offerer: Option<Addr>,
+    ) -> Result<Response, ContractError> {
+        // a delegate offers the funds of the offerer, within the spending cap of the delegation
+        let offerer = match offerer {
+            Some(offerer) if offerer != info.sender => {
+                self.use_delegation(
+                    deps.storage,
+                    &env,
+                    (
+                        offerer.clone(),
+                        info.sender.clone(),
+                        nft.contract_address.clone(),
+                    ),
+                    DelegatedAction::Offer,
+                    Uint128::from(funds_amount),
+                )?;
+                offerer
+            }
+            _ => info.sender,
+        };
+
+        // load config
+        let config = self.config.load(deps.storage)?;
+        // check ig the vaura_address is set (the default value is equal to "aura0")
+        if config.vaura_address == Addr::unchecked("aura0") {
+            return Err(ContractError::VauraAddressNotSet {});
+        }
+
+        // check if the end time is valid
+        if end_time.is_expired(&env.block) {
+            return Err(ContractError::InvalidEndTime {});
+        }
+        // ***********
+        // OFFERING FUNDS
+        // ***********
+        // load config
+        let config = self.config.load(deps.storage)?;
+
+        let token_address = config.vaura_address;
+        let amount = funds_amount;
+
+        // check that the allowance of the cw20 offer token is enough
+        let allowance_response: AllowanceResponse = deps.querier.query_wasm_smart(
+            &token_address,
+            &Cw20QueryMsg::Allowance {
+                owner: offerer.to_string(),
+                spender: env.contract.address.to_string(),
+            },
+        )?;
+
+        // check if the allowance is greater or equal the offer amount
+        if allowance_response.allowance < Uint128::from(amount) {
+            return Err(ContractError::InsufficientAllowance {});
+        }
+
+        // *******************
+        // CONSIDERATION ITEMS
+        // *******************
+        let contract_address = nft.contract_address;
+        let token_id = nft.token_id;
+        if let Some(token_id) = token_id {
+            // query the owner of the nft to check if the nft exist
+            let owner_response: StdResult<cw721::OwnerOfResponse> = deps.querier.query_wasm_smart(
+                &contract_address,
+                &Cw721QueryMsg::OwnerOf {
+                    token_id: token_id.clone(),
+                    include_expired: Some(false),
+                },
+            );
+
+            let nft_owner = match owner_response {
+                Ok(owner) => {
+                    if owner.owner == offerer {
+                        return Err(ContractError::CannotOfferOwnNFT {});
+                    }
+                    Addr::unchecked(owner.owner)
+                }
+                Err(_) => {
+                    return Err(ContractError::NftNotFound {});
+                }
+            };
+
+            // generate a new order id, so the offer will not override the other offers of the user
+            let order_id = self.generate_order_id(deps.storage)?;
+
+            // the offer item will contain the infomation of cw20 token
+            let offer_item = offer_item(
+                &ItemType::CW20,
+                &Asset::Cw20(CW20 {
+                    contract_address: token_address,
+                    amount,
+                }),
+                &0u128,
+                &0u128,
+            );
+
+            // the consideration item will contain the infomation of nft
+            let consideration_item = consideration_item(
+                &ItemType::CW721,
+                &Asset::Nft(NFT {
+                    contract_address: contract_address.clone(),
+                    token_id: Some(token_id.clone()),
+                }),
+                &0u128,
+                &0u128,
+                &offerer,
+            );
+
+            // generate order components
+            let order_offer = OrderComponents {
+                order_type: OrderType::OFFER, // The type of offer must be OFFER
+                order_id,
+                counter: self.user_counter(deps.storage, &offerer)?,
+                offerer,
+                offer: [offer_item].to_vec(),
+                consideration: [consideration_item].to_vec(),
+                start_time: None,
+                end_time: Some(end_time),
+            };
+
+            self.offers.save(deps.storage, order_id, &order_offer)?;
+
+            let offer_str = serde_json::to_string(&order_offer.offer)
+                .map_err(|err| StdError::serialize_err("OfferItem", err))?;
+            let consideration_str = serde_json::to_string(&order_offer.consideration)
+                .map_err(|err| StdError::serialize_err("ConsiderationItem", err))?;
+
+            let hook_msgs = self.hook_msgs(
+                deps.storage,
+                HookExecuteMsg::OfferHook(OfferHookMsg {
+                    action: HookAction::Created,
+                    order_id,
+                    offerer: order_offer.offerer.clone(),
+                    contract_address: contract_address.clone(),
+                    token_id: token_id.clone(),
+                    price: PaymentAsset::try_from(order_offer.offer[0].item.clone())?,
+                }),
+            )?;
+
+            let res = Response::new()
+                .add_submessages(hook_msgs)
+                .add_attribute("method", "create_offer")
+                .add_attribute("order_type", "OFFER")
+                .add_attribute("order_id", order_id.to_string())
+                .add_attribute("offerer", order_offer.offerer.clone())
+                .add_attribute("offer", offer_str)
+                .add_attribute("consideration", consideration_str)
+                .add_attribute("end_time", end_time.to_string());
+
+            // the offer is accepted immediately if it meets the auto accept price of the owner
+            // and the owner still approves this contract to manage the nft
+            let auto_accept_price =
+                self.auto_accept_price(deps.storage, &nft_owner, &contract_address, &token_id)?;
+            if let Some(auto_accept_price) = auto_accept_price {
+                if Uint128::from(amount) >= auto_accept_price
+                    && check_listing_approval(
+                        deps.as_ref(),
+                        &env,
+                        &nft_owner,
+                        &contract_address,
+                        &token_id,
+                    )
+                    .is_ok()
+                {
+                    let accept_res = self.execute_accept_nft_offer(
+                        deps,
+                        env,
+                        MessageInfo {
+                            sender: nft_owner,
+                            funds: vec![],
+                        },
+                        order_id,
+                        amount,
+                        None,
+                    )?;
+                    return Ok(res
+                        .add_submessages(accept_res.messages)
+                        .add_attribute("auto_accepted", "true"));
+                }
+            }
+
+            // return success
+            Ok(res)
+        } else {
+            // if the token_id is not exist, then this order is offer for a collection of nft
+            // we will handle this in the next version => return error for now
+            Err(ContractError::CollectionOfferNotSupported {})
+        }
+    }
+
+    // grant a delegate the permission to execute some actions on behalf of the sender for a collection
+    // the delegation replaces the previous one of the same delegate and collection
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_grant_permission(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        delegate: Addr,
+        contract_address: Addr,
+        actions: Vec<DelegatedAction>,
+        spending_cap: Option<Uint128>,
+        expires: Cw721Expiration,
+    ) -> Result<Response, ContractError> {
+        if delegate == info.sender || actions.is_empty() {
+            return Err(ContractError::InvalidDelegation {});
+        }
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::InvalidEndTime {});
+        }
+
+        let delegation = Delegation {
+            owner: info.sender.clone(),
+            delegate: delegate.clone(),
+            contract_address: contract_address.clone(),
+            actions,
+            spending_cap,
+            spent: Uint128::zero(),
+            expires,
+        };
+        self.delegations.save(
+            deps.storage,
+            (
+                info.sender.clone(),
+                delegate.clone(),
+                contract_address.clone(),
+            ),
+            &delegation,
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "grant_permission")
+            .add_attribute("owner", info.sender)
+            .add_attribute("delegate", delegate)
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("expires", expires.to_string()))
+    }
+
+    pub fn execute_revoke_permission(
+        self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        delegate: Addr,
+        contract_address: Addr,
+    ) -> Result<Response, ContractError> {
+        self.delegations.remove(
+            deps.storage,
+            (
+                info.sender.clone(),
+                delegate.clone(),
+                contract_address.clone(),
+            ),
+        );
+
+        Ok(Response::new()
+            .add_attribute("method", "revoke_permission")
+            .add_attribute("owner", info.sender)
+            .add_attribute("delegate", delegate)
+            .add_attribute("contract_address", contract_address))
+    }
+
+    // check that the delegate can execute the action on behalf of the owner
+    // the amount is added to the spent amount of the delegation
+    fn use_delegation(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        key: DelegationKey,
+        action: DelegatedAction,
+        amount: Uint128,
+    ) -> Result<(), ContractError> {
+        let mut delegation = match self.delegations.may_load(storage, key.clone())? {
+            Some(delegation)
+                if !delegation.expires.is_expired(&env.block)
+                    && delegation.actions.contains(&action) =>
+            {
+                delegation
+            }
+            _ => return Err(ContractError::Unauthorized {}),
+        };
+
+        if amount.is_zero() {
+            return Ok(());
+        }
+        delegation.spent += amount;
+        if let Some(spending_cap) = delegation.spending_cap {
+            if delegation.spent > spending_cap {
+                return Err(ContractError::SpendingCapExceeded { spending_cap });
+            }
+        }
+        self.delegations.save(storage, key, &delegation)?;
+        Ok(())
+    }
+
+    // register the royalties of a collection, replacing the previous ones
+    pub fn execute_set_collection_royalty(
+        self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        recipients: Vec<RoyaltyRecipient>,
+    ) -> Result<Response, ContractError> {
+        check_collection_admin(&deps, &info.sender, &contract_address)?;
+
+        if recipients.is_empty()
+            || recipients.iter().any(|recipient| recipient.bps == 0)
+            || recipients
+                .iter()
+                .map(|recipient| recipient.bps)
+                .sum::<u64>()
+                > 10_000
+        {
+            return Err(ContractError::InvalidRoyaltyRates {});
+        }
+        for recipient in &recipients {
+            deps.api.addr_validate(recipient.address.as_str())?;
+        }
+
+        self.royalty_registry
+            .save(deps.storage, &contract_address, &recipients)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_collection_royalty")
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("recipients", recipients.len().to_string()))
+    }
+
+    pub fn execute_remove_collection_royalty(
+        self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+    ) -> Result<Response, ContractError> {
+        check_collection_admin(&deps, &info.sender, &contract_address)?;
+
+        self.royalty_registry
+            .remove(deps.storage, &contract_address);
+
+        Ok(Response::new()
+            .add_attribute("method", "remove_collection_royalty")
+            .add_attribute("contract_address", contract_address))
+    }
+
+    // set the price from which the offers for a nft, or any nft of a collection if the token_id is None,
+    // are accepted automatically
+    pub fn execute_set_auto_accept(
+        self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: Option<String>,
+        min_price: Uint128,
+    ) -> Result<Response, ContractError> {
+        if min_price.is_zero() {
+            return Err(ContractError::InvalidAmount {});
+        }
+
+        // the sender must own the nft
+        if let Some(token_id) = &token_id {
+            check_nft_owner(&deps, &contract_address, token_id, &info.sender)?;
+        }
+
+        self.auto_accepts.save(
+            deps.storage,
+            (
+                &info.sender,
+                &contract_address,
+                token_id.as_deref().unwrap_or_default(),
+            ),
+            &min_price,
+        )?;
+
+        let mut res = Response::new()
+            .add_attribute("method", "set_auto_accept")
+            .add_attribute("owner", info.sender)
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("min_price", min_price.to_string());
+        if let Some(token_id) = token_id {
+            res = res.add_attribute("token_id", token_id);
+        }
+        Ok(res)
+    }
+
+    pub fn execute_remove_auto_accept(
+        self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: Option<String>,
+    ) -> Result<Response, ContractError> {
+        self.auto_accepts.remove(
+            deps.storage,
+            (
+                &info.sender,
+                &contract_address,
+                token_id.as_deref().unwrap_or_default(),
+            ),
+        );
+
+        let mut res = Response::new()
+            .add_attribute("method", "remove_auto_accept")
+            .add_attribute("owner", info.sender)
+            .add_attribute("contract_address", contract_address);
+        if let Some(token_id) = token_id {
+            res = res.add_attribute("token_id", token_id);
+        }
+        Ok(res)
+    }
+
+    // function to accept offer nft using ordering style
+    pub fn execute_accept_nft_offer(
+        self,
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        order_id: OrderKey,
+        funds_amount: u128,
+        referrer: Option<Addr>,
+    ) -> Result<Response, ContractError> {
+        // get order components
+        let order_components = self.offers.load(deps.storage, order_id)?;
+        let offerer = order_components.offerer.clone();
+
+        // check if the offerer has invalidated the offer
+        if !self.is_order_valid(deps.storage, &order_components)? {
+            return Err(ContractError::OrderInvalidated {});
+        }
+
+        // if the end time of the offer is expired, then return error
+        if order_components.end_time.unwrap().is_expired(&env.block) {
+            return Err(ContractError::OrderExpired {});
+        }
+        match &order_components.consideration[0].item {
+            // match if the consideration item is Nft
+            Asset::Nft(NFT {
+                contract_address,
+                token_id: Some(token_id),
+            }) => {
+                // query the owner of the nft
+                let nft_owner = query_nft_owner(&deps, contract_address, token_id)?;
+
+                // if the nft is not belong to the info.sender or their delegator, then return error
+                if nft_owner != info.sender {
+                    self.use_delegation(
+                        deps.storage,
+                        &env,
+                        (
+                            nft_owner.clone(),
+                            info.sender.clone(),
+                            contract_address.clone(),
+                        ),
+                        DelegatedAction::AcceptOffer,
+                        Uint128::zero(),
+                    )?;
+                }
+
+                // the buyer and the seller cannot refer the sale
+                if referrer.as_ref() == Some(&offerer) || referrer.as_ref() == Some(&nft_owner) {
+                    return Err(ContractError::InvalidReferrer {});
+                }
+
+                let mut res: Response = Response::new();
+                if let Some(referrer) = &referrer {
+                    res = res.add_attribute("referrer", referrer.to_string());
+                }
+
+                // ***********************
+                // TRANSFER CW20 TO SENDER
+                // ***********************
+                // convert Asset to PaymentAsset
+                let payment_item = PaymentAsset::try_from(order_components.offer[0].item.clone())?;
+
+                // execute cw20 transfer msg from offerer to info.sender
+                match &payment_item {
+                    PaymentAsset::Cw20 {
+                        contract_address: _,
+                        amount,
+                    } => {
+                        if funds_amount < *amount {
+                            return Err(ContractError::InsufficientFunds {});
+                        }
+                        let payment_messages = self.payment_with_royalty(
+                            &mut deps,
+                            &env,
+                            contract_address.clone(),
+                            token_id.clone(),
+                            payment_item.clone(),
+                            offerer.clone(),
+                            nft_owner.clone(),
+                            referrer,
+                        )?;
+                        self.accrue_rewards(
+                            deps.storage,
+                            &env,
+                            contract_address,
+                            [&nft_owner, &offerer],
+                            std::slice::from_ref(&payment_item),
+                        )?;
+
+                        // loop through all payment messages and add item to response to execute
+                        for payment_message in payment_messages {
+                            res = res.add_message(payment_message);
+                        }
+                    }
+                    _ => {
+                        return Err(ContractError::OfferTokenTypeInvalid {});
+                    }
+                }
+
+                // ***********************
+                // TRANSFER NFT TO OFFERER
+                // ***********************
+                // message to transfer nft to offerer
+                let transfer_nft_msg = WasmMsg::Execute {
+                    contract_addr: contract_address.clone().to_string(),
+                    msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                        recipient: order_components.offerer.clone().to_string(),
+                        token_id: token_id.clone(),
+                    })?,
+                    funds: vec![],
+                };
+
+                // add transfer nft message to response to execute
+                res = res.add_message(transfer_nft_msg);
+
+                res = res.add_submessages(self.hook_msgs(
+                    deps.storage,
+                    HookExecuteMsg::SaleHook(SaleHookMsg {
+                        contract_address: contract_address.clone(),
+                        token_id: token_id.clone(),
+                        seller: nft_owner.clone(),
+                        buyer: order_components.offerer.clone(),
+                        price: payment_item,
+                    }),
+                )?);
+
+                // After the offer is accepted, we will delete the order
+                self.offers.remove(deps.storage, order_id)?;
+
+                Ok(res
+                    .add_attribute("method", "execute_accept_nft_offer")
+                    .add_attribute("order_id", order_id.to_string())
+                    .add_attribute("owner", nft_owner)
+                    .add_attribute("offerer", order_components.offerer)
+                    .add_attribute("nft_contract_address", contract_address.to_string())
+                    .add_attribute("token_id", token_id.clone()))
+            }
+            // if the consideration item is not Nft, then return error
+            _ => Err(ContractError::ConsiderationNotNft {}),
+        }
+    }
+
+    pub fn execute_cancel_offer(
+        &self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        order_ids: Vec<OrderKey>,
+    ) -> Result<Response, ContractError> {
+        // if the number of offers is greater than 50, then return error
+        if order_ids.len() > 50 {
+            return Err(ContractError::TooManyOrders { max: 50 });
+        }
+
+        // loop through all offers
+        let mut hook_msgs = vec![];
+        for order_id in order_ids {
+            // check if the order exists
+            let order = match self.offers.may_load(deps.storage, order_id)? {
+                Some(order) => order,
+                None => {
+                    return Err(ContractError::OfferNotFound { order_id });
+                }
+            };
+
+            // only the offerer can cancel the offer
+            if order.offerer != info.sender {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            // we will remove the cancelled offer
+            self.offers.remove(deps.storage, order_id)?;
+
+            if let Asset::Nft(NFT {
+                contract_address,
+                token_id: Some(token_id),
+            }) = &order.consideration[0].item
+            {
+                hook_msgs.extend(self.hook_msgs(
+                    deps.storage,
+                    HookExecuteMsg::OfferHook(OfferHookMsg {
+                        action: HookAction::Cancelled,
+                        order_id,
+                        offerer: order.offerer.clone(),
+                        contract_address: contract_address.clone(),
+                        token_id: token_id.clone(),
+                        price: PaymentAsset::try_from(order.offer[0].item.clone())?,
+                    }),
+                )?);
+            }
+        }
+
+        Ok(Response::new()
+            .add_submessages(hook_msgs)
+            .add_attribute("method", "cancel_all_offer")
+            .add_attribute("user", info.sender.to_string())
+            .add_attribute("cancelled_at", env.block.time.to_string()))
+    }
+
+    // function to create an order with arbitrary offer and consideration items
+    // the offered nfts and cw20 tokens stay with the offerer until the order is fulfilled,
+    // the offered native tokens are escrowed in the marketplace
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_create_order(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        offer: Vec<OfferItem>,
+        consideration: Vec<ConsiderationItem>,
+        start_time: Option<Cw721Expiration>,
+        end_time: Option<Cw721Expiration>,
+    ) -> Result<Response, ContractError> {
+        if offer.is_empty() || consideration.is_empty() {
+            return Err(ContractError::OfferEmpty {});
+        }
+
+        // if start_time or end_time is not set, we don't need to check
+        if let (Some(start_time), Some(end_time)) = (start_time, end_time) {
+            if start_time >= end_time {
+                return Err(ContractError::InvalidEndTime {});
+            }
+        }
+        if end_time.is_some() && end_time.unwrap().is_expired(&env.block) {
+            return Err(ContractError::InvalidEndTime {});
+        }
+
+        // an order of the marketplace must trade at least one nft
+        let offer_has_nft = offer.iter().any(|item| matches!(item.item, Asset::Nft(_)));
+        let consideration_has_nft = consideration
+            .iter()
+            .any(|item| matches!(item.item, Asset::Nft(_)));
+        if !offer_has_nft && !consideration_has_nft {
+            return Err(ContractError::OrderWithoutNft {});
+        }
+
+        // the offerer must be able to deliver all offer items
+        for item in &offer {
+            validate_asset(&deps, &item.item_type, &item.item)?;
+            match &item.item {
+                Asset::Nft(NFT {
+                    contract_address,
+                    token_id: Some(token_id),
+                }) => {
+                    check_nft_owner(&deps, contract_address, token_id, &info.sender)?;
+
+                    // check that user approves this contract to manage this token
+                    let approval_response: StdResult<cw721::ApprovalResponse> =
+                        deps.querier.query_wasm_smart(
+                            contract_address,
+                            &Cw721QueryMsg::Approval {
+                                token_id: token_id.clone(),
+                                spender: env.contract.address.to_string(),
+                                include_expired: Some(false),
+                            },
+                        );
+                    if approval_response.is_err() {
+                        return Err(ContractError::Unauthorized {});
+                    }
+                }
+                Asset::Cw20(CW20 {
+                    contract_address,
+                    amount,
+                }) => {
+                    let allowance_response: AllowanceResponse = deps.querier.query_wasm_smart(
+                        contract_address,
+                        &Cw20QueryMsg::Allowance {
+                            owner: info.sender.to_string(),
+                            spender: env.contract.address.to_string(),
+                        },
+                    )?;
+                    if allowance_response.allowance < Uint128::from(*amount) {
+                        return Err(ContractError::InsufficientAllowance {});
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // the native offer items must be escrowed when creating the order
+        if !coins_match(
+            &native_coins(offer.iter().map(|item| &item.item)),
+            &info.funds,
+        ) {
+            return Err(ContractError::InsufficientFunds {});
+        }
+
+        for item in &consideration {
+            validate_asset(&deps, &item.item_type, &item.item)?;
+            deps.api.addr_validate(item.recipient.as_str())?;
+
+            // query the owner of the nft to check if the nft exist
+            if let Asset::Nft(NFT {
+                contract_address,
+                token_id: Some(token_id),
+            }) = &item.item
+            {
+                let owner_response: StdResult<cw721::OwnerOfResponse> =
+                    deps.querier.query_wasm_smart(
+                        contract_address,
+                        &Cw721QueryMsg::OwnerOf {
+                            token_id: token_id.clone(),
+                            include_expired: Some(false),
+                        },
+                    );
+                if owner_response.is_err() {
+                    return Err(ContractError::NftNotFound {});
+                }
+            }
+        }
+
+        // an order offering nfts is a listing, otherwise it is an offer for the nfts in consideration
+        let order_type = if offer_has_nft {
+            OrderType::LISTING
+        } else {
+            OrderType::OFFER
+        };
+
+        let order_id = self.generate_order_id(deps.storage)?;
+        let order = OrderComponents {
+            order_type,
+            order_id,
+            counter: self.user_counter(deps.storage, &info.sender)?,
+            offerer: info.sender,
+            offer,
+            consideration,
+            start_time,
+            end_time,
+        };
+        self.orders.save(deps.storage, order_id, &order)?;
+
+        let offer_str = serde_json::to_string(&order.offer)
+            .map_err(|err| StdError::serialize_err("OfferItem", err))?;
+        let consideration_str = serde_json::to_string(&order.consideration)
+            .map_err(|err| StdError::serialize_err("ConsiderationItem", err))?;
+
+        Ok(Response::new()
+            .add_attribute("method", "create_order")
+            .add_attribute("order_id", order_id.to_string())
+            .add_attribute("offerer", order.offerer)
+            .add_attribute("offer", offer_str)
+            .add_attribute("consideration", consideration_str))
+    }
+
+    // function to fulfill an order, all offer and consideration items are transferred in the same
+    // transaction, so if any of the transfers fails, the whole order fails
+    pub fn execute_fulfill_order(
+        self,
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        order_id: OrderKey,
+    ) -> Result<Response, ContractError> {
+        let order = self.orders.load(deps.storage, order_id)?;
+
+        let res = self.process_fulfill_order(&mut deps, &env, &info, &order)?;
+
+        self.orders.remove(deps.storage, order_id)?;
+
+        Ok(res
+            .add_attribute("method", "fulfill_order")
+            .add_attribute("order_id", order_id.to_string())
+            .add_attribute("offerer", order.offerer)
+            .add_attribute("fulfiller", info.sender))
+    }
+
+    // function to fulfill an order signed off-chain by the offerer
+    // the order is not stored in the marketplace, only its hash is recorded to prevent replay
+    pub fn execute_fulfill_signed_order(
+        self,
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        order: OrderComponents,
+        signature: Binary,
+        public_key: Binary,
+    ) -> Result<Response, ContractError> {
+        let order_hash = order_hash(&env.block.chain_id, &env.contract.address, &order)?;
+
+        // the order cannot be fulfilled after it was filled or cancelled
+        if self.signed_orders.has(deps.storage, &order_hash) {
+            return Err(ContractError::OrderAlreadyUsed {});
+        }
+
+        verify_order_signature(&deps, &order.offerer, &order_hash, &signature, &public_key)?;
+
+        // a signed order must be created with the current counter of the offerer
+        if order.counter != self.user_counter(deps.storage, &order.offerer)? {
+            return Err(ContractError::OrderInvalidated {});
+        }
+
+        // the marketplace cannot escrow native tokens of an order which is not stored on-chain
+        if order
+            .offer
+            .iter()
+            .any(|item| matches!(item.item, Asset::Native(_)))
+        {
+            return Err(ContractError::NativeOfferInSignedOrder {});
+        }
+
+        // an order of the marketplace must trade at least one nft
+        if !order
+            .offer
+            .iter()
+            .map(|item| &item.item)
+            .chain(order.consideration.iter().map(|item| &item.item))
+            .any(|item| matches!(item, Asset::Nft(_)))
+        {
+            return Err(ContractError::OrderWithoutNft {});
+        }
+
+        for item in &order.offer {
+            validate_asset(&deps, &item.item_type, &item.item)?;
+        }
+        for item in &order.consideration {
+            validate_asset(&deps, &item.item_type, &item.item)?;
+            deps.api.addr_validate(item.recipient.as_str())?;
+        }
+
+        let res = self.process_fulfill_order(&mut deps, &env, &info, &order)?;
+
+        self.signed_orders
+            .save(deps.storage, &order_hash, &SignedOrderStatus::Filled {})?;
+
+        Ok(res
+            .add_attribute("method", "fulfill_signed_order")
+            .add_attribute("order_hash", Binary::from(order_hash).to_base64())
+            .add_attribute("offerer", order.offerer)
+            .add_attribute("fulfiller", info.sender))
+    }
+
+    // function to cancel orders signed off-chain, only the offerer can cancel them
+    pub fn execute_cancel_signed_orders(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        orders: Vec<OrderComponents>,
+    ) -> Result<Response, ContractError> {
+        // if the number of orders is greater than 50, then return error
+        if orders.len() > 50 {
+            return Err(ContractError::TooManyOrders { max: 50 });
+        }
+
+        let mut res = Response::new();
+        for order in orders {
+            if order.offerer != info.sender {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            let order_hash = order_hash(&env.block.chain_id, &env.contract.address, &order)?;
+            if let Some(SignedOrderStatus::Filled {}) =
+                self.signed_orders.may_load(deps.storage, &order_hash)?
+            {
+                return Err(ContractError::OrderAlreadyUsed {});
+            }
+            self.signed_orders
+                .save(deps.storage, &order_hash, &SignedOrderStatus::Cancelled {})?;
+            res = res.add_attribute("order_hash", Binary::from(order_hash).to_base64());
+        }
+
+        Ok(res
+            .add_attribute("method", "cancel_signed_orders")
+            .add_attribute("offerer", info.sender)
+            .add_attribute("cancelled_at", env.block.time.to_string()))
+    }
+
+    // function to cancel an order, the escrowed native tokens are refunded to the offerer
+    pub fn execute_cancel_order(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        order_id: OrderKey,
+    ) -> Result<Response, ContractError> {
+        let order = self.orders.load(deps.storage, order_id)?;
+
+        // only the offerer can cancel the order
+        if order.offerer != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        self.orders.remove(deps.storage, order_id)?;
+
+        let mut res = Response::new();
+        for coin in native_coins(order.offer.iter().map(|item| &item.item)) {
+            res = res.add_messages(self.send_native(deps.storage, &order.offerer, coin)?);
+        }
+
+        Ok(res
+            .add_attribute("method", "cancel_order")
+            .add_attribute("order_id", order_id.to_string())
+            .add_attribute("cancelled_at", env.block.time.to_string()))
+    }
+
+    // function to increase the counter of the sender, which invalidates all listings, offers and orders
+    // the sender created before, including the orders signed off-chain
+    pub fn execute_increment_counter(
+        self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let counter = self.user_counter(deps.storage, &info.sender)? + 1;
+        self.counters.save(deps.storage, &info.sender, &counter)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "increment_counter")
+            .add_attribute("user", info.sender)
+            .add_attribute("counter", counter.to_string()))
+    }
+
+    // check an order can be fulfilled by the sender and create the messages to transfer all its items
+    fn process_fulfill_order(
+        &self,
+        deps: &mut DepsMut,
+        env: &Env,
+        info: &MessageInfo,
+        order: &OrderComponents,
+    ) -> Result<Response, ContractError> {
+        if order.offerer == info.sender {
+            return Err(ContractError::CannotFulfillOwnOrder {});
+        }
+
+        // check if the offerer has invalidated the order
+        if !self.is_order_valid(deps.storage, order)? {
+            return Err(ContractError::OrderInvalidated {});
+        }
+
+        // check if current block is after start_time
+        if order.start_time.is_some() && !order.start_time.unwrap().is_expired(&env.block) {
+            return Err(ContractError::OrderNotStarted {});
+        }
+
+        if order.end_time.is_some() && order.end_time.unwrap().is_expired(&env.block) {
+            return Err(ContractError::OrderExpired {});
+        }
+
+        // the native consideration items must be paid with the funds of the message
+        if !coins_match(
+            &native_coins(order.consideration.iter().map(|item| &item.item)),
+            &info.funds,
+        ) {
+            return Err(ContractError::InsufficientFunds {});
+        }
+
+        let mut res = Response::new();
+
+        // the fungible items of each side pay for the nfts of the other side, with their royalties
+        let offer_nfts = order_nfts(order.offer.iter().map(|item| &item.item));
+        let consideration_nfts = order_nfts(order.consideration.iter().map(|item| &item.item));
+
+        // transfer consideration items from fulfiller to their recipients
+        let consideration_payments = with_order_royalties(
+            deps.as_ref(),
+            order
+                .consideration
+                .iter()
+                .map(|item| (item.item.clone(), item.recipient.clone())),
+            &offer_nfts,
+            &order.offerer,
+        )?;
+        for (asset, recipient) in consideration_payments {
+            res = res.add_message(transfer_asset_msg(deps, &asset, &info.sender, &recipient)?);
+        }
+
+        // transfer offer items from offerer to fulfiller
+        let offer_payments = with_order_royalties(
+            deps.as_ref(),
+            order
+                .offer
+                .iter()
+                .map(|item| (item.item.clone(), info.sender.clone())),
+            &consideration_nfts,
+            &info.sender,
+        )?;
+        for (asset, recipient) in offer_payments {
+            res = res.add_message(transfer_asset_msg(
+                deps,
+                &asset,
+                &order.offerer,
+                &recipient,
+            )?);
+        }
+
+        // the traders get points for the fungible assets exchanged for the nfts of the order
+        let items = order
+            .offer
+            .iter()
+            .map(|item| &item.item)
+            .chain(order.consideration.iter().map(|item| &item.item));
+        let mut collection = None;
+        let mut payments = vec![];
+        for asset in items {
+            match asset {
+                Asset::Nft(nft) => {
+                    collection.get_or_insert(nft.contract_address.clone());
+                }
+                _ => payments.push(PaymentAsset::try_from(asset.clone())?),
+            }
+        }
+        if let Some(collection) = collection {
+            self.accrue_rewards(
+                deps.storage,
+                env,
+                &collection,
+                [&order.offerer, &info.sender],
+                &payments,
+            )?;
+        }
+
+        Ok(res)
+    }
+
+    pub fn execute_edit_vaura_token(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        token_address: String,
+    ) -> Result<Response, ContractError> {
+        // get owner
+        let mut conf = self.config.load(deps.storage)?;
+
+        // check if the sender is the owner
+        if conf.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        // update vaura address in config
+        conf.vaura_address = Addr::unchecked(&token_address);
+        // conf.vaura_address = deps.api.addr_validate(&token_address)?;
+
+        // save config
+        self.config.save(deps.storage, &conf)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "edit_vaura_token")
+            .add_attribute("vaura_token_address", token_address))
+    }
+
+    // function to process payment transfer with royalty
+    // enable or disable pull payments, only the owner can do this
+    pub fn execute_edit_pull_payments(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        enabled: bool,
+    ) -> Result<Response, ContractError> {
+        let mut conf = self.config.load(deps.storage)?;
+
+        // check if the sender is the owner
+        if conf.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        conf.pull_payments = enabled;
+        self.config.save(deps.storage, &conf)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "edit_pull_payments")
+            .add_attribute("enabled", enabled.to_string()))
+    }
+
+    // escrow a nft in the marketplace to rent it out, the seller can reclaim it when it is not rented
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_list_for_rent(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+        price_per_day: Coin,
+        max_days: u64,
+    ) -> Result<Response, ContractError> {
+        check_listing_approval(
+            deps.as_ref(),
+            &env,
+            &info.sender,
+            &contract_address,
+            &token_id,
+        )?;
+
+        let auction_config = AuctionConfig::Rental {
+            price_per_day: price_per_day.clone(),
+            max_days,
+        };
+        if !self.validate_auction_config(&auction_config) {
+            return Err(ContractError::InvalidAuctionConfig {});
+        }
+
+        let hook_msgs = self.remove_sales_of_escrowed_nft(
+            deps.storage,
+            &info.sender,
+            &contract_address,
+            &token_id,
+        )?;
+
+        let rental = Listing {
+            contract_address: contract_address.clone(),
+            token_id: token_id.clone(),
+            auction_config,
+            counter: self.user_counter(deps.storage, &info.sender)?,
+            seller: info.sender.clone(),
+            buyer: None,
+            status: ListingStatus::Ongoing {},
+            reserved_for: None,
+        };
+        self.rentals.save(
+            deps.storage,
+            listing_key(&contract_address, &token_id),
+            &rental,
+        )?;
+
+        // the nft is held by the marketplace until it is reclaimed
+        let transfer_nft_msg = WasmMsg::Execute {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                recipient: env.contract.address.to_string(),
+                token_id: token_id.clone(),
+            })?,
+            funds: vec![],
+        };
+
+        Ok(Response::new()
+            .add_message(transfer_nft_msg)
+            .add_submessages(hook_msgs)
+            .add_attribute("method", "list_for_rent")
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("token_id", token_id)
+            .add_attribute("seller", info.sender)
+            .add_attribute("price_per_day", price_per_day.to_string())
+            .add_attribute("max_days", max_days.to_string()))
+    }
+
+    // rent a nft for a number of days, the payment is split like a fixed price sale
+    pub fn execute_rent(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+        days: u64,
+    ) -> Result<Response, ContractError> {
+        let rental_key = listing_key(&contract_address, &token_id);
+        let mut rental = self.rentals.load(deps.storage, rental_key.clone())?;
+
+        // the nft can be rented again once the current rental expires
+        if let ListingStatus::Rented { expires, .. } = rental.status {
+            if expires > env.block.time {
+                return Err(ContractError::NftRented { expires });
+            }
+        }
+        if info.sender == rental.seller {
+            return Err(ContractError::OwnerCannotBuy {});
+        }
+
+        // check if the seller has invalidated the rental
+        if !self.is_listing_valid(deps.storage, &rental)? {
+            return Err(ContractError::OrderInvalidated {});
+        }
+
+        let (price_per_day, max_days) = match &rental.auction_config {
+            AuctionConfig::Rental {
+                price_per_day,
+                max_days,
+            } => (price_per_day, *max_days),
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
+        if days == 0 || days > max_days {
+            return Err(ContractError::InvalidRentalDuration { days, max_days });
+        }
+
+        // check if enough funds
+        let price = Coin {
+            denom: price_per_day.denom.clone(),
+            amount: price_per_day
+                .amount
+                .checked_mul(Uint128::from(days))
+                .map_err(StdError::from)?,
+        };
+        if info.funds.len() != 1 || info.funds[0] != price {
+            return Err(ContractError::InsufficientFunds {});
+        }
+
+        let expires = env.block.time.plus_seconds(days * SECONDS_PER_DAY);
+        rental.buyer = Some(info.sender.clone());
+        rental.status = ListingStatus::Rented {
+            user: info.sender.clone(),
+            expires,
+        };
+        self.rentals.save(deps.storage, rental_key, &rental)?;
+
+        // transfer the royalty to the creator and the remaining funds to the owner of the nft
+        let mut res = Response::new();
+        let config = self.config.load(deps.storage)?;
+        for (_, recipient, coin) in sale_payouts(deps.as_ref(), &config, &rental, &price, None)? {
+            res = res.add_messages(self.send_native(deps.storage, &recipient, coin)?);
+        }
+
+        // the rental is a sale of the use of the nft by its owner to the renter
+        res = res.add_submessages(self.hook_msgs(
+            deps.storage,
+            HookExecuteMsg::SaleHook(SaleHookMsg {
+                contract_address: rental.contract_address.clone(),
+                token_id: rental.token_id.clone(),
+                seller: rental.seller.clone(),
+                buyer: info.sender.clone(),
+                price: PaymentAsset::Native {
+                    denom: price.denom.clone(),
+                    amount: price.amount.u128(),
+                },
+            }),
+        )?);
+
+        Ok(res
+            .add_attribute("method", "rent")
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("token_id", token_id)
+            .add_attribute("user", info.sender)
+            .add_attribute("expires", expires.to_string()))
+    }
+
+    // return an escrowed nft to its owner, it cannot be reclaimed while it is rented
+    pub fn execute_reclaim_rental(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+    ) -> Result<Response, ContractError> {
+        let rental_key = listing_key(&contract_address, &token_id);
+        let rental = self.rentals.load(deps.storage, rental_key.clone())?;
+
+        if info.sender != rental.seller {
+            return Err(ContractError::Unauthorized {});
+        }
+        if let ListingStatus::Rented { expires, .. } = rental.status {
+            if expires > env.block.time {
+                return Err(ContractError::NftRented { expires });
+            }
+        }
+
+        self.rentals.remove(deps.storage, rental_key);
+
+        let transfer_nft_msg = WasmMsg::Execute {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                recipient: rental.seller.to_string(),
+                token_id: token_id.clone(),
+            })?,
+            funds: vec![],
+        };
+
+        Ok(Response::new()
+            .add_message(transfer_nft_msg)
+            .add_attribute("method", "reclaim_rental")
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("token_id", token_id))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_create_group_buy(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        token_id: String,
+        vault: Addr,
+        deadline: Cw721Expiration,
+    ) -> Result<Response, ContractError> {
+        // the contributions must be refundable after the deadline
+        if deadline == (Cw721Expiration::Never {}) || deadline.is_expired(&env.block) {
+            return Err(ContractError::InvalidEndTime {});
+        }
+
+        let listing = self
+            .listings
+            .load(deps.storage, listing_key(&contract_address, &token_id))?
+            .with_active_price(&env.block);
+        let price = match &listing.auction_config {
+            AuctionConfig::FixedPrice { price, .. } => price.clone(),
+            _ => return Err(ContractError::InvalidAuctionConfig {}),
+        };
+
+        let group_buy = GroupBuy {
+            group_buy_id: self.generate_order_id(deps.storage)?,
+            creator: info.sender.clone(),
+            contract_address,
+            token_id,
+            price,
+            vault,
+            deadline,
+            raised: Uint128::zero(),
+            status: GroupBuyStatus::Open {},
+        };
+        if self
+            .group_buy_listing(deps.as_ref(), &env, &group_buy)?
+            .is_none()
+        {
+            return Err(ContractError::ListingNotActive {});
+        }
+        self.group_buys
+            .save(deps.storage, group_buy.group_buy_id, &group_buy)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "create_group_buy")
+            .add_attribute("group_buy_id", group_buy.group_buy_id.to_string())
+            .add_attribute("contract_address", group_buy.contract_address)
+            .add_attribute("token_id", group_buy.token_id)
+            .add_attribute("vault", group_buy.vault)
+            .add_attribute("price", group_buy.price.to_string()))
+    }
+
+    // the contribution which completes the price buys the listing for the vault
+    pub fn execute_contribute_group_buy(
+        self,
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        group_buy_id: GroupBuyKey,
+    ) -> Result<Response, ContractError> {
+        let mut group_buy = self.group_buys.load(deps.storage, group_buy_id)?;
+        if group_buy.status != (GroupBuyStatus::Open {}) {
+            return Err(ContractError::GroupBuyNotOpen {});
+        }
+        if group_buy.deadline.is_expired(&env.block) {
+            return Err(ContractError::GroupBuyEnded {
+                deadline: group_buy.deadline,
+            });
+        }
+
+        // only the denom of the price is accepted
+        if info.funds.len() != 1
+            || info.funds[0].denom != group_buy.price.denom
+            || info.funds[0].amount.is_zero()
+        {
+            return Err(ContractError::InsufficientFunds {});
+        }
+        let mut listing = self
+            .group_buy_listing(deps.as_ref(), &env, &group_buy)?
+            .ok_or(ContractError::ListingNotActive {})?;
+
+        // the contribution above the remaining price is refunded
+        let amount = info.funds[0]
+            .amount
+            .min(group_buy.price.amount - group_buy.raised);
+        let refund = info.funds[0].amount - amount;
+        self.group_buy_contributions.update(
+            deps.storage,
+            (group_buy_id, &info.sender),
+            |contribution| -> StdResult<_> { Ok(contribution.unwrap_or_default() + amount) },
+        )?;
+        group_buy.raised += amount;
+
+        let mut res = Response::new().add_messages(self.send_native(
+            deps.storage,
+            &info.sender,
+            Coin {
+                denom: group_buy.price.denom.clone(),
+                amount: refund,
+            },
+        )?);
+
+        if group_buy.raised == group_buy.price.amount {
+            group_buy.status = GroupBuyStatus::Completed {};
+
+            listing.buyer = Some(group_buy.vault.clone());
+            self.listings.remove(
+                deps.storage,
+                listing_key(&listing.contract_address, &listing.token_id),
+            )?;
+
+            let buy_res = self.process_buy_fixed_price(
+                deps.branch(),
+                env,
+                MessageInfo {
+                    sender: group_buy.vault.clone(),
+                    funds: vec![group_buy.price.clone()],
+                },
+                &listing,
+                None,
+            )?;
+            res = res
+                .add_submessages(buy_res.messages)
+                .add_attribute("buyer", group_buy.vault.to_string());
+        }
+        self.group_buys
+            .save(deps.storage, group_buy_id, &group_buy)?;
+
+        Ok(res
+            .add_attribute("method", "contribute_group_buy")
+            .add_attribute("group_buy_id", group_buy_id.to_string())
+            .add_attribute("contributor", info.sender)
+            .add_attribute("amount", amount.to_string())
+            .add_attribute("raised", group_buy.raised.to_string()))
+    }
+
+    // anyone can refund the contributors once the group buy cannot succeed
+    pub fn execute_refund_group_buy(
+        self,
+        deps: DepsMut,
+        env: Env,
+        _info: MessageInfo,
+        group_buy_id: GroupBuyKey,
+    ) -> Result<Response, ContractError> {
+        let mut group_buy = self.group_buys.load(deps.storage, group_buy_id)?;
+        if group_buy.status != (GroupBuyStatus::Open {}) {
+            return Err(ContractError::GroupBuyNotOpen {});
+        }
+        if !group_buy.deadline.is_expired(&env.block)
+            && self
+                .group_buy_listing(deps.as_ref(), &env, &group_buy)?
+                .is_some()
+        {
+            return Err(ContractError::GroupBuyNotRefundable {});
+        }
+
+        group_buy.status = GroupBuyStatus::Refunded {};
+        self.group_buys
+            .save(deps.storage, group_buy_id, &group_buy)?;
+
+        let contributions = self
+            .group_buy_contributions
+            .prefix(group_buy_id)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        let mut res = Response::new();
+        for (contributor, amount) in contributions {
+            res = res.add_messages(self.send_native(
+                deps.storage,
+                &contributor,
+                Coin {
+                    denom: group_buy.price.denom.clone(),
+                    amount,
+                },
+            )?);
+        }
+
+        Ok(res
+            .add_attribute("method", "refund_group_buy")
+            .add_attribute("group_buy_id", group_buy_id.to_string())
+            .add_attribute("refunded", group_buy.raised.to_string()))
+    }
+
+    // the listing of a group buy, if the vault can still buy it at the price of the group buy
+    // a scheduled price change makes the listing unavailable to the group buy
+    fn group_buy_listing(
+        &self,
+        deps: Deps,
+        env: &Env,
+        group_buy: &GroupBuy,
+    ) -> Result<Option<Listing>, ContractError> {
+        let listing = match self.listings.may_load(
+            deps.storage,
+            listing_key(&group_buy.contract_address, &group_buy.token_id),
+        )? {
+            Some(listing) => listing.with_active_price(&env.block),
+            None => return Ok(None),
+        };
+        let buyable = match &listing.auction_config {
+            AuctionConfig::FixedPrice {
+                price,
+                start_time,
+                end_time,
+                ..
+            } => {
+                *price == group_buy.price
+                    && check_fixed_price_period(start_time, end_time, env).is_ok()
+            }
+            _ => false,
+        };
+        if !buyable
+            || !listing.is_active()
+            || listing.seller == group_buy.vault
+            || (listing.is_private() && listing.reserved_for.as_ref() != Some(&group_buy.vault))
+            || !self.is_listing_valid(deps.storage, &listing)?
+            || check_listing_approval(
+                deps,
+                env,
+                &listing.seller,
+                &listing.contract_address,
+                &listing.token_id,
+            )
+            .is_err()
+        {
+            return Ok(None);
+        }
+        Ok(Some(listing))
+    }
+
+    // escrow a nft and request a loan, the request is an order offering the nft
+    // in exchange for the principal, which must be repaid with the interest
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_request_loan(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        nft: NFT,
+        principal: PaymentAsset,
+        repayment: u128,
+        duration: u64,
+    ) -> Result<Response, ContractError> {
+        let token_id = nft
+            .token_id
+            .clone()
+            .ok_or(ContractError::InvalidLoanTerms {})?;
+        let contract_address = deps.api.addr_validate(nft.contract_address.as_str())?;
+        check_listing_approval(
+            deps.as_ref(),
+            &env,
+            &info.sender,
+            &contract_address,
+            &token_id,
+        )?;
+
+        let (principal, principal_amount) = match principal {
+            PaymentAsset::Native { denom, amount } => {
+                (Asset::Native(NATIVE { denom, amount }), amount)
+            }
+            PaymentAsset::Cw20 {
+                contract_address,
+                amount,
+            } => (
+                Asset::Cw20(CW20 {
+                    contract_address: deps.api.addr_validate(contract_address.as_str())?,
+                    amount,
+                }),
+                amount,
+            ),
+        };
+        if principal_amount == 0 || repayment < principal_amount || duration == 0 {
+            return Err(ContractError::InvalidLoanTerms {});
+        }
+
+        let hook_msgs = self.remove_sales_of_escrowed_nft(
+            deps.storage,
+            &info.sender,
+            &contract_address,
+            &token_id,
+        )?;
+
+        let loan_id = self.generate_order_id(deps.storage)?;
+        let nft = Asset::Nft(NFT {
+            contract_address: contract_address.clone(),
+            token_id: Some(token_id.clone()),
+        });
+        let loan = Loan {
+            order: OrderComponents {
+                order_type: OrderType::LISTING,
+                order_id: loan_id,
+                offerer: info.sender.clone(),
+                offer: vec![offer_item(&ItemType::CW721, &nft, &1, &1)],
+                consideration: vec![consideration_item(
+                    &principal.item_type(),
+                    &principal,
+                    &principal_amount,
+                    &repayment,
+                    &info.sender,
+                )],
+                start_time: None,
+                end_time: None,
+                counter: self.user_counter(deps.storage, &info.sender)?,
+            },
+            duration,
+            status: LoanStatus::Requested {},
+        };
+        self.loans.save(deps.storage, loan_id, &loan)?;
+
+        // the nft is held by the marketplace until the loan is repaid or foreclosed
+        let transfer_nft_msg = WasmMsg::Execute {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                recipient: env.contract.address.to_string(),
+                token_id: token_id.clone(),
+            })?,
+            funds: vec![],
+        };
+
+        Ok(Response::new()
+            .add_message(transfer_nft_msg)
+            .add_submessages(hook_msgs)
+            .add_attribute("method", "request_loan")
+            .add_attribute("loan_id", loan_id.to_string())
+            .add_attribute("borrower", info.sender)
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("token_id", token_id))
+    }
+
+    pub fn execute_cancel_loan_request(
+        self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        loan_id: OrderKey,
+    ) -> Result<Response, ContractError> {
+        let loan = self.loans.load(deps.storage, loan_id)?;
+        if loan.order.offerer != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        if loan.lender().is_some() {
+            return Err(ContractError::LoanAlreadyFunded {});
+        }
+
+        self.loans.remove(deps.storage, loan_id)?;
+
+        Ok(Response::new()
+            .add_messages(return_collateral_msg(&loan, &loan.order.offerer)?)
+            .add_attribute("method", "cancel_loan_request")
+            .add_attribute("loan_id", loan_id.to_string()))
+    }
+
+    // the lender pays the principal to the borrower, the loan is due after its duration
+    pub fn execute_fund_loan(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        loan_id: OrderKey,
+    ) -> Result<Response, ContractError> {
+        let mut loan = self.loans.load(deps.storage, loan_id)?;
+        if loan.lender().is_some() {
+            return Err(ContractError::LoanAlreadyFunded {});
+        }
+        if loan.order.offerer == info.sender {
+            return Err(ContractError::CannotFulfillOwnOrder {});
+        }
+
+        let principal = &loan.order.consideration[0];
+        let payment_msgs = self.loan_payment_msgs(
+            deps.storage,
+            &info,
+            &principal.item,
+            principal.start_amount,
+            &principal.recipient,
+        )?;
+
+        let due = env.block.time.plus_seconds(loan.duration);
+        loan.status = LoanStatus::Active {
+            lender: info.sender.clone(),
+            due,
+        };
+        self.loans.save(deps.storage, loan_id, &loan)?;
+
+        Ok(Response::new()
+            .add_messages(payment_msgs)
+            .add_attribute("method", "fund_loan")
+            .add_attribute("loan_id", loan_id.to_string())
+            .add_attribute("lender", info.sender)
+            .add_attribute("due", due.to_string()))
+    }
+
+    // the borrower pays the repayment to the lender and gets the nft back
+    pub fn execute_repay_loan(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        loan_id: OrderKey,
+    ) -> Result<Response, ContractError> {
+        let loan = self.loans.load(deps.storage, loan_id)?;
+        if loan.order.offerer != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        let (lender, due) = match &loan.status {
+            LoanStatus::Active { lender, due } => (lender, *due),
+            LoanStatus::Requested {} => return Err(ContractError::LoanNotFunded {}),
+        };
+        if env.block.time >= due {
+            return Err(ContractError::LoanOverdue { due });
+        }
+
+        let repayment = &loan.order.consideration[0];
+        let payment_msgs = self.loan_payment_msgs(
+            deps.storage,
+            &info,
+            &repayment.item,
+            repayment.end_amount,
+            lender,
+        )?;
+
+        self.loans.remove(deps.storage, loan_id)?;
+
+        Ok(Response::new()
+            .add_messages(payment_msgs)
+            .add_messages(return_collateral_msg(&loan, &loan.order.offerer)?)
+            .add_attribute("method", "repay_loan")
+            .add_attribute("loan_id", loan_id.to_string()))
+    }
+
+    // the lender takes the nft of a loan which is not repaid at the due time
+    pub fn execute_foreclose_loan(
+        self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        loan_id: OrderKey,
+    ) -> Result<Response, ContractError> {
+        let loan = self.loans.load(deps.storage, loan_id)?;
+        let due = match &loan.status {
+            LoanStatus::Active { lender, due } if *lender == info.sender => *due,
+            LoanStatus::Active { .. } => return Err(ContractError::Unauthorized {}),
+            LoanStatus::Requested {} => return Err(ContractError::LoanNotFunded {}),
+        };
+        if env.block.time < due {
+            return Err(ContractError::LoanNotDue { due });
+        }
+
+        self.loans.remove(deps.storage, loan_id)?;
+
+        // the lender gets the collateral for the principal it funded, the funding itself is not a sale
+        // since the borrower gets the nft back when the loan is repaid
+        let mut hook_msgs = vec![];
+        if let Some((contract_address, token_id)) = loan.order.nft() {
+            let principal = &loan.order.consideration[0];
+            hook_msgs = self.hook_msgs(
+                deps.storage,
+                HookExecuteMsg::SaleHook(SaleHookMsg {
+                    contract_address,
+                    token_id,
+                    seller: loan.order.offerer.clone(),
+                    buyer: info.sender.clone(),
+                    price: PaymentAsset::try_from(with_amount(
+                        &principal.item,
+                        principal.start_amount,
+                    ))?,
+                }),
+            )?;
+        }
+
+        Ok(Response::new()
+            .add_messages(return_collateral_msg(&loan, &info.sender)?)
+            .add_submessages(hook_msgs)
+            .add_attribute("method", "foreclose_loan")
+            .add_attribute("loan_id", loan_id.to_string())
+            .add_attribute("lender", info.sender))
+    }
+
+    // remove the listing and the bundles of a nft which is escrowed in the marketplace,
+    // so that they cannot be bought while the marketplace holds the nft
+    // the asks of the nft cannot be settled anymore, they are dropped by their reverse auctions
+    fn remove_sales_of_escrowed_nft(
+        &self,
+        storage: &mut dyn Storage,
+        owner: &Addr,
+        contract_address: &Addr,
+        token_id: &str,
+    ) -> Result<Vec<SubMsg>, ContractError> {
+        // a sealed bid auction with bids must be settled to release the deposits
+        let listing_key = listing_key(contract_address, &token_id.to_string());
+        if self.has_sealed_bids(storage, &listing_key) {
+            return Err(ContractError::AuctionHasBids {});
+        }
+
+        let mut hook_msgs = vec![];
+        if let Some(listing) = self.listings.may_load(storage, listing_key.clone())? {
+            self.listings.remove(storage, listing_key)?;
+            hook_msgs = self.hook_msgs(
+                storage,
+                HookExecuteMsg::ListingHook(ListingHookMsg {
+                    action: HookAction::Cancelled,
+                    contract_address: listing.contract_address,
+                    token_id: listing.token_id,
+                    seller: listing.seller,
+                }),
+            )?;
+        }
+
+        let nft = NFT {
+            contract_address: contract_address.clone(),
+            token_id: Some(token_id.to_string()),
+        };
+        let bundle_ids = self
+            .bundles
+            .idx
+            .seller
+            .prefix(owner.clone())
+            .range(storage, None, None, Order::Ascending)
+            .filter(|item| !matches!(item, Ok((_, bundle)) if !bundle.nfts.contains(&nft)))
+            .map(|item| item.map(|(bundle_id, _)| bundle_id))
+            .collect::<StdResult<Vec<_>>>()?;
+        for bundle_id in bundle_ids {
+            self.bundles.remove(storage, bundle_id)?;
+        }
+        Ok(hook_msgs)
+    }
+
+    // pay an amount of the asset of a loan from the sender to the recipient,
+    // the native tokens are sent with the message, the cw20 tokens are transferred with an allowance
+    fn loan_payment_msgs(
+        &self,
+        storage: &mut dyn Storage,
+        info: &MessageInfo,
+        asset: &Asset,
+        amount: u128,
+        recipient: &Addr,
+    ) -> Result<Vec<CosmosMsg>, ContractError> {
+        match asset {
+            Asset::Native(NATIVE { denom, .. }) => {
+                let coin = Coin {
+                    denom: denom.clone(),
+                    amount: Uint128::from(amount),
+                };
+                if info.funds.len() != 1 || info.funds[0] != coin {
+                    return Err(ContractError::InsufficientFunds {});
+                }
+                Ok(self.send_native(storage, recipient, coin)?)
+            }
+            Asset::Cw20(CW20 {
+                contract_address, ..
+            }) => Ok(vec![WasmMsg::Execute {
+                contract_addr: contract_address.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                    owner: info.sender.to_string(),
+                    recipient: recipient.to_string(),
+                    amount: Uint128::from(amount),
+                })?,
+                funds: vec![],
+            }
+            .into()]),
+            Asset::Nft(_) => Err(ContractError::NotPaymentAsset {}),
+        }
+    }
+
+    pub fn execute_edit_referral_fee(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        referral_fee_bps: u64,
+    ) -> Result<Response, ContractError> {
+        let mut conf = self.config.load(deps.storage)?;
+
+        // check if the sender is the owner
+        if conf.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        if referral_fee_bps > 10_000 {
+            return Err(ContractError::InvalidReferralFee {});
+        }
+
+        conf.referral_fee_bps = referral_fee_bps;
+        self.config.save(deps.storage, &conf)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "edit_referral_fee")
+            .add_attribute("referral_fee_bps", referral_fee_bps.to_string()))
+    }
+
+    // add a referral fee to the total earnings of the referrer
+    fn add_referral_earnings(
+        &self,
+        storage: &mut dyn Storage,
+        referrer: &Addr,
+        fee: &PaymentAsset,
+    ) -> StdResult<()> {
+        match fee {
+            PaymentAsset::Native { denom, amount } => {
+                self.referral_native.update(
+                    storage,
+                    (referrer, denom),
+                    |earnings| -> StdResult<_> {
+                        Ok(earnings.unwrap_or_default() + Uint128::from(*amount))
+                    },
+                )?;
+            }
+            PaymentAsset::Cw20 {
+                contract_address,
+                amount,
+            } => {
+                self.referral_cw20.update(
+                    storage,
+                    (referrer, contract_address),
+                    |earnings| -> StdResult<_> {
+                        Ok(earnings.unwrap_or_default() + Uint128::from(*amount))
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn execute_add_hook(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+    ) -> Result<Response, ContractError> {
+        // check if the sender is the owner
+        if self.config.load(deps.storage)?.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let mut hooks = self.hooks.may_load(deps.storage)?.unwrap_or_default();
+        if hooks.contains(&contract_address) {
+            return Err(ContractError::AlreadyExists {});
+        }
+        hooks.push(contract_address.clone());
+        self.hooks.save(deps.storage, &hooks)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "add_hook")
+            .add_attribute("contract_address", contract_address))
+    }
+
+    pub fn execute_remove_hook(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+    ) -> Result<Response, ContractError> {
+        // check if the sender is the owner
+        if self.config.load(deps.storage)?.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let mut hooks = self.hooks.may_load(deps.storage)?.unwrap_or_default();
+        if !hooks.contains(&contract_address) {
+            return Err(ContractError::HookNotFound {});
+        }
+        hooks.retain(|hook| *hook != contract_address);
+        self.hooks.save(deps.storage, &hooks)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "remove_hook")
+            .add_attribute("contract_address", contract_address))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_edit_rewards_config(
+        &self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        token: Addr,
+        epoch_duration: u64,
+        rewards_per_epoch: Uint128,
+        distribution: RewardDistribution,
+        eligible_assets: Vec<RewardAsset>,
+    ) -> Result<Response, ContractError> {
+        // check if the sender is the owner
+        if self.config.load(deps.storage)?.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        // the epochs of the existing points must not move
+        let start_time = match self.rewards_config.may_load(deps.storage)? {
+            Some(config) if config.epoch_duration != epoch_duration => {
+                return Err(ContractError::InvalidRewardsConfig {});
+            }
+            Some(config) => config.start_time,
+            None if epoch_duration == 0 => return Err(ContractError::InvalidRewardsConfig {}),
+            None => env.block.time,
+        };
+
+        for (index, eligible) in eligible_assets.iter().enumerate() {
+            if let PaymentAssetInfo::Cw20 { contract_address } = &eligible.asset {
+                deps.api.addr_validate(contract_address.as_str())?;
+            }
+            // an asset is weighted only once
+            if eligible.weight_bps == 0
+                || eligible_assets[..index]
+                    .iter()
+                    .any(|other| other.asset == eligible.asset)
+            {
+                return Err(ContractError::InvalidRewardsConfig {});
+            }
+        }
+
+        self.rewards_config.save(
+            deps.storage,
+            &RewardsConfig {
+                token: token.clone(),
+                start_time,
+                epoch_duration,
+                rewards_per_epoch,
+                distribution,
+                eligible_assets,
+            },
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "edit_rewards_config")
+            .add_attribute("token", token)
+            .add_attribute("rewards_per_epoch", rewards_per_epoch.to_string()))
+    }
+
+    pub fn execute_set_reward_multiplier(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        contract_address: Addr,
+        multiplier: u64,
+    ) -> Result<Response, ContractError> {
+        // check if the sender is the owner
+        if self.config.load(deps.storage)?.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        self.reward_multipliers
+            .save(deps.storage, &contract_address, &multiplier)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_reward_multiplier")
+            .add_attribute("contract_address", contract_address)
+            .add_attribute("multiplier", multiplier.to_string()))
+    }
+
+    // claim the rewards of the sender for an ended epoch
+    pub fn execute_claim_rewards(
+        &self,
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        epoch: u64,
+    ) -> Result<Response, ContractError> {
+        let config = self
+            .rewards_config
+            .may_load(deps.storage)?
+            .ok_or(ContractError::RewardsNotConfigured {})?;
+        if epoch >= config.epoch(env.block.time) {
+            return Err(ContractError::EpochNotEnded { epoch });
+        }
+
+        let amount = self.pending_rewards(deps.storage, &config, &info.sender, epoch)?;
+        if amount.is_zero() {
+            return Err(ContractError::NoRewardsToClaim {});
+        }
+        self.claimed_rewards
+            .save(deps.storage, (&info.sender, epoch), &amount)?;
+
+        let msg = match config.distribution {
+            RewardDistribution::Mint => Cw20ExecuteMsg::Mint {
+                recipient: info.sender.to_string(),
+                amount,
+            },
+            RewardDistribution::Pool => Cw20ExecuteMsg::Transfer {
+                recipient: info.sender.to_string(),
+                amount,
+            },
+        };
+
+        Ok(Response::new()
+            .add_message(WasmMsg::Execute {
+                contract_addr: config.token.to_string(),
+                msg: to_binary(&msg)?,
+                funds: vec![],
+            })
+            .add_attribute("method", "claim_rewards")
+            .add_attribute("user", info.sender)
+            .add_attribute("epoch", epoch.to_string())
+            .add_attribute("amount", amount.to_string()))
+    }
+
+    // add the reward points of a trade to its buyer and its seller
+    // only the payments in eligible assets count, each weighted by its asset
+    fn accrue_rewards(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        contract_address: &Addr,
+        traders: [&Addr; 2],
+        payments: &[PaymentAsset],
+    ) -> StdResult<()> {
+        let config = match self.rewards_config.may_load(storage)? {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        let volume: Uint128 = payments
+            .iter()
+            .map(|payment| config.weighted_volume(payment))
+            .sum();
+        let multiplier = self
+            .reward_multipliers
+            .may_load(storage, contract_address)?
+            .unwrap_or(DEFAULT_REWARD_MULTIPLIER);
+        let points = volume.multiply_ratio(multiplier, DEFAULT_REWARD_MULTIPLIER);
+        if points.is_zero() {
+            return Ok(());
+        }
+
+        // the rewards of an epoch are fixed by its first trade, so editing the config is not retroactive
+        let epoch = config.epoch(env.block.time);
+        if !self.epoch_rewards.has(storage, epoch) {
+            self.epoch_rewards
+                .save(storage, epoch, &config.rewards_per_epoch)?;
+        }
+        for trader in traders {
+            self.user_points
+                .update(storage, (trader, epoch), |user_points| -> StdResult<_> {
+                    Ok(user_points.unwrap_or_default() + points)
+                })?;
+        }
+        self.epoch_points
+            .update(storage, epoch, |epoch_points| -> StdResult<_> {
+                Ok(epoch_points.unwrap_or_default() + points + points)
+            })?;
+        Ok(())
+    }
+
+    // create the submessages which send the message to all hook contracts
+    // the errors of the hooks are handled in reply, so that they do not revert the trade
+    fn hook_msgs(&self, storage: &dyn Storage, msg: HookExecuteMsg) -> StdResult<Vec<SubMsg>> {
+        let msg = to_binary(&msg)?;
+        Ok(self
+            .hooks
+            .may_load(storage)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|hook| {
+                SubMsg::reply_on_error(
+                    WasmMsg::Execute {
+                        contract_addr: hook.to_string(),
+                        msg: msg.clone(),
+                        funds: vec![],
+                    },
+                    HOOK_REPLY_ID,
+                )
+                .with_gas_limit(HOOK_GAS_LIMIT)
+            })
+            .collect())
+    }
+
+    // withdraw all pending balances of the sender
+    // withdraw the pending balance of the sender in a single asset,
+    // so that an asset which cannot be transferred does not block the others
+    pub fn execute_withdraw(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        asset: PaymentAssetInfo,
+    ) -> Result<Response, ContractError> {
+        let res = Response::new()
+            .add_attribute("method", "withdraw")
+            .add_attribute("recipient", info.sender.to_string());
+
+        match asset {
+            PaymentAssetInfo::Native { denom } => {
+                let amount = self
+                    .pending_native
+                    .may_load(deps.storage, (&info.sender, &denom))?
+                    .ok_or(ContractError::NoPendingBalance {})?;
+                self.pending_native
+                    .remove(deps.storage, (&info.sender, &denom));
+                Ok(res
+                    .add_message(BankMsg::Send {
+                        to_address: info.sender.to_string(),
+                        amount: vec![Coin {
+                            denom: denom.clone(),
+                            amount,
+                        }],
+                    })
+                    .add_attribute("denom", denom)
+                    .add_attribute("amount", amount.to_string()))
+            }
+            PaymentAssetInfo::Cw20 { contract_address } => {
+                let amount = self
+                    .pending_cw20
+                    .may_load(deps.storage, (&info.sender, &contract_address))?
+                    .ok_or(ContractError::NoPendingBalance {})?;
+                self.pending_cw20
+                    .remove(deps.storage, (&info.sender, &contract_address));
+                Ok(res
+                    .add_message(WasmMsg::Execute {
+                        contract_addr: contract_address.to_string(),
+                        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                            recipient: info.sender.to_string(),
+                            amount,
+                        })?,
+                        funds: vec![],
+                    })
+                    .add_attribute("token_address", contract_address)
+                    .add_attribute("amount", amount.to_string()))
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn payment_with_royalty(
+        &self,
+        deps: &mut DepsMut,
+        env: &Env,
+        nft_contract_address: Addr,
+        nft_id: String,
+        token: PaymentAsset,
         sender: Addr,
         receipient: Addr,
-    ) -> Vec<CosmosMsg> {
+        referrer: Option<Addr>,
+    ) -> Result<Vec<CosmosMsg>, ContractError> {
         // create empty vector of CosmosMsg
         let mut res_messages: Vec<CosmosMsg> = vec![];
 
@@ -741,122 +3811,790 @@ impl MarketplaceContract<'static> {
         };
 
         // get cw2981 royalties info
-        let royalty_query_msg = Cw2981QueryMsg::Extension {
-            msg: cw2981_royalties::msg::Cw2981QueryMsg::RoyaltyInfo {
-                token_id: nft_id,
-                sale_price: amount,
-            },
-        };
+        let mut payouts = royalty_payouts(
+            deps.as_ref(),
+            &nft_contract_address,
+            &nft_id,
+            amount,
+            &receipient,
+        )?
+        .into_iter()
+        .map(|(_, recipient, amount)| (recipient, amount))
+        .collect::<Vec<_>>();
 
-        let royalty_info_rsp: Result<RoyaltiesInfoResponse, cosmwasm_std::StdError> =
-            deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-                contract_addr: nft_contract_address.to_string(),
-                msg: to_binary(&royalty_query_msg).unwrap(),
-            }));
-
-        let (creator, royalty_amount): (Option<Addr>, Option<Uint128>) = match royalty_info_rsp {
-            Ok(RoyaltiesInfoResponse {
-                address,
-                royalty_amount,
-            }) => {
-                if address.is_empty() || royalty_amount == Uint128::zero() {
-                    (None, None)
-                } else {
-                    (
-                        Some(deps.api.addr_validate(&address).unwrap()),
-                        Some(royalty_amount),
-                    )
+        // the referral fee is carved out of the payout to the recipient
+        let config = self.config.load(deps.storage)?;
+        let referrer = referrer.filter(|_| config.referral_fee_bps > 0);
+        if let (Some(referrer), Some((_, proceeds))) = (referrer, payouts.last_mut()) {
+            let fee = carve_referral_fee(&config, amount, proceeds)?;
+            let earnings = if is_native {
+                PaymentAsset::Native {
+                    denom: token_info.clone(),
+                    amount: fee.u128(),
                 }
-            }
-            Err(_) => (None, None),
-        };
+            } else {
+                PaymentAsset::Cw20 {
+                    contract_address: Addr::unchecked(&token_info),
+                    amount: fee.u128(),
+                }
+            };
+            self.add_referral_earnings(deps.storage, &referrer, &earnings)?;
+            payouts.push((referrer, fee));
+        }
 
-        // there is no royalty, creator is the receipient, or royalty amount is 0
-        if creator.is_none()
-            || *creator.as_ref().unwrap() == receipient
-            || royalty_amount.is_none()
-            || royalty_amount.unwrap().is_zero()
-        {
-            match &is_native {
-                false => {
-                    // execute cw20 transfer msg from info.sender to receipient
-                    let transfer_response = WasmMsg::Execute {
-                        contract_addr: deps.api.addr_validate(&token_info).unwrap().to_string(),
+        if is_native {
+            // transfer the funds held by this contract
+            for (recipient, amount) in payouts {
+                res_messages.extend(self.send_native(
+                    deps.storage,
+                    &recipient,
+                    Coin {
+                        denom: token_info.clone(),
+                        amount,
+                    },
+                )?);
+            }
+        } else {
+            let token_address = deps.api.addr_validate(&token_info)?;
+            if config.pull_payments {
+                // collect the whole amount from the sender, the recipients will withdraw their part
+                res_messages.push(
+                    WasmMsg::Execute {
+                        contract_addr: token_address.to_string(),
                         msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
                             owner: sender.to_string(),
-                            recipient: receipient.to_string(),
+                            recipient: env.contract.address.to_string(),
                             amount,
-                        })
-                        .unwrap(),
+                        })?,
                         funds: vec![],
-                    };
-                    res_messages.push(transfer_response.into());
+                    }
+                    .into(),
+                );
+                for (recipient, amount) in payouts {
+                    self.pending_cw20.update(
+                        deps.storage,
+                        (&recipient, &token_address),
+                        |balance| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
+                    )?;
                 }
-                true => {
-                    // transfer all funds to receipient
-                    let transfer_response = BankMsg::Send {
-                        to_address: receipient.to_string(),
-                        amount: vec![Coin {
-                            denom: token_info,
-                            amount,
-                        }],
-                    };
-                    res_messages.push(transfer_response.into());
+            } else {
+                // execute cw20 transfer msg from sender to each recipient
+                for (recipient, amount) in payouts {
+                    res_messages.push(
+                        WasmMsg::Execute {
+                            contract_addr: token_address.to_string(),
+                            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                                owner: sender.to_string(),
+                                recipient: recipient.to_string(),
+                                amount,
+                            })?,
+                            funds: vec![],
+                        }
+                        .into(),
+                    );
                 }
             }
-        } else if let (Some(creator), Some(royalty_amount)) = (creator, royalty_amount) {
-            match &is_native {
-                false => {
-                    // execute cw20 transfer transfer royalty to creator
-                    let transfer_token_creator_response = WasmMsg::Execute {
-                        contract_addr: deps.api.addr_validate(&token_info).unwrap().to_string(),
-                        msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
-                            owner: sender.to_string(),
-                            recipient: creator.to_string(),
-                            amount: royalty_amount,
-                        })
-                        .unwrap(),
-                        funds: vec![],
-                    };
-                    res_messages.push(transfer_token_creator_response.into());
+        }
 
-                    // execute cw20 transfer remaining funds to receipient
-                    let transfer_token_seller_msg = WasmMsg::Execute {
-                        contract_addr: deps.api.addr_validate(&token_info).unwrap().to_string(),
-                        msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
-                            owner: sender.to_string(),
-                            recipient: receipient.to_string(),
-                            amount: amount - royalty_amount,
-                        })
-                        .unwrap(),
-                        funds: vec![],
-                    };
-                    res_messages.push(transfer_token_seller_msg.into());
-                }
-                true => {
-                    // transfer royalty to creator
-                    let transfer_token_creator_response = BankMsg::Send {
-                        to_address: creator.to_string(),
-                        amount: vec![Coin {
-                            denom: token_info.clone(),
-                            amount: royalty_amount,
-                        }],
-                    };
-                    res_messages.push(transfer_token_creator_response.into());
+        Ok(res_messages)
+    }
 
-                    // transfer remaining funds to receipient
-                    let transfer_token_seller_msg = BankMsg::Send {
-                        to_address: receipient.to_string(),
-                        amount: vec![Coin {
-                            denom: token_info,
-                            amount: amount - royalty_amount,
-                        }],
-                    };
-                    res_messages.push(transfer_token_seller_msg.into());
+    // create the messages to pay the native price of a nft to the seller
+    // the royalty is paid to the creator, unless the creator is the seller
+    fn native_sale_payment_msgs(
+        &self,
+        deps: &mut DepsMut,
+        contract_address: &Addr,
+        token_id: &str,
+        seller: &Addr,
+        price: &Coin,
+    ) -> Result<Vec<CosmosMsg>, ContractError> {
+        let mut msgs = vec![];
+        let mut seller_amount = price.amount;
+        for (creator, royalty_amount) in
+            query_royalty(deps.as_ref(), contract_address, token_id, price.amount)?
+        {
+            if creator != *seller {
+                seller_amount = seller_amount.checked_sub(royalty_amount).map_err(|_| {
+                    ContractError::RoyaltyExceedsPrice {
+                        royalty: royalty_amount,
+                        price: price.amount,
+                    }
+                })?;
+                msgs.extend(self.send_native(
+                    deps.storage,
+                    &creator,
+                    Coin {
+                        denom: price.denom.clone(),
+                        amount: royalty_amount,
+                    },
+                )?);
+            }
+        }
+        msgs.extend(self.send_native(
+            deps.storage,
+            seller,
+            Coin {
+                denom: price.denom.clone(),
+                amount: seller_amount,
+            },
+        )?);
+        Ok(msgs)
+    }
+
+    // create the message to send native tokens held by this contract to a recipient
+    // when pull payments are enabled, the tokens are credited to the pending balance of the recipient
+    // so that a recipient which cannot receive funds does not make the whole transaction revert
+    fn send_native(
+        &self,
+        storage: &mut dyn Storage,
+        recipient: &Addr,
+        coin: Coin,
+    ) -> StdResult<Vec<CosmosMsg>> {
+        if coin.amount.is_zero() {
+            return Ok(vec![]);
+        }
+        if !self.config.load(storage)?.pull_payments {
+            return Ok(vec![BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![coin],
+            }
+            .into()]);
+        }
+        self.credit_native(storage, recipient, &coin)?;
+        Ok(vec![])
+    }
+
+    // credit native tokens to the pending balance of the recipient, to be withdrawn by the recipient
+    fn credit_native(
+        &self,
+        storage: &mut dyn Storage,
+        recipient: &Addr,
+        coin: &Coin,
+    ) -> StdResult<()> {
+        if coin.amount.is_zero() {
+            return Ok(());
+        }
+        self.pending_native.update(
+            storage,
+            (recipient, &coin.denom),
+            |balance| -> StdResult<_> { Ok(balance.unwrap_or_default() + coin.amount) },
+        )?;
+        Ok(())
+    }
+}
+
+// check that the type of an item matches its asset and the asset is well-formed
+fn validate_asset(
+    deps: &DepsMut,
+    item_type: &ItemType,
+    asset: &Asset,
+) -> Result<(), ContractError> {
+    if *item_type != asset.item_type() {
+        return Err(ContractError::ItemTypeMismatch {});
+    }
+    match asset {
+        Asset::Nft(NFT {
+            contract_address,
+            token_id,
+        }) => {
+            deps.api.addr_validate(contract_address.as_str())?;
+            if token_id.is_none() {
+                return Err(ContractError::CollectionItemNotSupported {});
+            }
+        }
+        Asset::Cw20(CW20 {
+            contract_address,
+            amount,
+        }) => {
+            deps.api.addr_validate(contract_address.as_str())?;
+            if *amount == 0 {
+                return Err(ContractError::InvalidAmount {});
+            }
+        }
+        Asset::Native(NATIVE { denom, amount }) => {
+            if denom.is_empty() || *amount == 0 {
+                return Err(ContractError::InvalidAmount {});
+            }
+        }
+    }
+    Ok(())
+}
+
+// check that a time is within the period of a fixed price listing
+// the period must be defined with times, a step cannot be compared with a block height
+fn step_within_period(
+    time: Timestamp,
+    start_time: &Option<Cw721Expiration>,
+    end_time: &Option<Cw721Expiration>,
+) -> bool {
+    let after_start = match start_time {
+        None => true,
+        Some(Cw721Expiration::AtTime(start)) => time >= *start,
+        Some(_) => false,
+    };
+    let before_end = match end_time {
+        None | Some(Cw721Expiration::Never {}) => true,
+        Some(Cw721Expiration::AtTime(end)) => time < *end,
+        Some(_) => false,
+    };
+    after_start && before_end
+}
+
+// check that the owner owns the token and approves this contract to manage it
+// for now, we require never expired approval
+pub(crate) fn check_listing_approval(
+    deps: Deps,
+    env: &Env,
+    owner: &Addr,
+    contract_address: &Addr,
+    token_id: &str,
+) -> Result<(), ContractError> {
+    // check if user is the owner of the token
+    let query_owner_msg = Cw721QueryMsg::OwnerOf {
+        token_id: token_id.to_string(),
+        include_expired: Some(false),
+    };
+    let owner_response: StdResult<cw721::OwnerOfResponse> =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&query_owner_msg)?,
+        }));
+    match owner_response {
+        Ok(res) => {
+            if res.owner != *owner {
+                return Err(ContractError::Unauthorized {});
+            }
+        }
+        Err(_) => {
+            return Err(ContractError::Unauthorized {});
+        }
+    }
+
+    // check that user approves this contract to manage this token
+    // for now, we require never expired approval
+    let query_approval_msg = Cw721QueryMsg::Approval {
+        token_id: token_id.to_string(),
+        spender: env.contract.address.to_string(),
+        include_expired: Some(true),
+    };
+    let approval_response: StdResult<cw721::ApprovalResponse> =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&query_approval_msg)?,
+        }));
+
+    // check if approval is never expired
+    match approval_response {
+        Ok(approval) => match approval.approval.expires {
+            Cw721Expiration::Never {} => {}
+            _ => return Err(ContractError::Unauthorized {}),
+        },
+        Err(_) => {
+            return Err(ContractError::ApprovalMustNeverExpire {});
+        }
+    }
+
+    Ok(())
+}
+
+// the best ask of a reverse auction, if its nft can still be transferred to the buyer
+fn settleable_ask<'a>(deps: Deps, env: &Env, auction: &'a ReverseAuction) -> Option<&'a Ask> {
+    auction.best_ask.as_ref().filter(|ask| {
+        check_listing_approval(
+            deps,
+            env,
+            &ask.seller,
+            &auction.nft.contract_address,
+            &ask.token_id,
+        )
+        .is_ok()
+    })
+}
+
+// query the cw2981 royalty of a nft for a sale price
+// the royalties registered in the marketplace are used if the contract does not support cw2981
+// returns no royalty if there is no royalty to pay
+pub(crate) fn query_royalty(
+    deps: Deps,
+    contract_address: &Addr,
+    token_id: &str,
+    sale_price: Uint128,
+) -> Result<Vec<(Addr, Uint128)>, ContractError> {
+    let royalty_query_msg = Cw2981QueryMsg::Extension {
+        msg: cw2981_royalties::msg::Cw2981QueryMsg::RoyaltyInfo {
+            token_id: token_id.to_string(),
+            sale_price,
+        },
+    };
+    let royalty_info_rsp: StdResult<RoyaltiesInfoResponse> =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&royalty_query_msg)?,
+        }));
+
+    match royalty_info_rsp {
+        Ok(RoyaltiesInfoResponse {
+            address,
+            royalty_amount,
+        }) => {
+            // an invalid royalty address is handled as no royalty, so the nft can still be traded
+            if royalty_amount.is_zero() {
+                return Ok(vec![]);
+            }
+            Ok(deps
+                .api
+                .addr_validate(&address)
+                .map(|address| vec![(address, royalty_amount)])
+                .unwrap_or_default())
+        }
+        Err(_) => Ok(contract()
+            .royalty_registry
+            .may_load(deps.storage, contract_address)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|recipient| {
+                (
+                    recipient.address,
+                    sale_price.multiply_ratio(recipient.bps, 10_000u64),
+                )
+            })
+            .filter(|(_, royalty_amount)| !royalty_amount.is_zero())
+            .collect()),
+    }
+}
+
+// check that a fixed price sale is open at the current block
+pub(crate) fn check_fixed_price_period(
+    start_time: &Option<Cw721Expiration>,
+    end_time: &Option<Cw721Expiration>,
+    env: &Env,
+) -> Result<(), ContractError> {
+    // check if current block is after start_time
+    if start_time.is_some() && !start_time.unwrap().is_expired(&env.block) {
+        return Err(ContractError::AuctionNotStarted {
+            start_time: start_time.unwrap(),
+        });
+    }
+
+    if end_time.is_some() && end_time.unwrap().is_expired(&env.block) {
+        return Err(ContractError::AuctionEnded {
+            end_time: end_time.unwrap(),
+            block_time: env.block.time,
+        });
+    }
+    Ok(())
+}
+
+// check that the buyer can buy the listing, referred by the referrer
+pub(crate) fn check_buyer(
+    listing: &Listing,
+    buyer: &Addr,
+    referrer: Option<&Addr>,
+) -> Result<(), ContractError> {
+    // check if buyer is the same as seller
+    if *buyer == listing.seller {
+        return Err(ContractError::OwnerCannotBuy {});
+    }
+
+    // the buyer and the seller cannot refer the purchase
+    if referrer == Some(buyer) || referrer == Some(&listing.seller) {
+        return Err(ContractError::InvalidReferrer {});
+    }
+
+    // a private listing can only be bought by the reserved buyer
+    if listing.reserved_for.is_some() && listing.reserved_for.as_ref() != Some(buyer) {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+// compute the payouts of a listing sold at a fixed price, the referral fee of a referred purchase
+// is carved out of the proceeds of the seller
+pub(crate) fn sale_payouts(
+    deps: Deps,
+    config: &Config,
+    listing: &Listing,
+    price: &Coin,
+    referrer: Option<&Addr>,
+) -> Result<Vec<(TransferKind, Addr, Coin)>, ContractError> {
+    nft_sale_payouts(
+        deps,
+        config,
+        &listing.contract_address,
+        &listing.token_id,
+        &listing.seller,
+        price,
+        referrer,
+    )
+}
+
+// compute the payouts of a nft sold by the seller at a price
+// the royalty is paid to the creator, unless the creator is the seller, and the remaining funds to the seller,
+// the referral fee of a referred purchase is carved out of the proceeds of the seller
+pub(crate) fn nft_sale_payouts(
+    deps: Deps,
+    config: &Config,
+    contract_address: &Addr,
+    token_id: &str,
+    seller: &Addr,
+    price: &Coin,
+    referrer: Option<&Addr>,
+) -> Result<Vec<(TransferKind, Addr, Coin)>, ContractError> {
+    let coin = |amount| Coin {
+        denom: price.denom.clone(),
+        amount,
+    };
+    let mut payouts: Vec<_> =
+        royalty_payouts(deps, contract_address, token_id, price.amount, seller)?
+            .into_iter()
+            .map(|(kind, recipient, amount)| (kind, recipient, coin(amount)))
+            .collect();
+    let referrer = referrer.filter(|_| config.referral_fee_bps > 0);
+    if let (Some(referrer), Some((_, _, proceeds))) = (referrer, payouts.last_mut()) {
+        let fee = carve_referral_fee(config, price.amount, &mut proceeds.amount)?;
+        payouts.push((TransferKind::ReferralFee, referrer.clone(), coin(fee)));
+    }
+    Ok(payouts)
+}
+
+// split the price of a bundle between its collections in proportion to their number of nfts,
+// then the share of each collection equally between its nfts, the rounding remainders go to the last ones
+fn bundle_item_prices(nfts: &[NFT], price: Uint128) -> Vec<Uint128> {
+    let mut collections: BTreeMap<&Addr, Vec<usize>> = BTreeMap::new();
+    for (index, nft) in nfts.iter().enumerate() {
+        collections
+            .entry(&nft.contract_address)
+            .or_default()
+            .push(index);
+    }
+
+    let mut item_prices = vec![Uint128::zero(); nfts.len()];
+    let mut counted_nfts = 0u128;
+    let mut allocated = Uint128::zero();
+    for indexes in collections.values() {
+        counted_nfts += indexes.len() as u128;
+        let collection_end = price.multiply_ratio(counted_nfts, nfts.len() as u128);
+        let share = collection_end - allocated;
+        allocated = collection_end;
+
+        let count = indexes.len() as u128;
+        for (position, index) in indexes.iter().enumerate() {
+            let position = position as u128;
+            item_prices[*index] =
+                share.multiply_ratio(position + 1, count) - share.multiply_ratio(position, count);
+        }
+    }
+    item_prices
+}
+
+// compute the payouts of a nft sold for an amount of tokens
+// the royalty is paid to the creator, unless the creator is the recipient
+pub(crate) fn royalty_payouts(
+    deps: Deps,
+    contract_address: &Addr,
+    token_id: &str,
+    amount: Uint128,
+    recipient: &Addr,
+) -> Result<Vec<(TransferKind, Addr, Uint128)>, ContractError> {
+    let mut payouts = vec![];
+    let mut remaining_amount = amount;
+    for (creator, royalty_amount) in query_royalty(deps, contract_address, token_id, amount)? {
+        if creator == *recipient {
+            continue;
+        }
+        remaining_amount = remaining_amount.checked_sub(royalty_amount).map_err(|_| {
+            ContractError::RoyaltyExceedsPrice {
+                royalty: royalty_amount,
+                price: amount,
+            }
+        })?;
+        payouts.push((TransferKind::Royalty, creator, royalty_amount));
+    }
+    payouts.push((
+        TransferKind::SellerProceeds,
+        recipient.clone(),
+        remaining_amount,
+    ));
+    Ok(payouts)
+}
+
+// query the owner of a nft
+fn query_nft_owner(
+    deps: &DepsMut,
+    contract_address: &Addr,
+    token_id: &str,
+) -> Result<Addr, ContractError> {
+    let owner_response: StdResult<cw721::OwnerOfResponse> = deps.querier.query_wasm_smart(
+        contract_address,
+        &Cw721QueryMsg::OwnerOf {
+            token_id: token_id.to_string(),
+            include_expired: Some(false),
+        },
+    );
+    match owner_response {
+        Ok(owner_response) => Ok(Addr::unchecked(owner_response.owner)),
+        Err(_) => Err(ContractError::NftNotFound {}),
+    }
+}
+
+// transfer the escrowed nft of a loan to the recipient
+fn return_collateral_msg(loan: &Loan, recipient: &Addr) -> StdResult<Vec<CosmosMsg>> {
+    loan.order
+        .nft()
+        .map(|(contract_address, token_id)| -> StdResult<CosmosMsg> {
+            Ok(WasmMsg::Execute {
+                contract_addr: contract_address.to_string(),
+                msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                    recipient: recipient.to_string(),
+                    token_id,
+                })?,
+                funds: vec![],
+            }
+            .into())
+        })
+        .into_iter()
+        .collect()
+}
+
+// compute the referral fee of a sale price and remove it from the proceeds it is carved out of
+fn carve_referral_fee(
+    config: &Config,
+    price: Uint128,
+    proceeds: &mut Uint128,
+) -> Result<Uint128, ContractError> {
+    let fee = price.multiply_ratio(config.referral_fee_bps, 10_000u64);
+    *proceeds =
+        proceeds
+            .checked_sub(fee)
+            .map_err(|_| ContractError::ReferralFeeExceedsProceeds {
+                fee,
+                proceeds: *proceeds,
+            })?;
+    Ok(fee)
+}
+
+// check that the sender is the owner of the marketplace or the minter of the collection
+fn check_collection_admin(
+    deps: &DepsMut,
+    sender: &Addr,
+    contract_address: &Addr,
+) -> Result<(), ContractError> {
+    if *sender == contract().config.load(deps.storage)?.owner {
+        return Ok(());
+    }
+    let minter_response: StdResult<MinterResponse> = deps
+        .querier
+        .query_wasm_smart(contract_address, &Cw2981QueryMsg::Minter {});
+    match minter_response {
+        Ok(minter_response) if minter_response.minter == *sender => Ok(()),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
+
+// check that the nft is owned by the owner
+fn check_nft_owner(
+    deps: &DepsMut,
+    contract_address: &Addr,
+    token_id: &str,
+    owner: &Addr,
+) -> Result<(), ContractError> {
+    let owner_response: StdResult<cw721::OwnerOfResponse> = deps.querier.query_wasm_smart(
+        contract_address,
+        &Cw721QueryMsg::OwnerOf {
+            token_id: token_id.to_string(),
+            include_expired: Some(false),
+        },
+    );
+    match owner_response {
+        Ok(owner_response) if owner_response.owner == *owner => Ok(()),
+        Ok(_) => Err(ContractError::Unauthorized {}),
+        Err(_) => Err(ContractError::NftNotFound {}),
+    }
+}
+
+// create the message to transfer an asset from an address to another
+// nfts and cw20 tokens are transferred by the allowance of the marketplace,
+// native tokens are sent from the balance of the marketplace
+fn transfer_asset_msg(
+    deps: &DepsMut,
+    asset: &Asset,
+    from: &Addr,
+    to: &Addr,
+) -> Result<CosmosMsg, ContractError> {
+    match asset {
+        Asset::Nft(NFT {
+            contract_address,
+            token_id: Some(token_id),
+        }) => {
+            // the marketplace may be approved by other owners, so we must check the owner here
+            check_nft_owner(deps, contract_address, token_id, from)?;
+            Ok(WasmMsg::Execute {
+                contract_addr: contract_address.to_string(),
+                msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                    recipient: to.to_string(),
+                    token_id: token_id.clone(),
+                })?,
+                funds: vec![],
+            }
+            .into())
+        }
+        Asset::Nft(NFT { token_id: None, .. }) => Err(ContractError::CollectionItemNotSupported {}),
+        Asset::Cw20(CW20 {
+            contract_address,
+            amount,
+        }) => Ok(WasmMsg::Execute {
+            contract_addr: contract_address.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: from.to_string(),
+                recipient: to.to_string(),
+                amount: Uint128::from(*amount),
+            })?,
+            funds: vec![],
+        }
+        .into()),
+        Asset::Native(NATIVE { denom, amount }) => Ok(BankMsg::Send {
+            to_address: to.to_string(),
+            amount: vec![Coin {
+                denom: denom.clone(),
+                amount: Uint128::from(*amount),
+            }],
+        }
+        .into()),
+    }
+}
+
+// the nfts of the items of one side of an order
+fn order_nfts<'a>(assets: impl Iterator<Item = &'a Asset>) -> Vec<&'a NFT> {
+    assets
+        .filter_map(|asset| match asset {
+            Asset::Nft(nft) => Some(nft),
+            _ => None,
+        })
+        .collect()
+}
+
+// carve the royalties of the nfts out of the fungible items paying for them
+// each fungible item is split equally between the nfts, the last one takes the rounding remainder
+// there is no royalty if the creator is the owner of the nfts, the nft items are kept as they are
+fn with_order_royalties(
+    deps: Deps,
+    items: impl Iterator<Item = (Asset, Addr)>,
+    nfts: &[&NFT],
+    nft_owner: &Addr,
+) -> Result<Vec<(Asset, Addr)>, ContractError> {
+    let mut payments = vec![];
+    for (asset, recipient) in items {
+        let amount = match &asset {
+            Asset::Native(NATIVE { amount, .. }) | Asset::Cw20(CW20 { amount, .. }) => *amount,
+            Asset::Nft(_) => 0,
+        };
+        if amount == 0 || nfts.is_empty() {
+            payments.push((asset, recipient));
+            continue;
+        }
+
+        let item_price = amount / nfts.len() as u128;
+        let mut royalties: BTreeMap<Addr, u128> = BTreeMap::new();
+        for (index, nft) in nfts.iter().enumerate() {
+            let sale_price = if index + 1 == nfts.len() {
+                amount - item_price * index as u128
+            } else {
+                item_price
+            };
+            for (creator, royalty_amount) in query_royalty(
+                deps,
+                &nft.contract_address,
+                nft.token_id.as_deref().unwrap_or_default(),
+                Uint128::from(sale_price),
+            )? {
+                if creator != *nft_owner {
+                    *royalties.entry(creator).or_default() += royalty_amount.u128();
                 }
             }
         }
 
-        res_messages
+        let royalty: u128 = royalties.values().sum();
+        if royalty > amount {
+            return Err(ContractError::RoyaltyExceedsPrice {
+                royalty: Uint128::from(royalty),
+                price: Uint128::from(amount),
+            });
+        }
+        payments.push((with_amount(&asset, amount - royalty), recipient));
+        for (creator, royalty_amount) in royalties {
+            payments.push((with_amount(&asset, royalty_amount), creator));
+        }
+    }
+    Ok(payments
+        .into_iter()
+        .filter(|(asset, _)| {
+            !matches!(
+                asset,
+                Asset::Native(NATIVE { amount: 0, .. }) | Asset::Cw20(CW20 { amount: 0, .. })
+            )
+        })
+        .collect())
+}
+
+// the same fungible asset with another amount
+fn with_amount(asset: &Asset, amount: u128) -> Asset {
+    match asset {
+        Asset::Native(NATIVE { denom, .. }) => Asset::Native(NATIVE {
+            denom: denom.clone(),
+            amount,
+        }),
+        Asset::Cw20(CW20 {
+            contract_address, ..
+        }) => Asset::Cw20(CW20 {
+            contract_address: contract_address.clone(),
+            amount,
+        }),
+        Asset::Nft(_) => asset.clone(),
+    }
+}
+
+// sum up the native assets by denom, the result is sorted by denom
+fn native_coins<'a>(assets: impl Iterator<Item = &'a Asset>) -> Vec<Coin> {
+    let mut amounts: BTreeMap<String, Uint128> = BTreeMap::new();
+    for asset in assets {
+        if let Asset::Native(NATIVE { denom, amount }) = asset {
+            *amounts.entry(denom.clone()).or_default() += Uint128::from(*amount);
+        }
+    }
+    amounts
+        .into_iter()
+        .map(|(denom, amount)| Coin { denom, amount })
+        .collect()
+}
+
+// check that the funds are exactly the expected coins
+fn coins_match(expected: &[Coin], funds: &[Coin]) -> bool {
+    let mut funds: Vec<Coin> = funds
+        .iter()
+        .filter(|coin| !coin.amount.is_zero())
+        .cloned()
+        .collect();
+    funds.sort_by(|a, b| a.denom.cmp(&b.denom));
+    expected == funds.as_slice()
+}
+
+// verify that the order hash is signed by the offerer
+// the address of the offerer is derived from the public key like the cosmos sdk does for secp256k1 accounts
+fn verify_order_signature(
+    deps: &DepsMut,
+    offerer: &Addr,
+    order_hash: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<(), ContractError> {
+    let key_address = Ripemd160::digest(Sha256::digest(public_key));
+    if deps.api.addr_canonicalize(offerer.as_str())?.as_slice() != key_address.as_slice() {
+        return Err(ContractError::InvalidSignature {});
+    }
+
+    match deps.api.secp256k1_verify(order_hash, signature, public_key) {
+        Ok(true) => Ok(()),
+        _ => Err(ContractError::InvalidSignature {}),
     }
 }
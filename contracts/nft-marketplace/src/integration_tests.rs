@@ -1,15 +1,23 @@
 #[cfg(test)]
 pub mod env {
-    use cosmwasm_std::{Addr, Coin, Empty, StdError, Uint128};
+    use cosmwasm_schema::cw_serde;
+    use cosmwasm_std::testing::MockApi;
+    use cosmwasm_std::{
+        to_binary, Addr, Api, Binary, CanonicalAddr, Coin, Deps, DepsMut, Empty, Env, MessageInfo,
+        RecoverPubkeyError, Response, StdError, StdResult, Uint128, VerificationError,
+    };
     use cw20::{Cw20Coin, MinterResponse};
-    use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
+    use cw2981_royalties::msg::{Cw2981QueryMsg, RoyaltiesInfoResponse};
+    use cw721::{Approval, ApprovalResponse, Expiration, OwnerOfResponse};
+    use cw_multi_test::{App, AppBuilder, BankKeeper, Contract, ContractWrapper, Executor};
+    use cw_storage_plus::Item;
     // use cw20_base::msg::InstantiateMsg as Cw20InstantiateMsg;
     // use cw20_base::contract::{execute as cw20_execute, instantiate as cw20_instantiate, query as cw20_query};
     use crate::contract::{
         execute as MarketPlaceExecute, instantiate as MarketPlaceInstantiate,
-        query as MarketPlaceQuery,
+        query as MarketPlaceQuery, reply as MarketPlaceReply,
     };
-    use crate::msg::InstantiateMsg;
+    use crate::msg::{HookExecuteMsg, InstantiateMsg};
     use cw2981_royalties::msg::InstantiateMsg as Cw2981InstantiateMsg;
     use cw2981_royalties::{
         execute as cw2981_execute, instantiate as cw2981_instantiate, query as cw2981_query,
@@ -42,8 +50,90 @@ pub mod env {
         pub contract_code_id: u64,
     }
 
+    // an api which uses the hex of canonical addresses of 20 bytes as human addresses,
+    // so the tests can use addresses derived from secp256k1 public keys like the cosmos sdk does
+    // other addresses are handled by the MockApi
+    #[derive(Default)]
+    pub struct SignatureApi {
+        mock_api: MockApi,
+    }
+
+    impl Api for SignatureApi {
+        fn addr_validate(&self, human: &str) -> StdResult<Addr> {
+            let canonical = self.addr_canonicalize(human)?;
+            let normalized = self.addr_humanize(&canonical)?;
+            if human != normalized {
+                return Err(StdError::generic_err(
+                    "Invalid input: address not normalized",
+                ));
+            }
+            Ok(Addr::unchecked(human))
+        }
+
+        fn addr_canonicalize(&self, human: &str) -> StdResult<CanonicalAddr> {
+            match hex::decode(human) {
+                Ok(bytes) if bytes.len() == 20 => Ok(CanonicalAddr::from(bytes)),
+                _ => self.mock_api.addr_canonicalize(human),
+            }
+        }
+
+        fn addr_humanize(&self, canonical: &CanonicalAddr) -> StdResult<Addr> {
+            if canonical.len() == 20 {
+                return Ok(Addr::unchecked(hex::encode(canonical.as_slice())));
+            }
+            self.mock_api.addr_humanize(canonical)
+        }
+
+        fn secp256k1_verify(
+            &self,
+            message_hash: &[u8],
+            signature: &[u8],
+            public_key: &[u8],
+        ) -> Result<bool, VerificationError> {
+            self.mock_api
+                .secp256k1_verify(message_hash, signature, public_key)
+        }
+
+        fn secp256k1_recover_pubkey(
+            &self,
+            message_hash: &[u8],
+            signature: &[u8],
+            recovery_param: u8,
+        ) -> Result<Vec<u8>, RecoverPubkeyError> {
+            self.mock_api
+                .secp256k1_recover_pubkey(message_hash, signature, recovery_param)
+        }
+
+        fn ed25519_verify(
+            &self,
+            message: &[u8],
+            signature: &[u8],
+            public_key: &[u8],
+        ) -> Result<bool, VerificationError> {
+            self.mock_api.ed25519_verify(message, signature, public_key)
+        }
+
+        fn ed25519_batch_verify(
+            &self,
+            messages: &[&[u8]],
+            signatures: &[&[u8]],
+            public_keys: &[&[u8]],
+        ) -> Result<bool, VerificationError> {
+            self.mock_api
+                .ed25519_batch_verify(messages, signatures, public_keys)
+        }
+
+        fn debug(&self, message: &str) {
+            self.mock_api.debug(message)
+        }
+    }
+
     fn mock_app() -> App {
-        AppBuilder::new().build(|router, _, storage| {
+        mock_app_with_api(MockApi::default())
+    }
+
+    fn mock_app_with_api<A: Api>(api: A) -> App<BankKeeper, A> {
+        AppBuilder::new().with_api(api).build(|router, _, storage| {
             router
                 .bank
                 .init_balance(
@@ -88,10 +178,199 @@ pub mod env {
 
     fn nft_marketplace_contract_template() -> Box<dyn Contract<Empty>> {
         let contract =
-            ContractWrapper::new(MarketPlaceExecute, MarketPlaceInstantiate, MarketPlaceQuery);
+            ContractWrapper::new(MarketPlaceExecute, MarketPlaceInstantiate, MarketPlaceQuery)
+                .with_reply(MarketPlaceReply);
+        Box::new(contract)
+    }
+
+    // a hook contract which records the messages it receives, or fails to handle them
+    #[cw_serde]
+    pub struct HookReceiverInstantiateMsg {
+        pub fail: bool,
+    }
+
+    #[cw_serde]
+    pub enum HookReceiverQueryMsg {
+        Messages {},
+    }
+
+    const HOOK_FAIL: Item<bool> = Item::new("fail");
+    const HOOK_MESSAGES: Item<Vec<HookExecuteMsg>> = Item::new("messages");
+
+    fn hook_receiver_instantiate(
+        deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        msg: HookReceiverInstantiateMsg,
+    ) -> StdResult<Response> {
+        HOOK_FAIL.save(deps.storage, &msg.fail)?;
+        Ok(Response::new())
+    }
+
+    fn hook_receiver_execute(
+        deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        msg: HookExecuteMsg,
+    ) -> StdResult<Response> {
+        if HOOK_FAIL.load(deps.storage)? {
+            return Err(StdError::generic_err("hook failed"));
+        }
+        let mut messages = HOOK_MESSAGES.may_load(deps.storage)?.unwrap_or_default();
+        messages.push(msg);
+        HOOK_MESSAGES.save(deps.storage, &messages)?;
+        Ok(Response::new())
+    }
+
+    fn hook_receiver_query(deps: Deps, _env: Env, _msg: HookReceiverQueryMsg) -> StdResult<Binary> {
+        to_binary(&HOOK_MESSAGES.may_load(deps.storage)?.unwrap_or_default())
+    }
+
+    fn hook_receiver_contract_template() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            hook_receiver_execute,
+            hook_receiver_instantiate,
+            hook_receiver_query,
+        );
+        Box::new(contract)
+    }
+
+    // instantiate a hook contract, which fails to handle the messages if fail is true
+    pub fn instantiate_hook_receiver(app: &mut App, fail: bool) -> String {
+        let code_id = app.store_code(hook_receiver_contract_template());
+        app.instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &HookReceiverInstantiateMsg { fail },
+            &[],
+            "test instantiate hook contract",
+            None,
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    // the behaviors of a deliberately broken contract, which stands for a non compliant nft or cw20 contract
+    #[cw_serde]
+    pub enum BrokenBehavior {
+        // every query returns an error
+        QueryError {},
+        // every query returns a response of a wrong shape
+        WrongResponse {},
+        // the nfts are owned by the owner and approved forever to any spender,
+        // the royalty of the percentage of the sale price is paid to the address, which may be invalid
+        Royalty {
+            owner: String,
+            address: String,
+            percentage: u64,
+        },
+    }
+
+    #[cw_serde]
+    pub enum BrokenExecuteMsg {
+        SetBehavior { behavior: BrokenBehavior },
+        TransferNft { recipient: String, token_id: String },
+    }
+
+    // the queries of nft and cw20 contracts sent by the marketplace
+    #[cw_serde]
+    pub enum BrokenQueryMsg {
+        OwnerOf {
+            token_id: String,
+            include_expired: Option<bool>,
+        },
+        Approval {
+            token_id: String,
+            spender: String,
+            include_expired: Option<bool>,
+        },
+        NftInfo {
+            token_id: String,
+        },
+        Extension {
+            msg: Cw2981QueryMsg,
+        },
+        Allowance {
+            owner: String,
+            spender: String,
+        },
+    }
+
+    const BROKEN_BEHAVIOR: Item<BrokenBehavior> = Item::new("behavior");
+
+    fn broken_instantiate(
+        deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        msg: BrokenBehavior,
+    ) -> StdResult<Response> {
+        BROKEN_BEHAVIOR.save(deps.storage, &msg)?;
+        Ok(Response::new())
+    }
+
+    // set_behavior changes the behavior, the transfers of nfts succeed without doing anything
+    fn broken_execute(
+        deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        msg: BrokenExecuteMsg,
+    ) -> StdResult<Response> {
+        if let BrokenExecuteMsg::SetBehavior { behavior } = msg {
+            BROKEN_BEHAVIOR.save(deps.storage, &behavior)?;
+        }
+        Ok(Response::new())
+    }
+
+    fn broken_query(deps: Deps, _env: Env, msg: BrokenQueryMsg) -> StdResult<Binary> {
+        match BROKEN_BEHAVIOR.load(deps.storage)? {
+            BrokenBehavior::QueryError {} => Err(StdError::generic_err("broken contract")),
+            BrokenBehavior::WrongResponse {} => to_binary("wrong response"),
+            BrokenBehavior::Royalty {
+                owner,
+                address,
+                percentage,
+            } => match msg {
+                BrokenQueryMsg::OwnerOf { .. } => to_binary(&OwnerOfResponse {
+                    owner,
+                    approvals: vec![],
+                }),
+                BrokenQueryMsg::Approval { spender, .. } => to_binary(&ApprovalResponse {
+                    approval: Approval {
+                        spender,
+                        expires: Expiration::Never {},
+                    },
+                }),
+                BrokenQueryMsg::Extension {
+                    msg: Cw2981QueryMsg::RoyaltyInfo { sale_price, .. },
+                } => to_binary(&RoyaltiesInfoResponse {
+                    address,
+                    royalty_amount: sale_price.multiply_ratio(percentage, 100u64),
+                }),
+                _ => Err(StdError::generic_err("unsupported query")),
+            },
+        }
+    }
+
+    fn broken_contract_template() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(broken_execute, broken_instantiate, broken_query);
         Box::new(contract)
     }
 
+    // instantiate a broken contract with the behavior
+    pub fn instantiate_broken_contract(app: &mut App, behavior: BrokenBehavior) -> String {
+        let code_id = app.store_code(broken_contract_template());
+        app.instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &behavior,
+            &[],
+            "test instantiate broken contract",
+            None,
+        )
+        .unwrap()
+        .to_string()
+    }
+
     // *********************************************************
     // You MUST store code and instantiate all contracts here
     // Follow the example (2) below:
@@ -127,8 +406,15 @@ pub mod env {
     //    }
     // *********************************************************
     pub fn instantiate_contracts() -> (App, Vec<ContractInfo>) {
+        instantiate_contracts_with_api(MockApi::default())
+    }
+
+    // the same as instantiate_contracts, but the app uses the given api
+    pub fn instantiate_contracts_with_api<A: Api>(
+        api: A,
+    ) -> (App<BankKeeper, A>, Vec<ContractInfo>) {
         // Create a new app instance
-        let mut app = mock_app();
+        let mut app = mock_app_with_api(api);
 
         // Cw2981 contract
         // store the code of all contracts to the app and get the code ids
@@ -1,10 +1,18 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Addr;
-use cw721::Expiration;
+use cosmwasm_std::{Addr, Binary, Coin, Timestamp, Uint128};
+use cw2981_royalties::{msg::RoyaltiesInfoResponse, Metadata};
+use cw721::{Expiration, NftInfoResponse};
 
 use crate::{
-    order_state::{OrderComponents, NFT},
-    state::{AuctionConfig, AuctionContract, Listing},
+    order_state::{
+        Asset, ConsiderationItem, OfferItem, OrderComponents, PaymentAsset, PaymentAssetInfo,
+        SignedOrderStatus, NFT,
+    },
+    state::{
+        AuctionConfig, AuctionContract, Bundle, DelegatedAction, Delegation, GroupBuy,
+        InstallmentPlan, Listing, Loan, ReverseAuction, RewardAsset, RewardDistribution,
+        RewardsConfig, RoyaltyRecipient, SealedBid,
+    },
 };
 
 #[cw_serde]
@@ -14,16 +22,24 @@ pub struct InstantiateMsg {
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    // List a NFT for sale
+    // List a NFT for sale, a listing reserved for a buyer is private
     ListNft {
         contract_address: String,
         token_id: String,
         auction_config: AuctionConfig,
+        reserved_for: Option<String>,
     },
-    // Buy a listed NFT
+    // Buy a listed NFT, the referrer gets the referral fee
     Buy {
         contract_address: String,
         token_id: String,
+        referrer: Option<String>,
+    },
+    // Buy the cheapest listings of a collection, the unspent funds are refunded
+    SweepCollection {
+        contract_address: String,
+        max_items: u32,
+        max_price_per_item: Uint128,
     },
     // Cancel a listed NFT
     Cancel {
@@ -39,26 +55,256 @@ pub enum ExecuteMsg {
         contract_address: String,
     },
 
-    // Offer a Nft
+    // Offer a Nft, a delegate offers on behalf of the offerer
     OfferNft {
         nft: NFT,
         funds_amount: u128,
         end_time: Expiration,
+        offerer: Option<String>,
     },
-    // Accept a Nft offer
+    // Accept a Nft offer, the referrer gets the referral fee
     AcceptNftOffer {
-        offerer: String,
-        nft: NFT,
+        order_id: u64,
         funds_amount: u128,
+        referrer: Option<String>,
     },
-    // Cancel offer of User
+    // Cancel offers of User
     CancelOffer {
-        nfts: Vec<NFT>,
+        order_ids: Vec<u64>,
     },
     // edit contract address of vaura token
     EditVauraToken {
         token_address: String,
     },
+    // Create an order with arbitrary offer and consideration items
+    CreateOrder {
+        offer: Vec<OfferItem>,
+        consideration: Vec<ConsiderationItem>,
+        start_time: Option<Expiration>,
+        end_time: Option<Expiration>,
+    },
+    // Fulfill an order, all items of the order are transferred atomically
+    FulfillOrder {
+        order_id: u64,
+    },
+    // Cancel an order, the escrowed native tokens are refunded to the offerer
+    CancelOrder {
+        order_id: u64,
+    },
+    // Fulfill an order signed off-chain by the offerer
+    // the order_id of a signed order is a salt chosen by the offerer
+    // the public_key is the compressed secp256k1 public key of the offerer
+    FulfillSignedOrder {
+        order: OrderComponents,
+        signature: Binary,
+        public_key: Binary,
+    },
+    // Cancel orders signed off-chain by the sender
+    CancelSignedOrders {
+        orders: Vec<OrderComponents>,
+    },
+    // Increase the counter of the sender to invalidate all of their listings, offers and orders
+    IncrementCounter {},
+    // List several nfts for sale as a single lot
+    ListBundle {
+        nfts: Vec<NFT>,
+        auction_config: AuctionConfig,
+    },
+    // Buy all nfts of a bundle, the referrer gets the referral fee
+    BuyBundle {
+        bundle_id: u64,
+        referrer: Option<String>,
+    },
+    // Cancel a bundle
+    CancelBundle {
+        bundle_id: u64,
+    },
+    // Create a reverse auction for a nft, the funds sent are the max price
+    // any nft of the collection is accepted if the token_id is None
+    CreateReverseAuction {
+        nft: NFT,
+        end_time: Expiration,
+    },
+    // Submit an ask lower than the current lowest ask of a reverse auction
+    SubmitAsk {
+        auction_id: u64,
+        token_id: String,
+        price: Uint128,
+    },
+    // Settle a reverse auction after its end time
+    SettleReverseAuction {
+        auction_id: u64,
+    },
+    // Cancel a reverse auction without any ask that can still be settled
+    CancelReverseAuction {
+        auction_id: u64,
+    },
+    // Commit a sealed bid to a sealed bid auction, the funds sent are the deposit
+    // an auction accepts a limited number of bids
+    CommitBid {
+        contract_address: String,
+        token_id: String,
+        commitment: Binary,
+    },
+    // Reveal a sealed bid during the reveal phase
+    RevealBid {
+        contract_address: String,
+        token_id: String,
+        amount: Uint128,
+        salt: String,
+    },
+    // Settle a sealed bid auction after the reveal phase
+    // the deposits returned to the losing bidders are credited to their pending balances
+    SettleSealedBid {
+        contract_address: String,
+        token_id: String,
+    },
+    // enable or disable pull payments, proceeds and refunds are then credited to pending balances
+    EditPullPayments {
+        enabled: bool,
+    },
+    // edit the share of the sale price paid to the referrers, in basis points
+    // it is carved out of the proceeds of the seller
+    EditReferralFee {
+        referral_fee_bps: u64,
+    },
+    // Withdraw the pending balance of the sender in an asset
+    Withdraw {
+        asset: PaymentAssetInfo,
+    },
+    // Accept automatically the offers at or above min_price for a nft
+    // or for any nft of a collection if the token_id is None
+    SetAutoAccept {
+        contract_address: String,
+        token_id: Option<String>,
+        min_price: Uint128,
+    },
+    // Remove an auto accept rule
+    RemoveAutoAccept {
+        contract_address: String,
+        token_id: Option<String>,
+    },
+    // Allow a delegate to list, cancel, offer or accept offers on behalf of the sender for a collection
+    // the offers of the delegate are limited by the spending cap
+    GrantPermission {
+        delegate: String,
+        contract_address: String,
+        actions: Vec<DelegatedAction>,
+        spending_cap: Option<Uint128>,
+        expires: Expiration,
+    },
+    // Revoke the permissions of a delegate for a collection
+    RevokePermission {
+        delegate: String,
+        contract_address: String,
+    },
+    // Register the royalties of a collection, which are paid when the collection does not support cw2981
+    // only the minter of the collection or the owner of the marketplace can register them
+    SetCollectionRoyalty {
+        contract_address: String,
+        recipients: Vec<RoyaltyRecipient>,
+    },
+    // Remove the registered royalties of a collection
+    RemoveCollectionRoyalty {
+        contract_address: String,
+    },
+    // Add a contract notified of the listings, offers and sales, only the owner can add it
+    AddHook {
+        contract_address: String,
+    },
+    // Remove a hook contract
+    RemoveHook {
+        contract_address: String,
+    },
+    // Configure the trading rewards, only the owner can configure them
+    // the epochs start at the first configuration and their duration cannot be changed
+    EditRewardsConfig {
+        token: String,
+        epoch_duration: u64,
+        rewards_per_epoch: Uint128,
+        distribution: RewardDistribution,
+        // the payment assets which give points, with the weight of their amounts in basis points
+        eligible_assets: Vec<RewardAsset>,
+    },
+    // Set the multiplier of the reward points of a collection, in basis points
+    SetRewardMultiplier {
+        contract_address: String,
+        multiplier: u64,
+    },
+    // Claim the rewards of the sender for an ended epoch
+    ClaimRewards {
+        epoch: u64,
+    },
+    // escrow a nft in the marketplace to rent it out for a price per day
+    ListForRent {
+        contract_address: String,
+        token_id: String,
+        price_per_day: Coin,
+        max_days: u64,
+    },
+    // rent a nft for a number of days, the renter becomes the user of the nft
+    Rent {
+        contract_address: String,
+        token_id: String,
+        days: u64,
+    },
+    // take back a nft which is not rented, or whose rental expired
+    ReclaimRental {
+        contract_address: String,
+        token_id: String,
+    },
+    // pay the next installment of a nft bought with installments, the nft is sent on the final payment
+    PayInstallment {
+        contract_address: String,
+        token_id: String,
+    },
+    // end a purchase with installments after a missed installment, the seller gets the nft back
+    // and a share of the paid funds, the rest is refunded to the buyer
+    ForfeitInstallments {
+        contract_address: String,
+        token_id: String,
+    },
+    // pool funds to buy a fixed price listing for the vault,
+    // the contributions are refunded if the price is not raised before the deadline
+    CreateGroupBuy {
+        contract_address: String,
+        token_id: String,
+        vault: String,
+        deadline: Expiration,
+    },
+    // contribute the funds sent to a group buy, the listing is bought when the price is raised
+    // and the contribution above the remaining price is refunded
+    ContributeGroupBuy {
+        group_buy_id: u64,
+    },
+    // refund all contributors of a group buy after its deadline, or once its listing cannot be bought
+    RefundGroupBuy {
+        group_buy_id: u64,
+    },
+    // escrow a nft as collateral to borrow the principal for a duration in seconds,
+    // the repayment includes the interest and must be at least the principal
+    RequestLoan {
+        nft: NFT,
+        principal: PaymentAsset,
+        repayment: u128,
+        duration: u64,
+    },
+    // cancel a loan request which is not funded, the nft is returned to the borrower
+    CancelLoanRequest {
+        loan_id: u64,
+    },
+    // pay the principal of a loan request to the borrower
+    FundLoan {
+        loan_id: u64,
+    },
+    // pay the repayment to the lender before the due time, the nft is returned to the borrower
+    RepayLoan {
+        loan_id: u64,
+    },
+    // take the nft of a loan which is not repaid at the due time
+    ForecloseLoan {
+        loan_id: u64,
+    },
 }
 
 #[cw_serde]
@@ -70,7 +316,7 @@ pub enum QueryMsg {
     // list config of contract
     #[returns(crate::state::Config)]
     Config {},
-    // get listing by contract_address
+    // get public listings by contract_address
     #[returns(ListingsResponse)]
     ListingsByContractAddress {
         contract_address: String,
@@ -78,10 +324,52 @@ pub enum QueryMsg {
         limit: Option<u32>,
     },
     // get listing by contract_address and token_id
+    // a private listing is only returned to its seller or its reserved buyer as the viewer
     #[returns(Listing)]
     Listing {
         contract_address: String,
         token_id: String,
+        viewer: Option<String>,
+    },
+    // get a listing with the information and the royalty of its nft
+    #[returns(ListingInfo)]
+    ListingWithInfo {
+        contract_address: String,
+        token_id: String,
+        viewer: Option<String>,
+    },
+    // get public listings by contract_address with the information and the royalty of their nfts
+    #[returns(ListingsWithInfoResponse)]
+    ListingsWithInfoByContractAddress {
+        contract_address: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // simulate buying a listing, the transfers are returned with the reason the purchase would fail
+    // the checks of the buyer and the referrer are only simulated if the buyer is given
+    #[returns(SimulationResponse)]
+    SimulateBuy {
+        contract_address: String,
+        token_id: String,
+        buyer: Option<String>,
+        referrer: Option<String>,
+    },
+    // simulate accepting an offer by the owner of the nft
+    #[returns(SimulationResponse)]
+    SimulateAcceptOffer { order_id: u64 },
+    // get private listings created by a seller
+    #[returns(ListingsResponse)]
+    PrivateListingsBySeller {
+        seller: String,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    },
+    // get private listings reserved for a buyer
+    #[returns(ListingsResponse)]
+    ReservedListings {
+        buyer: String,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
     },
     // get list of auction contracts
     #[returns(Vec<Addr>)]
@@ -95,26 +383,182 @@ pub enum QueryMsg {
     },
     // get the specific offer
     #[returns(OrderComponents)]
-    Offer {
-        contract_address: String,
-        token_id: String,
-        offerer: String,
-    },
+    Offer { order_id: u64 },
     // get all offers of a nft
     #[returns(OffersResponse)]
     NftOffers {
         contract_address: String,
         token_id: String,
-        start_after_offerer: Option<String>,
+        start_after: Option<u64>,
         limit: Option<u32>,
     },
     // get all offers of a user
     #[returns(OffersResponse)]
     UserOffers {
         offerer: String,
-        start_after_nft: Option<NFT>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get the specific order
+    #[returns(OrderComponents)]
+    Order { order_id: u64 },
+    // get all orders of a nft, a listing is indexed by its offered nft and an offer by its requested nft
+    #[returns(OrdersResponse)]
+    NftOrders {
+        contract_address: String,
+        token_id: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get all orders of a user
+    #[returns(OrdersResponse)]
+    UserOrders {
+        offerer: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get the hash of an order, which must be signed by the offerer
+    #[returns(Binary)]
+    SignedOrderHash { order: OrderComponents },
+    // get the status of an order signed off-chain, None if it is neither filled nor cancelled
+    #[returns(Option<SignedOrderStatus>)]
+    SignedOrderStatus { order_hash: Binary },
+    // get the current counter of a user
+    #[returns(u64)]
+    Counter { user: String },
+    // get the specific bundle
+    #[returns(Bundle)]
+    Bundle { bundle_id: u64 },
+    // get all bundles of a seller
+    #[returns(BundlesResponse)]
+    BundlesBySeller {
+        seller: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get the specific reverse auction
+    #[returns(ReverseAuction)]
+    ReverseAuction { auction_id: u64 },
+    // get all reverse auctions
+    #[returns(ReverseAuctionsResponse)]
+    ReverseAuctions {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get all sealed bids of a listing
+    #[returns(SealedBidsResponse)]
+    SealedBids {
+        contract_address: String,
+        token_id: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // get the price from which the offers for a nft are accepted automatically by its owner
+    // the rule of the collection is returned if the token_id is None
+    #[returns(Option<Uint128>)]
+    AutoAccept {
+        owner: String,
+        contract_address: String,
+        token_id: Option<String>,
+    },
+    // get the permissions granted by an owner to a delegate for a collection
+    #[returns(Option<Delegation>)]
+    Delegation {
+        owner: String,
+        delegate: String,
+        contract_address: String,
+    },
+    // get all permissions granted by an owner
+    #[returns(DelegationsResponse)]
+    DelegationsByOwner {
+        owner: String,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    },
+    // get the config of the trading rewards
+    #[returns(Option<RewardsConfig>)]
+    RewardsConfig {},
+    // get the multiplier of the reward points of a collection, in basis points
+    #[returns(u64)]
+    RewardMultiplier { contract_address: String },
+    // get the points and the pending and claimed rewards of a user per epoch
+    #[returns(UserRewardsResponse)]
+    UserRewards {
+        user: String,
+        start_after: Option<u64>,
         limit: Option<u32>,
     },
+    // get the hook contracts
+    #[returns(Vec<Addr>)]
+    Hooks {},
+    // get the rental listing of a nft
+    #[returns(Listing)]
+    Rental {
+        contract_address: String,
+        token_id: String,
+    },
+    // get the user of a rented nft, there is no user once the rental expires
+    #[returns(RentalUserResponse)]
+    RentalUser {
+        contract_address: String,
+        token_id: String,
+    },
+    // get the purchase with installments of a nft
+    #[returns(InstallmentPlan)]
+    InstallmentPlan {
+        contract_address: String,
+        token_id: String,
+    },
+    // get a group buy
+    #[returns(GroupBuy)]
+    GroupBuy { group_buy_id: u64 },
+    // get the contributions to a group buy
+    #[returns(GroupBuyContributionsResponse)]
+    GroupBuyContributions {
+        group_buy_id: u64,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // get a loan
+    #[returns(Loan)]
+    Loan { loan_id: u64 },
+    // get the loans of a borrower
+    #[returns(LoansResponse)]
+    LoansByBorrower {
+        borrower: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get the funded loans of a lender
+    #[returns(LoansResponse)]
+    LoansByLender {
+        lender: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get the loans collateralized by the nfts of a collection
+    #[returns(LoansResponse)]
+    LoansByCollection {
+        contract_address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // get the royalties registered for a collection
+    #[returns(Option<Vec<RoyaltyRecipient>>)]
+    CollectionRoyalty { contract_address: String },
+    // get the total referral fees earned by a referrer
+    #[returns(ReferralEarningsResponse)]
+    ReferralEarnings { referrer: String },
+    // get the pending balances of an address, which can be withdrawn
+    #[returns(PendingBalanceResponse)]
+    PendingBalance { address: String },
+    // get the commitment of a sealed bid, which must be sent when committing the bid
+    #[returns(Binary)]
+    SealedBidCommitment {
+        bidder: String,
+        amount: Uint128,
+        salt: String,
+    },
 }
 
 #[cw_serde]
@@ -122,6 +566,20 @@ pub struct ListingsResponse {
     pub listings: Vec<Listing>,
 }
 
+// the royalties are computed at the listed price, they are empty if the listing is not sold at a fixed price
+// or if the nft has no royalty, neither from cw2981 nor registered in the marketplace
+#[cw_serde]
+pub struct ListingInfo {
+    pub listing: Listing,
+    pub nft_info: Option<NftInfoResponse<Metadata>>,
+    pub royalties: Vec<RoyaltiesInfoResponse>,
+}
+
+#[cw_serde]
+pub struct ListingsWithInfoResponse {
+    pub listings: Vec<ListingInfo>,
+}
+
 #[cw_serde]
 pub struct ValidateResponse {
     pub valid: bool,
@@ -131,3 +589,141 @@ pub struct ValidateResponse {
 pub struct OffersResponse {
     pub offers: Vec<OrderComponents>,
 }
+
+#[cw_serde]
+pub struct OrdersResponse {
+    pub orders: Vec<OrderComponents>,
+}
+
+#[cw_serde]
+pub struct BundlesResponse {
+    pub bundles: Vec<Bundle>,
+}
+
+#[cw_serde]
+pub struct ReverseAuctionsResponse {
+    pub auctions: Vec<ReverseAuction>,
+}
+
+#[cw_serde]
+pub struct SealedBidsResponse {
+    pub bids: Vec<SealedBid>,
+}
+
+#[cw_serde]
+pub struct PendingBalanceResponse {
+    pub balances: Vec<PaymentAsset>,
+}
+
+#[cw_serde]
+pub struct GroupBuyContribution {
+    pub contributor: Addr,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct GroupBuyContributionsResponse {
+    pub contributions: Vec<GroupBuyContribution>,
+}
+
+#[cw_serde]
+pub struct LoansResponse {
+    pub loans: Vec<Loan>,
+}
+
+#[cw_serde]
+pub struct RentalUserResponse {
+    pub user: Option<Addr>,
+    pub expires: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct ReferralEarningsResponse {
+    pub earnings: Vec<PaymentAsset>,
+}
+
+#[cw_serde]
+pub struct DelegationsResponse {
+    pub delegations: Vec<Delegation>,
+}
+
+#[cw_serde]
+pub struct EpochRewards {
+    pub epoch: u64,
+    pub points: Uint128,
+    pub pending: Uint128,
+    pub claimed: Uint128,
+}
+
+#[cw_serde]
+pub struct UserRewardsResponse {
+    pub rewards: Vec<EpochRewards>,
+}
+
+#[cw_serde]
+pub enum HookAction {
+    Created,
+    Cancelled,
+}
+
+// a nft is sold by the seller to the buyer at the price, by any kind of sale,
+// a rental is the sale of the use of the nft and a foreclosure the sale of the collateral for the principal
+#[cw_serde]
+pub struct SaleHookMsg {
+    pub contract_address: Addr,
+    pub token_id: String,
+    pub seller: Addr,
+    pub buyer: Addr,
+    pub price: PaymentAsset,
+}
+
+#[cw_serde]
+pub struct ListingHookMsg {
+    pub action: HookAction,
+    pub contract_address: Addr,
+    pub token_id: String,
+    pub seller: Addr,
+}
+
+#[cw_serde]
+pub struct OfferHookMsg {
+    pub action: HookAction,
+    pub order_id: u64,
+    pub offerer: Addr,
+    pub contract_address: Addr,
+    pub token_id: String,
+    pub price: PaymentAsset,
+}
+
+// the messages sent to the hook contracts, which must handle them
+// a failing hook does not revert the trade
+#[cw_serde]
+pub enum HookExecuteMsg {
+    SaleHook(SaleHookMsg),
+    ListingHook(ListingHookMsg),
+    OfferHook(OfferHookMsg),
+}
+
+#[cw_serde]
+pub enum TransferKind {
+    Royalty,
+    SellerProceeds,
+    ReferralFee,
+    Nft,
+    Escrow,
+}
+
+// the recipient is None when it is the sender of the simulated trade
+#[cw_serde]
+pub struct SimulatedTransfer {
+    pub kind: TransferKind,
+    pub recipient: Option<Addr>,
+    pub asset: Asset,
+}
+
+// the error is the reason the simulated trade would fail, None if it would succeed
+#[cw_serde]
+pub struct SimulationResponse {
+    pub transfers: Vec<SimulatedTransfer>,
+    pub error: Option<String>,
+}
@@ -1,7 +1,10 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::Addr;
+use cosmwasm_std::{to_vec, Addr, StdResult};
 use cw721::Expiration;
-use cw_storage_plus::{Index, IndexList, IndexedMap, MultiIndex};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Map, MultiIndex};
+use sha2::{Digest, Sha256};
+
+use crate::ContractError;
 
 pub type Nft = (Addr, String);
 pub type User = Addr;
@@ -37,6 +40,17 @@ pub enum Asset {
     Cw20(CW20),
 }
 
+impl Asset {
+    // returns the item type matching the asset
+    pub fn item_type(&self) -> ItemType {
+        match self {
+            Asset::Nft(_) => ItemType::CW721,
+            Asset::Native(_) => ItemType::NATIVE,
+            Asset::Cw20(_) => ItemType::CW20,
+        }
+    }
+}
+
 #[cw_serde]
 pub enum PaymentAsset {
     Native {
@@ -49,18 +63,48 @@ pub enum PaymentAsset {
     },
 }
 
-impl From<Asset> for PaymentAsset {
-    fn from(asset: Asset) -> Self {
+impl PaymentAsset {
+    pub fn info(&self) -> PaymentAssetInfo {
+        match self {
+            PaymentAsset::Native { denom, .. } => PaymentAssetInfo::Native {
+                denom: denom.clone(),
+            },
+            PaymentAsset::Cw20 {
+                contract_address, ..
+            } => PaymentAssetInfo::Cw20 {
+                contract_address: contract_address.clone(),
+            },
+        }
+    }
+
+    pub fn amount(&self) -> u128 {
+        match self {
+            PaymentAsset::Native { amount, .. } | PaymentAsset::Cw20 { amount, .. } => *amount,
+        }
+    }
+}
+
+// a payment asset without its amount
+#[cw_serde]
+pub enum PaymentAssetInfo {
+    Native { denom: String },
+    Cw20 { contract_address: Addr },
+}
+
+impl TryFrom<Asset> for PaymentAsset {
+    type Error = ContractError;
+
+    fn try_from(asset: Asset) -> Result<Self, Self::Error> {
         match asset {
-            Asset::Native(NATIVE { denom, amount }) => PaymentAsset::Native { denom, amount },
+            Asset::Native(NATIVE { denom, amount }) => Ok(PaymentAsset::Native { denom, amount }),
             Asset::Cw20(CW20 {
                 contract_address,
                 amount,
-            }) => PaymentAsset::Cw20 {
+            }) => Ok(PaymentAsset::Cw20 {
                 contract_address,
                 amount,
-            },
-            _ => panic!("Asset is not a payment asset"),
+            }),
+            Asset::Nft(_) => Err(ContractError::NotPaymentAsset {}),
         }
     }
 }
@@ -125,16 +169,8 @@ pub fn consideration_item(
     }
 }
 
-// the OrderKey includes the address and id of NFT
-// !DO NOT change the order of the fields
-pub type OrderKey = (User, Nft);
-
-pub fn order_key(user_address: &Addr, contract_address: &Addr, token_id: &str) -> OrderKey {
-    (
-        user_address.clone(),
-        (contract_address.clone(), token_id.to_owned()),
-    )
-}
+// the OrderKey is a monotonically increasing id, so a user can keep several orders on the same NFT
+pub type OrderKey = u64;
 
 #[cw_serde]
 pub struct OrderComponents {
@@ -145,6 +181,31 @@ pub struct OrderComponents {
     pub consideration: Vec<ConsiderationItem>,
     pub start_time: Option<Expiration>,
     pub end_time: Option<Expiration>,
+    #[serde(default)]
+    pub counter: u64, // the counter of the offerer when the order was created
+}
+
+impl OrderComponents {
+    // returns the nft which is the subject of the order
+    // for an offer it is the first CW721 consideration item, for a listing it is the first CW721 offer item
+    // if there is no such item, we fall back to the first CW721 item of the other side
+    pub fn nft(&self) -> Option<Nft> {
+        let offer_items = self.offer.iter().map(|item| &item.item);
+        let consideration_items = self.consideration.iter().map(|item| &item.item);
+        let items = match self.order_type {
+            OrderType::OFFER => consideration_items
+                .chain(offer_items)
+                .collect::<Vec<&Asset>>(),
+            OrderType::LISTING => offer_items.chain(consideration_items).collect(),
+        };
+        items.into_iter().find_map(|item| match item {
+            Asset::Nft(NFT {
+                contract_address,
+                token_id: Some(token_id),
+            }) => Some((contract_address.clone(), token_id.clone())),
+            _ => None,
+        })
+    }
 }
 
 pub struct OfferIndexes<'a> {
@@ -160,19 +221,88 @@ impl<'a> IndexList<OrderComponents> for OfferIndexes<'a> {
     }
 }
 
-// helper function create a IndexedMap for listings
-pub fn orders<'a>() -> IndexedMap<'a, OrderKey, OrderComponents, OfferIndexes<'a>> {
+// the payload an offerer signs to create an order off-chain
+// the chain id and the address of the marketplace are the domain of the signature,
+// so the signed order cannot be replayed on another chain or another marketplace
+#[cw_serde]
+pub struct SignedOrderPayload {
+    pub chain_id: String,
+    pub contract_address: Addr,
+    pub order: OrderComponents,
+}
+
+// the hash of an off-chain order is the sha256 of its canonical (json) serialization,
+// it is the message signed by the offerer and the key to track filled and cancelled orders
+pub fn order_hash(
+    chain_id: &str,
+    contract_address: &Addr,
+    order: &OrderComponents,
+) -> StdResult<Vec<u8>> {
+    let payload = to_vec(&SignedOrderPayload {
+        chain_id: chain_id.to_string(),
+        contract_address: contract_address.clone(),
+        order: order.clone(),
+    })?;
+    Ok(Sha256::digest(payload).to_vec())
+}
+
+#[cw_serde]
+pub enum SignedOrderStatus {
+    Filled {},
+    Cancelled {},
+}
+
+fn order_components_map<'a>(
+    pk_namespace: &'a str,
+    users_namespace: &'a str,
+    nfts_namespace: &'a str,
+) -> IndexedMap<'a, OrderKey, OrderComponents, OfferIndexes<'a>> {
     let indexes = OfferIndexes {
         users: MultiIndex::new(
-            |_pk: &[u8], l: &OrderComponents| (l.order_id.0.clone()),
-            "users",
-            "orders__user_address",
+            |_pk: &[u8], l: &OrderComponents| l.offerer.clone(),
+            pk_namespace,
+            users_namespace,
         ),
         nfts: MultiIndex::new(
-            |_pk: &[u8], l: &OrderComponents| (l.order_id.1.clone()),
-            "nfts",
-            "orders__nft_identifier",
+            |_pk: &[u8], l: &OrderComponents| {
+                l.nft()
+                    .unwrap_or_else(|| (Addr::unchecked(""), String::new()))
+            },
+            pk_namespace,
+            nfts_namespace,
         ),
     };
-    IndexedMap::new("orders", indexes)
+    IndexedMap::new(pk_namespace, indexes)
+}
+
+// helper function create a IndexedMap for offers
+pub fn orders<'a>() -> IndexedMap<'a, OrderKey, OrderComponents, OfferIndexes<'a>> {
+    order_components_map("offers", "offers__user_address", "offers__nft_identifier")
+}
+
+// helper function create a IndexedMap for orders created with arbitrary offer and consideration items
+pub fn generic_orders<'a>() -> IndexedMap<'a, OrderKey, OrderComponents, OfferIndexes<'a>> {
+    order_components_map(
+        "generic_orders",
+        "generic_orders__user_address",
+        "generic_orders__nft_identifier",
+    )
+}
+
+// the key and struct of offers before the order id was introduced, we use them in the migration
+pub type OrderKeyOld = (User, Nft);
+
+#[cw_serde]
+pub struct OrderComponentsOld {
+    pub order_type: OrderType,
+    pub order_id: OrderKeyOld,
+    pub offerer: User,
+    pub offer: Vec<OfferItem>,
+    pub consideration: Vec<ConsiderationItem>,
+    pub start_time: Option<Expiration>,
+    pub end_time: Option<Expiration>,
+}
+
+pub fn orders_old<'a>() -> Map<'a, OrderKeyOld, OrderComponentsOld> {
+    Map::new("orders")
 }
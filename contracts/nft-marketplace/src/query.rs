@@ -1,26 +1,63 @@
-use cosmwasm_std::{Addr, Deps, Order, StdResult};
+use cosmwasm_std::{Addr, Binary, Deps, Env, Order, StdError, StdResult, Uint128};
+use cw20::Cw20QueryMsg;
+use cw2981_royalties::msg::RoyaltiesInfoResponse;
+use cw721::Cw721QueryMsg;
 use cw_storage_plus::Bound;
 
 use crate::{
-    msg::{ListingsResponse, OffersResponse},
-    order_state::{order_key, OrderComponents, OrderKey, NFT},
-    state::{listing_key, AuctionConfig, Listing, ListingKey, MarketplaceContract},
+    execute::{
+        check_buyer, check_fixed_price_period, check_listing_approval, query_royalty,
+        royalty_payouts, sale_payouts,
+    },
+    msg::{
+        BundlesResponse, DelegationsResponse, EpochRewards, GroupBuyContribution,
+        GroupBuyContributionsResponse, ListingInfo, ListingsResponse, ListingsWithInfoResponse,
+        LoansResponse, OffersResponse, OrdersResponse, PendingBalanceResponse,
+        ReferralEarningsResponse, RentalUserResponse, ReverseAuctionsResponse, SealedBidsResponse,
+        SimulatedTransfer, SimulationResponse, TransferKind, UserRewardsResponse,
+    },
+    order_state::{
+        order_hash, Asset, OrderComponents, OrderKey, PaymentAsset, SignedOrderStatus, CW20,
+        NATIVE, NFT,
+    },
+    state::{
+        listing_key, AuctionConfig, Bundle, BundleKey, GroupBuyKey, InstallmentPlan, Listing,
+        ListingKey, ListingStatus, MarketplaceContract, ReverseAuction, ReverseAuctionKey,
+    },
+    ContractError,
 };
 
 impl MarketplaceContract<'static> {
+    // the listing is returned at the price of its schedule at the block time
     pub fn query_listing(
         self,
         deps: Deps,
+        env: Env,
         contract_address: Addr,
         token_id: String,
+        viewer: Option<Addr>,
     ) -> StdResult<Listing> {
         let listing_key = listing_key(&contract_address, &token_id);
-        self.listings.load(deps.storage, listing_key)
+        let listing = self.listings.load(deps.storage, listing_key)?;
+
+        // hide the listing invalidated by the counter of the seller
+        if !self.is_listing_valid(deps.storage, &listing)? {
+            return Err(StdError::not_found("Listing"));
+        }
+        // hide the private listing from anyone but its seller and its reserved buyer
+        if listing.is_private()
+            && viewer.as_ref() != Some(&listing.seller)
+            && viewer != listing.reserved_for
+        {
+            return Err(StdError::not_found("Listing"));
+        }
+        Ok(listing.with_active_price(&env.block))
     }
 
     pub fn query_listings_by_contract_address(
         self,
         deps: Deps,
+        env: Env,
         status: String,
         contract_address: Addr,
         start_after: Option<String>,
@@ -36,7 +73,300 @@ impl MarketplaceContract<'static> {
             .prefix((status, contract_address))
             .range(deps.storage, start, None, Order::Ascending)
             .map(|item| item.map(|(_, listing)| listing))
+            .filter(|item| self.keep_valid_listing(deps, item))
+            // private listings are left out, they are only listed by their seller and buyer
+            .filter(|item| !matches!(item, Ok(listing) if listing.is_private()))
+            .take(limit)
+            .map(|item| item.map(|listing| listing.with_active_price(&env.block)))
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(ListingsResponse { listings })
+    }
+
+    // query a listing with the information and the royalty of its nft
+    pub fn query_listing_with_info(
+        self,
+        deps: Deps,
+        env: Env,
+        contract_address: Addr,
+        token_id: String,
+        viewer: Option<Addr>,
+    ) -> StdResult<ListingInfo> {
+        let listing = self.query_listing(deps, env, contract_address, token_id, viewer)?;
+        Ok(listing_info(deps, listing))
+    }
+
+    // query the public listings of a collection with the information and the royalty of their nfts
+    pub fn query_listings_with_info_by_contract_address(
+        self,
+        deps: Deps,
+        env: Env,
+        contract_address: Addr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<ListingsWithInfoResponse> {
+        let listings = self
+            .query_listings_by_contract_address(
+                deps,
+                env,
+                ListingStatus::Ongoing {}.name(),
+                contract_address,
+                start_after,
+                limit,
+            )?
+            .listings
+            .into_iter()
+            .map(|listing| listing_info(deps, listing))
+            .collect();
+        Ok(ListingsWithInfoResponse { listings })
+    }
+
+    // simulate buying a listing at its current price, with the same payouts and checks as buying it
+    // the buyer and the referrer are only checked if the buyer is given
+    pub fn query_simulate_buy(
+        self,
+        deps: Deps,
+        env: Env,
+        contract_address: Addr,
+        token_id: String,
+        buyer: Option<Addr>,
+        referrer: Option<Addr>,
+    ) -> StdResult<SimulationResponse> {
+        let listing = match self
+            .listings
+            .may_load(deps.storage, listing_key(&contract_address, &token_id))?
+        {
+            Some(listing) => listing.with_active_price(&env.block),
+            None => return Ok(failed_simulation(StdError::not_found("Listing"))),
+        };
+        let nft = Asset::Nft(NFT {
+            contract_address: contract_address.clone(),
+            token_id: Some(token_id.clone()),
+        });
+        let (transfers, period) = match &listing.auction_config {
+            AuctionConfig::FixedPrice {
+                price,
+                start_time,
+                end_time,
+                ..
+            } => {
+                // the nft is transferred to the buyer
+                let mut transfers = vec![SimulatedTransfer {
+                    kind: TransferKind::Nft,
+                    recipient: None,
+                    asset: nft,
+                }];
+                let config = self.config.load(deps.storage)?;
+                match sale_payouts(deps, &config, &listing, price, referrer.as_ref()) {
+                    Ok(payouts) => {
+                        transfers.extend(payouts.into_iter().map(|(kind, recipient, coin)| {
+                            SimulatedTransfer {
+                                kind,
+                                recipient: Some(recipient),
+                                asset: Asset::Native(NATIVE {
+                                    denom: coin.denom,
+                                    amount: coin.amount.u128(),
+                                }),
+                            }
+                        }))
+                    }
+                    Err(err) => return Ok(failed_simulation(err)),
+                }
+                (
+                    transfers,
+                    check_fixed_price_period(start_time, end_time, &env),
+                )
+            }
+            // the nft and the deposit are escrowed in the marketplace until the final installment
+            AuctionConfig::Installments { price, deposit, .. } => (
+                vec![
+                    SimulatedTransfer {
+                        kind: TransferKind::Nft,
+                        recipient: Some(env.contract.address.clone()),
+                        asset: nft,
+                    },
+                    SimulatedTransfer {
+                        kind: TransferKind::Escrow,
+                        recipient: Some(env.contract.address.clone()),
+                        asset: Asset::Native(NATIVE {
+                            denom: price.denom.clone(),
+                            amount: deposit.u128(),
+                        }),
+                    },
+                ],
+                Ok(()),
+            ),
+            _ => return Ok(failed_simulation("Invalid Auction Config")),
+        };
+
+        // the same checks as buying the listing
+        let error = if !listing.is_active() {
+            Some(ContractError::ListingNotActive {})
+        } else if !self.is_listing_valid(deps.storage, &listing)? {
+            Some(ContractError::OrderInvalidated {})
+        } else {
+            buyer
+                .as_ref()
+                .map_or(Ok(()), |buyer| {
+                    check_buyer(&listing, buyer, referrer.as_ref())
+                })
+                .and(period)
+                .and_then(|_| {
+                    check_listing_approval(
+                        deps,
+                        &env,
+                        &listing.seller,
+                        &contract_address,
+                        &token_id,
+                    )
+                })
+                .err()
+        };
+
+        Ok(SimulationResponse {
+            transfers,
+            error: error.map(|err| err.to_string()),
+        })
+    }
+
+    // simulate accepting an offer by the owner of the nft
+    pub fn query_simulate_accept_offer(
+        self,
+        deps: Deps,
+        env: Env,
+        order_id: OrderKey,
+    ) -> StdResult<SimulationResponse> {
+        let offer = match self.offers.may_load(deps.storage, order_id)? {
+            Some(offer) => offer,
+            None => return Ok(failed_simulation(StdError::not_found("Offer"))),
+        };
+        let (contract_address, token_id) = match &offer.consideration[0].item {
+            Asset::Nft(NFT {
+                contract_address,
+                token_id: Some(token_id),
+            }) => (contract_address, token_id),
+            _ => return Ok(failed_simulation("Consideration is not NFT")),
+        };
+        let (token_address, amount) = match &offer.offer[0].item {
+            Asset::Cw20(CW20 {
+                contract_address,
+                amount,
+            }) => (contract_address, *amount),
+            _ => return Ok(failed_simulation("Invalid Offer funding type")),
+        };
+        let owner: cw721::OwnerOfResponse = match deps.querier.query_wasm_smart(
+            contract_address,
+            &Cw721QueryMsg::OwnerOf {
+                token_id: token_id.clone(),
+                include_expired: Some(false),
+            },
+        ) {
+            Ok(owner) => owner,
+            Err(_) => return Ok(failed_simulation(ContractError::NftNotFound {})),
+        };
+        let owner = Addr::unchecked(owner.owner);
+
+        // the nft is transferred to the offerer
+        let mut transfers = vec![SimulatedTransfer {
+            kind: TransferKind::Nft,
+            recipient: Some(offer.offerer.clone()),
+            asset: Asset::Nft(NFT {
+                contract_address: contract_address.clone(),
+                token_id: Some(token_id.clone()),
+            }),
+        }];
+        match royalty_payouts(
+            deps,
+            contract_address,
+            token_id,
+            Uint128::from(amount),
+            &owner,
+        ) {
+            Ok(payouts) => {
+                transfers.extend(payouts.into_iter().map(|(kind, recipient, amount)| {
+                    SimulatedTransfer {
+                        kind,
+                        recipient: Some(recipient),
+                        asset: Asset::Cw20(CW20 {
+                            contract_address: token_address.clone(),
+                            amount: amount.u128(),
+                        }),
+                    }
+                }))
+            }
+            Err(err) => return Ok(failed_simulation(err)),
+        }
+
+        // the same checks as accepting the offer, the cw20 tokens of the offerer are also checked
+        let error = if !self.is_order_valid(deps.storage, &offer)? {
+            Some(ContractError::OrderInvalidated {})
+        } else if offer
+            .end_time
+            .is_some_and(|end_time| end_time.is_expired(&env.block))
+        {
+            Some(ContractError::OrderExpired {})
+        } else if !has_cw20_funds(deps, &env, token_address, &offer.offerer, amount) {
+            Some(ContractError::InsufficientBalance {})
+        } else {
+            check_listing_approval(deps, &env, &owner, contract_address, token_id).err()
+        };
+
+        Ok(SimulationResponse {
+            transfers,
+            error: error.map(|err| err.to_string()),
+        })
+    }
+
+    // query the private listings created by a seller
+    pub fn query_private_listings_by_seller(
+        self,
+        deps: Deps,
+        env: Env,
+        seller: Addr,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    ) -> StdResult<ListingsResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+        let start: Option<Bound<ListingKey>> = start_after.map(|(contract_address, token_id)| {
+            Bound::exclusive(listing_key(&Addr::unchecked(contract_address), &token_id))
+        });
+        let listings = self
+            .listings
+            .idx
+            .seller
+            .prefix(seller)
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, listing)| listing))
+            .filter(|item| self.keep_valid_listing(deps, item))
+            .filter(|item| !matches!(item, Ok(listing) if !listing.is_private()))
             .take(limit)
+            .map(|item| item.map(|listing| listing.with_active_price(&env.block)))
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(ListingsResponse { listings })
+    }
+
+    // query the private listings reserved for a buyer
+    pub fn query_reserved_listings(
+        self,
+        deps: Deps,
+        env: Env,
+        buyer: Addr,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    ) -> StdResult<ListingsResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+        let start: Option<Bound<ListingKey>> = start_after.map(|(contract_address, token_id)| {
+            Bound::exclusive(listing_key(&Addr::unchecked(contract_address), &token_id))
+        });
+        let listings = self
+            .listings
+            .idx
+            .reserved_for
+            .prefix(buyer.to_string())
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, listing)| listing))
+            .filter(|item| self.keep_valid_listing(deps, item))
+            .take(limit)
+            .map(|item| item.map(|listing| listing.with_active_price(&env.block)))
             .collect::<StdResult<Vec<_>>>()?;
         Ok(ListingsResponse { listings })
     }
@@ -68,15 +398,14 @@ impl MarketplaceContract<'static> {
     }
 
     // query information of a specific offer
-    pub fn query_offer(
-        self,
-        deps: Deps,
-        contract_address: Addr,
-        token_id: String,
-        offerer: Addr,
-    ) -> StdResult<OrderComponents> {
-        let order_key = order_key(&offerer, &contract_address, &token_id);
-        self.offers.load(deps.storage, order_key)
+    pub fn query_offer(self, deps: Deps, order_id: OrderKey) -> StdResult<OrderComponents> {
+        let offer = self.offers.load(deps.storage, order_id)?;
+
+        // hide the offer invalidated by the counter of the offerer
+        if !self.is_order_valid(deps.storage, &offer)? {
+            return Err(StdError::not_found("Offer"));
+        }
+        Ok(offer)
     }
 
     // query all offers of a specific nft
@@ -85,19 +414,12 @@ impl MarketplaceContract<'static> {
         deps: Deps,
         contract_address: Addr,
         token_id: String,
-        start_after_offerer: Option<String>,
+        start_after: Option<OrderKey>,
         limit: Option<u32>,
     ) -> StdResult<OffersResponse> {
         let limit = limit.unwrap_or(30).min(30) as usize;
 
-        let start: Option<Bound<OrderKey>> = start_after_offerer.map(|offerer| {
-            let order_key = order_key(
-                &deps.api.addr_validate(&offerer).unwrap(),
-                &contract_address,
-                &token_id,
-            );
-            Bound::exclusive(order_key)
-        });
+        let start: Option<Bound<OrderKey>> = start_after.map(Bound::exclusive);
 
         // load offers
         let offers = self
@@ -105,8 +427,9 @@ impl MarketplaceContract<'static> {
             .idx
             .nfts
             .prefix((contract_address, token_id))
-            .range(deps.storage, start, None, Order::Descending)
+            .range(deps.storage, start, None, Order::Ascending)
             .map(|item| item.map(|(_, order)| order))
+            .filter(|item| self.keep_valid_order(deps, item))
             .take(limit)
             .collect::<StdResult<Vec<_>>>()?;
 
@@ -119,15 +442,12 @@ impl MarketplaceContract<'static> {
         self,
         deps: Deps,
         offerer: Addr,
-        start_after_nft: Option<NFT>,
+        start_after: Option<OrderKey>,
         limit: Option<u32>,
     ) -> StdResult<OffersResponse> {
         let limit = limit.unwrap_or(30).min(30) as usize;
 
-        let start: Option<Bound<OrderKey>> = start_after_nft.map(|nft| {
-            let order_key = order_key(&offerer, &nft.contract_address, &nft.token_id.unwrap());
-            Bound::exclusive(order_key)
-        });
+        let start: Option<Bound<OrderKey>> = start_after.map(Bound::exclusive);
 
         // load offers
         let offers = self
@@ -135,12 +455,566 @@ impl MarketplaceContract<'static> {
             .idx
             .users
             .prefix(offerer)
-            .range(deps.storage, start, None, Order::Descending)
+            .range(deps.storage, start, None, Order::Ascending)
             .map(|item| item.map(|(_, order)| order))
+            .filter(|item| self.keep_valid_order(deps, item))
             .take(limit)
             .collect::<StdResult<Vec<_>>>()?;
 
         // return offers
         Ok(OffersResponse { offers })
     }
+
+    // query information of a specific order
+    pub fn query_order(self, deps: Deps, order_id: OrderKey) -> StdResult<OrderComponents> {
+        let order = self.orders.load(deps.storage, order_id)?;
+
+        // hide the order invalidated by the counter of the offerer
+        if !self.is_order_valid(deps.storage, &order)? {
+            return Err(StdError::not_found("Order"));
+        }
+        Ok(order)
+    }
+
+    // query all orders trading a specific nft
+    pub fn query_nft_orders(
+        self,
+        deps: Deps,
+        contract_address: Addr,
+        token_id: String,
+        start_after: Option<OrderKey>,
+        limit: Option<u32>,
+    ) -> StdResult<OrdersResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+
+        let start: Option<Bound<OrderKey>> = start_after.map(Bound::exclusive);
+
+        let orders = self
+            .orders
+            .idx
+            .nfts
+            .prefix((contract_address, token_id))
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, order)| order))
+            .filter(|item| self.keep_valid_order(deps, item))
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(OrdersResponse { orders })
+    }
+
+    // query all orders of a specific user
+    pub fn query_user_orders(
+        self,
+        deps: Deps,
+        offerer: Addr,
+        start_after: Option<OrderKey>,
+        limit: Option<u32>,
+    ) -> StdResult<OrdersResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+
+        let start: Option<Bound<OrderKey>> = start_after.map(Bound::exclusive);
+
+        let orders = self
+            .orders
+            .idx
+            .users
+            .prefix(offerer)
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, order)| order))
+            .filter(|item| self.keep_valid_order(deps, item))
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(OrdersResponse { orders })
+    }
+
+    // query the hash of an order which the offerer must sign
+    pub fn query_signed_order_hash(self, env: Env, order: OrderComponents) -> StdResult<Binary> {
+        let order_hash = order_hash(&env.block.chain_id, &env.contract.address, &order)?;
+        Ok(Binary::from(order_hash))
+    }
+
+    // query the status of an order signed off-chain
+    pub fn query_signed_order_status(
+        self,
+        deps: Deps,
+        order_hash: Binary,
+    ) -> StdResult<Option<SignedOrderStatus>> {
+        self.signed_orders
+            .may_load(deps.storage, order_hash.as_slice())
+    }
+
+    // query the current counter of a user
+    pub fn query_counter(self, deps: Deps, user: Addr) -> StdResult<u64> {
+        self.user_counter(deps.storage, &user)
+    }
+
+    // query information of a specific bundle
+    pub fn query_bundle(self, deps: Deps, bundle_id: BundleKey) -> StdResult<Bundle> {
+        let bundle = self.bundles.load(deps.storage, bundle_id)?;
+
+        // hide the bundle invalidated by the counter of the seller or by moving one of its nfts
+        if !self.is_bundle_owned(deps, &bundle)? {
+            return Err(StdError::not_found("Bundle"));
+        }
+        Ok(bundle)
+    }
+
+    // query all bundles of a specific seller
+    pub fn query_bundles_by_seller(
+        self,
+        deps: Deps,
+        seller: Addr,
+        start_after: Option<BundleKey>,
+        limit: Option<u32>,
+    ) -> StdResult<BundlesResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+
+        let start: Option<Bound<BundleKey>> = start_after.map(Bound::exclusive);
+
+        let bundles = self
+            .bundles
+            .idx
+            .seller
+            .prefix(seller)
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, bundle)| bundle))
+            .filter(|item| match item {
+                Ok(bundle) => self.is_bundle_owned(deps, bundle).unwrap_or(false),
+                Err(_) => true,
+            })
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(BundlesResponse { bundles })
+    }
+
+    // query the permissions granted by an owner
+    pub fn query_delegations_by_owner(
+        self,
+        deps: Deps,
+        owner: Addr,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    ) -> StdResult<DelegationsResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+
+        let start: Option<Bound<(Addr, Addr)>> = start_after.map(|(delegate, contract_address)| {
+            Bound::exclusive((Addr::unchecked(delegate), Addr::unchecked(contract_address)))
+        });
+
+        let delegations = self
+            .delegations
+            .sub_prefix(owner)
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, delegation)| delegation))
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(DelegationsResponse { delegations })
+    }
+
+    // query the points and the rewards of a user for the epochs in which the user traded
+    pub fn query_user_rewards(
+        self,
+        deps: Deps,
+        user: Addr,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<UserRewardsResponse> {
+        let config = match self.rewards_config.may_load(deps.storage)? {
+            Some(config) => config,
+            None => return Ok(UserRewardsResponse { rewards: vec![] }),
+        };
+        let limit = limit.unwrap_or(30).min(30) as usize;
+        let start = start_after.map(Bound::exclusive);
+
+        let rewards = self
+            .user_points
+            .prefix(&user)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                let (epoch, points) = item?;
+                Ok(EpochRewards {
+                    epoch,
+                    points,
+                    pending: self.pending_rewards(deps.storage, &config, &user, epoch)?,
+                    claimed: self
+                        .claimed_rewards
+                        .may_load(deps.storage, (&user, epoch))?
+                        .unwrap_or_default(),
+                })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(UserRewardsResponse { rewards })
+    }
+
+    // query information of a specific reverse auction
+    pub fn query_reverse_auction(
+        self,
+        deps: Deps,
+        auction_id: ReverseAuctionKey,
+    ) -> StdResult<ReverseAuction> {
+        self.reverse_auctions.load(deps.storage, auction_id)
+    }
+
+    // query all reverse auctions
+    pub fn query_reverse_auctions(
+        self,
+        deps: Deps,
+        start_after: Option<ReverseAuctionKey>,
+        limit: Option<u32>,
+    ) -> StdResult<ReverseAuctionsResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+
+        let start: Option<Bound<ReverseAuctionKey>> = start_after.map(Bound::exclusive);
+
+        let auctions = self
+            .reverse_auctions
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, auction)| auction))
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(ReverseAuctionsResponse { auctions })
+    }
+
+    // query all sealed bids of a listing
+    pub fn query_sealed_bids(
+        self,
+        deps: Deps,
+        contract_address: Addr,
+        token_id: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<SealedBidsResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+
+        let start: Option<Bound<Addr>> =
+            start_after.map(|bidder| Bound::exclusive(Addr::unchecked(bidder)));
+
+        let bids = self
+            .sealed_bids
+            .prefix(listing_key(&contract_address, &token_id))
+            .range(deps.storage, start, None, Order::Ascending)
+            .map(|item| item.map(|(_, bid)| bid))
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(SealedBidsResponse { bids })
+    }
+
+    // query the pending balances of an address
+    pub fn query_pending_balance(
+        self,
+        deps: Deps,
+        address: Addr,
+    ) -> StdResult<PendingBalanceResponse> {
+        let mut balances = self
+            .pending_native
+            .prefix(&address)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                item.map(|(denom, amount)| PaymentAsset::Native {
+                    denom,
+                    amount: amount.u128(),
+                })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        for item in
+            self.pending_cw20
+                .prefix(&address)
+                .range(deps.storage, None, None, Order::Ascending)
+        {
+            let (contract_address, amount) = item?;
+            balances.push(PaymentAsset::Cw20 {
+                contract_address,
+                amount: amount.u128(),
+            });
+        }
+
+        Ok(PendingBalanceResponse { balances })
+    }
+
+    pub fn query_rental(
+        self,
+        deps: Deps,
+        contract_address: Addr,
+        token_id: String,
+    ) -> StdResult<Listing> {
+        self.rentals
+            .load(deps.storage, listing_key(&contract_address, &token_id))
+    }
+
+    pub fn query_installment_plan(
+        self,
+        deps: Deps,
+        contract_address: Addr,
+        token_id: String,
+    ) -> StdResult<InstallmentPlan> {
+        self.installment_plans
+            .load(deps.storage, listing_key(&contract_address, &token_id))
+    }
+
+    pub fn query_group_buy_contributions(
+        self,
+        deps: Deps,
+        group_buy_id: GroupBuyKey,
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    ) -> StdResult<GroupBuyContributionsResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+        let start = start_after.as_ref().map(Bound::exclusive);
+
+        let contributions = self
+            .group_buy_contributions
+            .prefix(group_buy_id)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                item.map(|(contributor, amount)| GroupBuyContribution {
+                    contributor,
+                    amount,
+                })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(GroupBuyContributionsResponse { contributions })
+    }
+
+    pub fn query_loans_by_borrower(
+        self,
+        deps: Deps,
+        borrower: Addr,
+        start_after: Option<OrderKey>,
+        limit: Option<u32>,
+    ) -> StdResult<LoansResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+        let start: Option<Bound<OrderKey>> = start_after.map(Bound::exclusive);
+
+        let loans = self
+            .loans
+            .idx
+            .borrower
+            .prefix(borrower)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, loan)| loan))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(LoansResponse { loans })
+    }
+
+    // the loans which are not funded have no lender
+    pub fn query_loans_by_lender(
+        self,
+        deps: Deps,
+        lender: Addr,
+        start_after: Option<OrderKey>,
+        limit: Option<u32>,
+    ) -> StdResult<LoansResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+        let start: Option<Bound<OrderKey>> = start_after.map(Bound::exclusive);
+
+        let loans = self
+            .loans
+            .idx
+            .lender
+            .prefix(lender.to_string())
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, loan)| loan))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(LoansResponse { loans })
+    }
+
+    pub fn query_loans_by_collection(
+        self,
+        deps: Deps,
+        contract_address: Addr,
+        start_after: Option<OrderKey>,
+        limit: Option<u32>,
+    ) -> StdResult<LoansResponse> {
+        let limit = limit.unwrap_or(30).min(30) as usize;
+        let start: Option<Bound<OrderKey>> = start_after.map(Bound::exclusive);
+
+        let loans = self
+            .loans
+            .idx
+            .collection
+            .prefix(contract_address)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, loan)| loan))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        Ok(LoansResponse { loans })
+    }
+
+    // query the user of a rented nft, games grant the user rights to this address
+    pub fn query_rental_user(
+        self,
+        deps: Deps,
+        env: Env,
+        contract_address: Addr,
+        token_id: String,
+    ) -> StdResult<RentalUserResponse> {
+        let rental = self
+            .rentals
+            .may_load(deps.storage, listing_key(&contract_address, &token_id))?;
+        match rental.map(|rental| rental.status) {
+            Some(ListingStatus::Rented { user, expires }) if expires > env.block.time => {
+                Ok(RentalUserResponse {
+                    user: Some(user),
+                    expires: Some(expires),
+                })
+            }
+            _ => Ok(RentalUserResponse {
+                user: None,
+                expires: None,
+            }),
+        }
+    }
+
+    // query the total referral fees earned by a referrer
+    pub fn query_referral_earnings(
+        self,
+        deps: Deps,
+        referrer: Addr,
+    ) -> StdResult<ReferralEarningsResponse> {
+        let mut earnings = self
+            .referral_native
+            .prefix(&referrer)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                item.map(|(denom, amount)| PaymentAsset::Native {
+                    denom,
+                    amount: amount.u128(),
+                })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        for item in
+            self.referral_cw20
+                .prefix(&referrer)
+                .range(deps.storage, None, None, Order::Ascending)
+        {
+            let (contract_address, amount) = item?;
+            earnings.push(PaymentAsset::Cw20 {
+                contract_address,
+                amount: amount.u128(),
+            });
+        }
+
+        Ok(ReferralEarningsResponse { earnings })
+    }
+
+    // a bundle can be bought only if it is valid and the seller still owns all of its nfts
+    fn is_bundle_owned(&self, deps: Deps, bundle: &Bundle) -> StdResult<bool> {
+        if !self.is_bundle_valid(deps.storage, bundle)? {
+            return Ok(false);
+        }
+        for nft in &bundle.nfts {
+            let owner_response: StdResult<cw721::OwnerOfResponse> = deps.querier.query_wasm_smart(
+                &nft.contract_address,
+                &Cw721QueryMsg::OwnerOf {
+                    token_id: nft.token_id.clone().unwrap_or_default(),
+                    include_expired: Some(false),
+                },
+            );
+            match owner_response {
+                Ok(owner_response) if owner_response.owner == bundle.seller => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    // used to filter the listings of a range, errors are kept so that they are returned when collecting
+    fn keep_valid_listing(&self, deps: Deps, item: &StdResult<Listing>) -> bool {
+        match item {
+            Ok(listing) => self
+                .is_listing_valid(deps.storage, listing)
+                .unwrap_or(false),
+            Err(_) => true,
+        }
+    }
+
+    // used to filter the orders of a range, errors are kept so that they are returned when collecting
+    fn keep_valid_order(&self, deps: Deps, item: &StdResult<OrderComponents>) -> bool {
+        match item {
+            Ok(order) => self.is_order_valid(deps.storage, order).unwrap_or(false),
+            Err(_) => true,
+        }
+    }
+}
+
+// the information and the royalty of a nft are None if the nft contract does not answer
+fn listing_info(deps: Deps, listing: Listing) -> ListingInfo {
+    let nft_info = deps
+        .querier
+        .query_wasm_smart(
+            &listing.contract_address,
+            &Cw721QueryMsg::NftInfo {
+                token_id: listing.token_id.clone(),
+            },
+        )
+        .ok();
+
+    // the royalties registered in the marketplace are used if the nft does not support cw2981
+    let royalties = match &listing.auction_config {
+        AuctionConfig::FixedPrice { price, .. } => query_royalty(
+            deps,
+            &listing.contract_address,
+            &listing.token_id,
+            price.amount,
+        )
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(address, royalty_amount)| RoyaltiesInfoResponse {
+            address: address.to_string(),
+            royalty_amount,
+        })
+        .collect(),
+        _ => vec![],
+    };
+
+    ListingInfo {
+        listing,
+        nft_info,
+        royalties,
+    }
+}
+
+fn failed_simulation(error: impl ToString) -> SimulationResponse {
+    SimulationResponse {
+        transfers: vec![],
+        error: Some(error.to_string()),
+    }
+}
+
+// check that the owner has the balance and the allowance to pay an amount of cw20 tokens
+fn has_cw20_funds(deps: Deps, env: &Env, token_address: &Addr, owner: &Addr, amount: u128) -> bool {
+    let balance: StdResult<cw20::BalanceResponse> = deps.querier.query_wasm_smart(
+        token_address,
+        &Cw20QueryMsg::Balance {
+            address: owner.to_string(),
+        },
+    );
+    let allowance: StdResult<cw20::AllowanceResponse> = deps.querier.query_wasm_smart(
+        token_address,
+        &Cw20QueryMsg::Allowance {
+            owner: owner.to_string(),
+            spender: env.contract.address.to_string(),
+        },
+    );
+    match (balance, allowance) {
+        (Ok(balance), Ok(allowance)) => {
+            balance.balance.u128() >= amount && allowance.allowance.u128() >= amount
+        }
+        _ => false,
+    }
 }
@@ -1,9 +1,13 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, BlockInfo, Coin};
+use cosmwasm_std::{Addr, Binary, BlockInfo, Coin, Order, StdResult, Storage, Timestamp, Uint128};
 use cw721::Expiration;
-use cw_storage_plus::{Index, IndexList, IndexedMap, Item, MultiIndex, UniqueIndex};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, UniqueIndex};
+use sha2::{Digest, Sha256};
 
-use crate::order_state::{orders, OfferIndexes, OrderComponents, OrderKey};
+use crate::order_state::{
+    generic_orders, orders, OfferIndexes, OrderComponents, OrderKey, PaymentAsset,
+    PaymentAssetInfo, SignedOrderStatus, NFT,
+};
 
 #[cw_serde]
 pub enum AuctionConfig {
@@ -11,17 +15,50 @@ pub enum AuctionConfig {
         price: Coin,
         start_time: Option<Expiration>, // we use expiration for convinience
         end_time: Option<Expiration>,   // it's required that start_time < end_time
+        // the price changes to the price of each step at its time, in the denom of the price
+        #[serde(default)]
+        price_schedule: Vec<PriceStep>,
     },
     Other {
         auction: AuctionContract,
         config: String,
     },
+    // bids are committed as hashes before bidding_end and revealed before reveal_end
+    // the highest bidder wins at the second highest price
+    SealedBid {
+        min_price: Coin,
+        bidding_end: Expiration,
+        reveal_end: Expiration, // it's required that bidding_end < reveal_end
+    },
+    // the buyer pays the deposit, then the rest of the price in equal installments every interval,
+    // the nft is escrowed in the marketplace until the final payment
+    // if an installment is missed, forfeit_bps of the paid funds go to the seller who gets the nft back
+    Installments {
+        price: Coin,
+        deposit: Uint128,
+        installments: u64,
+        interval: u64,
+        forfeit_bps: u64,
+    },
+    // the nft is escrowed in the marketplace and rented for a whole number of days, at most MAX_RENTAL_DAYS
+    Rental {
+        price_per_day: Coin,
+        max_days: u64,
+    },
+}
+
+#[cw_serde]
+pub struct PriceStep {
+    pub time: Timestamp,
+    pub price: Uint128,
 }
 
 #[cw_serde]
 pub enum ListingStatus {
     Ongoing {},
     Sold { buyer: Addr },
+    // the user rights of a rented nft belong to the renter until expires
+    Rented { user: Addr, expires: Timestamp },
 }
 
 impl ListingStatus {
@@ -29,6 +66,7 @@ impl ListingStatus {
         match self {
             ListingStatus::Ongoing {} => "ongoing",
             ListingStatus::Sold { .. } => "ended",
+            ListingStatus::Rented { .. } => "rented",
         }
         .to_string()
     }
@@ -44,6 +82,10 @@ pub struct Listing {
     pub seller: Addr,
     pub buyer: Option<Addr>, // buyer, will be initialized to None
     pub status: ListingStatus,
+    #[serde(default)]
+    pub counter: u64, // the counter of the seller when the listing was created
+    #[serde(default)]
+    pub reserved_for: Option<Addr>, // only this address can buy a private listing
 }
 
 impl Listing {
@@ -51,6 +93,48 @@ impl Listing {
         matches!(self.status, ListingStatus::Ongoing {})
     }
 
+    // a private listing is reserved for a single buyer and hidden from the public queries
+    pub fn is_private(&self) -> bool {
+        self.reserved_for.is_some()
+    }
+
+    // the listing at the price of the last step of its schedule reached at the block time
+    pub fn with_active_price(mut self, block_info: &BlockInfo) -> Self {
+        if let AuctionConfig::FixedPrice {
+            price,
+            price_schedule,
+            ..
+        } = &mut self.auction_config
+        {
+            if let Some(step) = price_schedule
+                .iter()
+                .rev()
+                .find(|step| step.time <= block_info.time)
+            {
+                price.amount = step.price;
+            }
+        }
+        self
+    }
+
+    // the lowest price which a fixed price listing can reach with its schedule
+    pub fn min_fixed_price(&self) -> Option<Coin> {
+        match &self.auction_config {
+            AuctionConfig::FixedPrice {
+                price,
+                price_schedule,
+                ..
+            } => {
+                let mut min_price = price.clone();
+                for step in price_schedule {
+                    min_price.amount = min_price.amount.min(step.price);
+                }
+                Some(min_price)
+            }
+            _ => None,
+        }
+    }
+
     // expired is when a listing status is still ongoing but has passed the end_time
     // we don't care about other statuses as checking for expired is only useful for ongoing listings
     pub fn is_expired(&self, block_info: &BlockInfo) -> bool {
@@ -60,7 +144,10 @@ impl Listing {
                     Some(time) => time.is_expired(block_info),
                     None => false,
                 },
-                AuctionConfig::Other { .. } => false,
+                AuctionConfig::Other { .. }
+                | AuctionConfig::Installments { .. }
+                | AuctionConfig::Rental { .. } => false,
+                AuctionConfig::SealedBid { reveal_end, .. } => reveal_end.is_expired(block_info),
             },
             _ => false,
         }
@@ -76,14 +163,25 @@ pub fn listing_key(contract_address: &Addr, token_id: &TokenId) -> ListingKey {
 
 // listings can be indexed by contract_address
 // contract_address can point to multiple listings
+// private listings can be found by their seller and by their reserved buyer
+// listings of a collection can be iterated by the denom and the amount of their fixed price, from the cheapest
+// a listing with a price schedule is indexed by the lowest price of its schedule
 pub struct ListingIndexes<'a> {
     pub contract_address: MultiIndex<'a, (String, Addr), Listing, ListingKey>,
+    pub seller: MultiIndex<'a, Addr, Listing, ListingKey>,
+    pub reserved_for: MultiIndex<'a, String, Listing, ListingKey>,
+    pub price: MultiIndex<'a, (Addr, String, u128), Listing, ListingKey>,
 }
 
 impl<'a> IndexList<Listing> for ListingIndexes<'a> {
     // this method returns a list of all indexes
     fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Listing>> + '_> {
-        let v: Vec<&dyn Index<Listing>> = vec![&self.contract_address];
+        let v: Vec<&dyn Index<Listing>> = vec![
+            &self.contract_address,
+            &self.seller,
+            &self.reserved_for,
+            &self.price,
+        ];
         Box::new(v.into_iter())
     }
 }
@@ -96,14 +194,324 @@ pub fn listings<'a>() -> IndexedMap<'a, ListingKey, Listing, ListingIndexes<'a>>
             "listings",
             "listings__contract_address",
         ),
+        seller: MultiIndex::new(
+            |_pk: &[u8], l: &Listing| l.seller.clone(),
+            "listings",
+            "listings__seller",
+        ),
+        reserved_for: MultiIndex::new(
+            |_pk: &[u8], l: &Listing| {
+                l.reserved_for
+                    .as_ref()
+                    .map(|buyer| buyer.to_string())
+                    .unwrap_or_default()
+            },
+            "listings",
+            "listings__reserved_for",
+        ),
+        // the listings which are not sold at a fixed price are indexed without denom
+        price: MultiIndex::new(
+            |_pk: &[u8], l: &Listing| {
+                let (denom, amount) = match l.min_fixed_price() {
+                    Some(price) => (price.denom, price.amount.u128()),
+                    None => (String::new(), u128::MAX),
+                };
+                (l.contract_address.clone(), denom, amount)
+            },
+            "listings",
+            "listings__price",
+        ),
     };
     IndexedMap::new("listings", indexes)
 }
 
+// a bundle sells several nfts, possibly of different collections, as a single lot
+#[cw_serde]
+pub struct Bundle {
+    pub bundle_id: BundleKey,
+    pub nfts: Vec<NFT>, // nfts of the bundle, all of them are transferred to the buyer
+    pub auction_config: AuctionConfig, // only fixed price is supported for bundles
+    pub seller: Addr,
+    pub counter: u64, // the counter of the seller when the bundle was created
+}
+
+impl Bundle {
+    // a bundle is expired when it has passed the end_time
+    pub fn is_expired(&self, block_info: &BlockInfo) -> bool {
+        match self.auction_config {
+            AuctionConfig::FixedPrice { end_time, .. } => match end_time {
+                Some(time) => time.is_expired(block_info),
+                None => false,
+            },
+            AuctionConfig::Other { .. }
+            | AuctionConfig::SealedBid { .. }
+            | AuctionConfig::Installments { .. }
+            | AuctionConfig::Rental { .. } => false,
+        }
+    }
+}
+
+// bundles share the id sequence of orders
+pub type BundleKey = u64;
+
+// bundles can be indexed by seller
+pub struct BundleIndexes<'a> {
+    pub seller: MultiIndex<'a, Addr, Bundle, BundleKey>,
+}
+
+impl<'a> IndexList<Bundle> for BundleIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Bundle>> + '_> {
+        let v: Vec<&dyn Index<Bundle>> = vec![&self.seller];
+        Box::new(v.into_iter())
+    }
+}
+
+// helper function create a IndexedMap for bundles
+pub fn bundles<'a>() -> IndexedMap<'a, BundleKey, Bundle, BundleIndexes<'a>> {
+    let indexes = BundleIndexes {
+        seller: MultiIndex::new(
+            |_pk: &[u8], b: &Bundle| b.seller.clone(),
+            "bundles",
+            "bundles__seller",
+        ),
+    };
+    IndexedMap::new("bundles", indexes)
+}
+
+// the lowest ask of a reverse auction
+#[cw_serde]
+pub struct Ask {
+    pub seller: Addr,
+    pub token_id: String,
+    pub price: Uint128,
+}
+
+// in a reverse auction, the buyer escrows the max price and holders compete with decreasing asks
+// the lowest ask at the end_time wins
+#[cw_serde]
+pub struct ReverseAuction {
+    pub auction_id: ReverseAuctionKey,
+    pub buyer: Addr,
+    pub nft: NFT, // the requested nft, any nft of the collection if token_id is None
+    pub max_price: Coin, // the escrowed funds, the unspent part is refunded to the buyer
+    pub end_time: Expiration,
+    pub best_ask: Option<Ask>,
+}
+
+// reverse auctions share the id sequence of orders
+pub type ReverseAuctionKey = u64;
+
+// a purchase of a listing with installments, the listing keeps the installments config
+#[cw_serde]
+pub struct InstallmentPlan {
+    pub listing: Listing,
+    pub buyer: Addr,
+    pub paid: Uint128,
+    pub installments_paid: u64,
+    pub next_due: Timestamp,
+    pub referrer: Option<Addr>, // receives the referral fee on the final payment
+}
+
+#[cw_serde]
+pub enum GroupBuyStatus {
+    Open {},
+    Completed {},
+    Refunded {},
+}
+
+// the contributors of a group buy pool their funds to buy a fixed price listing,
+// the nft is sent to the vault once the price is raised
+#[cw_serde]
+pub struct GroupBuy {
+    pub group_buy_id: GroupBuyKey,
+    pub creator: Addr,
+    pub contract_address: Addr,
+    pub token_id: String,
+    pub price: Coin, // the price of the listing when the group buy was created
+    pub vault: Addr,
+    pub deadline: Expiration, // the contributions are refunded if the price is not raised before
+    pub raised: Uint128,
+    pub status: GroupBuyStatus,
+}
+
+// group buys share the id sequence of orders
+pub type GroupBuyKey = u64;
+
+#[cw_serde]
+pub enum LoanStatus {
+    Requested {},
+    Active { lender: Addr, due: Timestamp },
+}
+
+// a loan is requested with an order which offers the escrowed nft as collateral,
+// its consideration is the principal paid by the lender to the borrower (start_amount)
+// and the repayment owed by the borrower at the due time (end_amount)
+#[cw_serde]
+pub struct Loan {
+    pub order: OrderComponents,
+    pub duration: u64, // seconds from the funding to the due time
+    pub status: LoanStatus,
+}
+
+impl Loan {
+    pub fn lender(&self) -> Option<&Addr> {
+        match &self.status {
+            LoanStatus::Requested {} => None,
+            LoanStatus::Active { lender, .. } => Some(lender),
+        }
+    }
+}
+
+pub struct LoanIndexes<'a> {
+    pub borrower: MultiIndex<'a, Addr, Loan, OrderKey>,
+    pub lender: MultiIndex<'a, String, Loan, OrderKey>,
+    pub collection: MultiIndex<'a, Addr, Loan, OrderKey>,
+}
+
+impl<'a> IndexList<Loan> for LoanIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Loan>> + '_> {
+        let v: Vec<&dyn Index<Loan>> = vec![&self.borrower, &self.lender, &self.collection];
+        Box::new(v.into_iter())
+    }
+}
+
+// helper function create a IndexedMap for loans, the loans share the id sequence of orders
+pub fn loans<'a>() -> IndexedMap<'a, OrderKey, Loan, LoanIndexes<'a>> {
+    let indexes = LoanIndexes {
+        borrower: MultiIndex::new(
+            |_pk: &[u8], l: &Loan| l.order.offerer.clone(),
+            "loans",
+            "loans__borrower",
+        ),
+        lender: MultiIndex::new(
+            |_pk: &[u8], l: &Loan| l.lender().map(Addr::to_string).unwrap_or_default(),
+            "loans",
+            "loans__lender",
+        ),
+        collection: MultiIndex::new(
+            |_pk: &[u8], l: &Loan| {
+                l.order
+                    .nft()
+                    .map(|(contract_address, _)| contract_address)
+                    .unwrap_or_else(|| Addr::unchecked(""))
+            },
+            "loans",
+            "loans__collection",
+        ),
+    };
+    IndexedMap::new("loans", indexes)
+}
+
+// a bid of a sealed bid auction, the amount is hidden in the commitment until it is revealed
+#[cw_serde]
+pub struct SealedBid {
+    pub bidder: Addr,
+    pub commitment: Binary,
+    pub deposit: Coin,     // the deposit must cover the revealed amount
+    pub committed_at: u64, // block height of the commitment, the earlier bid wins a tie
+    pub revealed_amount: Option<Uint128>,
+}
+
+// sealed bids are stored by the listing and the bidder
+pub type SealedBidKey = (Addr, TokenId, Addr);
+
+// the commitment of a sealed bid is sha256(bidder | amount as 16 big endian bytes | salt)
+// the bidder is included so that a commitment cannot be copied by another bidder
+pub fn sealed_bid_commitment(bidder: &Addr, amount: Uint128, salt: &str) -> Binary {
+    let mut hasher = Sha256::new();
+    hasher.update(bidder.as_bytes());
+    hasher.update(amount.u128().to_be_bytes());
+    hasher.update(salt.as_bytes());
+    Binary::from(hasher.finalize().to_vec())
+}
+
+// the actions a delegate can execute on behalf of the owner
+#[cw_serde]
+pub enum DelegatedAction {
+    List,
+    Cancel,
+    Offer,
+    AcceptOffer,
+}
+
+// a permission granted by an owner to a delegate, scoped to a collection
+#[cw_serde]
+pub struct Delegation {
+    pub owner: Addr,
+    pub delegate: Addr,
+    pub contract_address: Addr,
+    pub actions: Vec<DelegatedAction>,
+    pub spending_cap: Option<Uint128>, // the total amount the delegate can offer, unlimited if None
+    pub spent: Uint128,
+    pub expires: Expiration,
+}
+
+// delegations are stored by (owner, delegate, contract_address)
+pub type DelegationKey = (Addr, Addr, Addr);
+
+// a royalty of a collection registered in the marketplace, the rate is in basis points of the sale price
+#[cw_serde]
+pub struct RoyaltyRecipient {
+    pub address: Addr,
+    pub bps: u64,
+}
+
+#[cw_serde]
+pub enum RewardDistribution {
+    Mint, // the marketplace mints the reward token
+    Pool, // the marketplace transfers the reward token from its balance
+}
+
+// the rewards of an epoch are shared between the traders in proportion to their points
+// a trade gives its buyer and its seller points equal to its volume times the multiplier of the collection
+#[cw_serde]
+pub struct RewardsConfig {
+    pub token: Addr,
+    pub start_time: Timestamp,
+    pub epoch_duration: u64, // in seconds
+    pub rewards_per_epoch: Uint128,
+    pub distribution: RewardDistribution,
+    // only the payments in these assets give points, there are none before they are configured
+    #[serde(default)]
+    pub eligible_assets: Vec<RewardAsset>,
+}
+
+impl RewardsConfig {
+    pub fn epoch(&self, time: Timestamp) -> u64 {
+        time.seconds().saturating_sub(self.start_time.seconds()) / self.epoch_duration
+    }
+
+    // the volume of a payment weighted by its asset, zero if its asset is not eligible
+    pub fn weighted_volume(&self, payment: &PaymentAsset) -> Uint128 {
+        let info = payment.info();
+        self.eligible_assets
+            .iter()
+            .find(|eligible| eligible.asset == info)
+            .map(|eligible| {
+                Uint128::from(payment.amount()).multiply_ratio(eligible.weight_bps, 10_000u64)
+            })
+            .unwrap_or_default()
+    }
+}
+
+// a payment asset eligible for rewards, the weight converts its amounts to a common volume
+#[cw_serde]
+pub struct RewardAsset {
+    pub asset: PaymentAssetInfo,
+    pub weight_bps: u64,
+}
+
+// the multiplier of the collections without a multiplier, in basis points
+pub const DEFAULT_REWARD_MULTIPLIER: u64 = 10_000;
+
 #[cw_serde]
 pub struct Config {
     pub owner: Addr,
     pub vaura_address: Addr,
+    #[serde(default)]
+    pub pull_payments: bool, // proceeds and refunds are credited to pending balances to be withdrawn
+    #[serde(default)]
+    pub referral_fee_bps: u64, // the share of the sale price paid to the referrer of a purchase
 }
 
 // we use this struct in the migration
@@ -157,6 +565,40 @@ pub struct MarketplaceContract<'a> {
         IndexedMap<'a, AuctionContractKey, AuctionContract, AuctionContractIndexes<'a>>,
 
     pub offers: IndexedMap<'a, OrderKey, OrderComponents, OfferIndexes<'a>>,
+    pub next_order_id: Item<'a, OrderKey>,
+    pub orders: IndexedMap<'a, OrderKey, OrderComponents, OfferIndexes<'a>>,
+    pub signed_orders: Map<'a, &'a [u8], SignedOrderStatus>,
+    pub counters: Map<'a, &'a Addr, u64>,
+    pub bundles: IndexedMap<'a, BundleKey, Bundle, BundleIndexes<'a>>,
+    pub reverse_auctions: Map<'a, ReverseAuctionKey, ReverseAuction>,
+    pub sealed_bids: Map<'a, SealedBidKey, SealedBid>,
+    pub pending_native: Map<'a, (&'a Addr, &'a str), Uint128>,
+    pub pending_cw20: Map<'a, (&'a Addr, &'a Addr), Uint128>,
+    // the auto accept prices are stored by (owner, contract_address, token_id)
+    // the token_id is empty for the rule of a whole collection
+    pub auto_accepts: Map<'a, (&'a Addr, &'a Addr, &'a str), Uint128>,
+    pub delegations: Map<'a, DelegationKey, Delegation>,
+    // the royalties of the collections which do not support cw2981
+    pub royalty_registry: Map<'a, &'a Addr, Vec<RoyaltyRecipient>>,
+    // the contracts notified of the listings, offers and sales
+    pub hooks: Item<'a, Vec<Addr>>,
+    pub rewards_config: Item<'a, RewardsConfig>,
+    pub reward_multipliers: Map<'a, &'a Addr, u64>,
+    // the points are stored by epoch, and by (user, epoch) for the points of a user
+    pub epoch_points: Map<'a, u64, Uint128>,
+    // the rewards of an epoch, set to the rewards_per_epoch of the config when its first points accrue
+    pub epoch_rewards: Map<'a, u64, Uint128>,
+    pub user_points: Map<'a, (&'a Addr, u64), Uint128>,
+    pub claimed_rewards: Map<'a, (&'a Addr, u64), Uint128>,
+    // the total referral fees earned by a referrer, by denom or by cw20 token
+    pub referral_native: Map<'a, (&'a Addr, &'a str), Uint128>,
+    pub referral_cw20: Map<'a, (&'a Addr, &'a Addr), Uint128>,
+    // the nfts escrowed for rent, listed with a rental auction config
+    pub rentals: Map<'a, ListingKey, Listing>,
+    pub loans: IndexedMap<'a, OrderKey, Loan, LoanIndexes<'a>>,
+    pub installment_plans: Map<'a, ListingKey, InstallmentPlan>,
+    pub group_buys: Map<'a, GroupBuyKey, GroupBuy>,
+    pub group_buy_contributions: Map<'a, (GroupBuyKey, &'a Addr), Uint128>,
 }
 
 // impl default for MarketplaceContract
@@ -168,7 +610,122 @@ impl Default for MarketplaceContract<'static> {
             auction_contracts: auction_contracts(),
 
             offers: orders(),
+            next_order_id: Item::<OrderKey>::new("next_order_id"),
+            orders: generic_orders(),
+            signed_orders: Map::new("signed_orders"),
+            counters: Map::new("counters"),
+            bundles: bundles(),
+            reverse_auctions: Map::new("reverse_auctions"),
+            sealed_bids: Map::new("sealed_bids"),
+            pending_native: Map::new("pending_native"),
+            pending_cw20: Map::new("pending_cw20"),
+            auto_accepts: Map::new("auto_accepts"),
+            delegations: Map::new("delegations"),
+            royalty_registry: Map::new("royalty_registry"),
+            hooks: Item::new("hooks"),
+            rewards_config: Item::new("rewards_config"),
+            reward_multipliers: Map::new("reward_multipliers"),
+            epoch_points: Map::new("epoch_points"),
+            epoch_rewards: Map::new("epoch_rewards"),
+            user_points: Map::new("user_points"),
+            claimed_rewards: Map::new("claimed_rewards"),
+            referral_native: Map::new("referral_native"),
+            referral_cw20: Map::new("referral_cw20"),
+            rentals: Map::new("rentals"),
+            loans: loans(),
+            installment_plans: Map::new("installment_plans"),
+            group_buys: Map::new("group_buys"),
+            group_buy_contributions: Map::new("group_buy_contributions"),
+        }
+    }
+}
+
+impl<'a> MarketplaceContract<'a> {
+    // returns the rewards of a user for an epoch which are not claimed yet
+    // the rewards of the current epoch change with the trades until the epoch ends
+    pub fn pending_rewards(
+        &self,
+        storage: &dyn Storage,
+        config: &RewardsConfig,
+        user: &Addr,
+        epoch: u64,
+    ) -> StdResult<Uint128> {
+        if self.claimed_rewards.has(storage, (user, epoch)) {
+            return Ok(Uint128::zero());
+        }
+        let points = self
+            .user_points
+            .may_load(storage, (user, epoch))?
+            .unwrap_or_default();
+        if points.is_zero() {
+            return Ok(Uint128::zero());
         }
+        let total_points = self.epoch_points.load(storage, epoch)?;
+        let rewards = self
+            .epoch_rewards
+            .may_load(storage, epoch)?
+            .unwrap_or(config.rewards_per_epoch);
+        Ok(rewards.multiply_ratio(points, total_points))
+    }
+
+    // returns the next order id and increases the counter
+    pub fn generate_order_id(&self, storage: &mut dyn Storage) -> StdResult<OrderKey> {
+        let order_id = self.next_order_id.may_load(storage)?.unwrap_or(1);
+        self.next_order_id.save(storage, &(order_id + 1))?;
+        Ok(order_id)
+    }
+
+    // returns the current counter of a user, all orders created with a lower counter are invalid
+    pub fn user_counter(&self, storage: &dyn Storage, user: &Addr) -> StdResult<u64> {
+        Ok(self.counters.may_load(storage, user)?.unwrap_or_default())
+    }
+
+    // a listing is valid as long as the counter of the seller has not moved past it
+    pub fn is_listing_valid(&self, storage: &dyn Storage, listing: &Listing) -> StdResult<bool> {
+        Ok(listing.counter >= self.user_counter(storage, &listing.seller)?)
+    }
+
+    // returns the price from which the offers for a nft are accepted automatically
+    // the rule of the nft takes precedence over the rule of its collection
+    pub fn auto_accept_price(
+        &self,
+        storage: &dyn Storage,
+        owner: &Addr,
+        contract_address: &Addr,
+        token_id: &str,
+    ) -> StdResult<Option<Uint128>> {
+        match self
+            .auto_accepts
+            .may_load(storage, (owner, contract_address, token_id))?
+        {
+            Some(price) => Ok(Some(price)),
+            None => self
+                .auto_accepts
+                .may_load(storage, (owner, contract_address, "")),
+        }
+    }
+
+    // returns true if a sealed bid has been committed to the listing
+    pub fn has_sealed_bids(&self, storage: &dyn Storage, listing_key: &ListingKey) -> bool {
+        self.sealed_bids
+            .prefix(listing_key.clone())
+            .keys_raw(storage, None, None, Order::Ascending)
+            .next()
+            .is_some()
+    }
+
+    // a bundle is valid as long as the counter of the seller has not moved past it
+    pub fn is_bundle_valid(&self, storage: &dyn Storage, bundle: &Bundle) -> StdResult<bool> {
+        Ok(bundle.counter >= self.user_counter(storage, &bundle.seller)?)
+    }
+
+    // an order is valid as long as the counter of the offerer has not moved past it
+    pub fn is_order_valid(
+        &self,
+        storage: &dyn Storage,
+        order: &OrderComponents,
+    ) -> StdResult<bool> {
+        Ok(order.counter >= self.user_counter(storage, &order.offerer)?)
     }
 }
 
@@ -2,21 +2,30 @@
 mod tests {
     use crate::contract::*;
     use crate::integration_tests::env::{instantiate_contracts, ADMIN, USER_1};
-    use crate::msg::{ExecuteMsg, InstantiateMsg, ListingsResponse, QueryMsg};
-    use crate::order_state::NFT;
-    use crate::state::{contract, AuctionConfig, Config, ListingStatus};
+    use crate::msg::{
+        ExecuteMsg, InstantiateMsg, ListingInfo, ListingsResponse, ListingsWithInfoResponse,
+        OffersResponse, PendingBalanceResponse, QueryMsg, SimulatedTransfer, SimulationResponse,
+        TransferKind,
+    };
+    use crate::order_state::{
+        Asset, OrderComponents, PaymentAsset, PaymentAssetInfo, SignedOrderStatus, CW20, NATIVE,
+        NFT,
+    };
+    use crate::state::{contract, AuctionConfig, Config, DelegatedAction, Listing, ListingStatus};
     use crate::ContractError;
 
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier};
     use cosmwasm_std::{
         coins, from_binary, to_binary, Addr, BankMsg, Coin, ContractResult, CosmosMsg, DepsMut,
-        MemoryStorage, OwnedDeps, Response, StdError, SubMsg, Timestamp, Uint128, WasmMsg,
-        WasmQuery,
+        MemoryStorage, OwnedDeps, Response, StdError, StdResult, SubMsg, Timestamp, Uint128,
+        WasmMsg, WasmQuery,
     };
     use cw20::Expiration as Cw20Expiration;
     use cw2981_royalties::msg::{Cw2981QueryMsg, RoyaltiesInfoResponse};
-    use cw2981_royalties::{ExecuteMsg as Cw2981ExecuteMsg, QueryMsg as Cw721QueryMsg};
-    use cw721::{Approval, ApprovalResponse, Expiration as Cw721Expiration, OwnerOfResponse};
+    use cw2981_royalties::{ExecuteMsg as Cw2981ExecuteMsg, Metadata, QueryMsg as Cw721QueryMsg};
+    use cw721::{
+        Approval, ApprovalResponse, Expiration as Cw721Expiration, NftInfoResponse, OwnerOfResponse,
+    };
 
     use cosmwasm_std::{BalanceResponse as BankBalanceResponse, BankQuery, Querier, QueryRequest};
     use cw20::BalanceResponse;
@@ -117,6 +126,16 @@ mod tests {
                                 );
                                 cosmwasm_std::SystemResult::Ok(result)
                             }
+                            Cw721QueryMsg::NftInfo { token_id } => {
+                                let result = ContractResult::Ok(
+                                    to_binary(&NftInfoResponse {
+                                        token_uri: Some(format!("ipfs://{}", token_id)),
+                                        extension: Metadata::default(),
+                                    })
+                                    .unwrap(),
+                                );
+                                cosmwasm_std::SystemResult::Ok(result)
+                            }
                             Cw721QueryMsg::OwnerOf {
                                 token_id,
                                 include_expired: _,
@@ -217,6 +236,75 @@ mod tests {
         instantiate(deps, mock_env(), info, msg)
     }
 
+    // fixtures shared by the multi-test modules
+    mod helpers {
+        use super::*;
+        use cw2981_royalties::MintMsg;
+        use cw721_base::msg::ExecuteMsg as Cw721ExecuteMsg;
+        use cw_multi_test::App;
+
+        // mint a nft to the owner and approve the marketplace to transfer it
+        pub(super) fn mint_and_approve(
+            app: &mut App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+            token_id: &str,
+            owner: &str,
+        ) {
+            let mint_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: owner.to_string(),
+                token_uri: None,
+                extension: Metadata::default(),
+            });
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(cw2981_address),
+                &mint_msg,
+                &[],
+            )
+            .unwrap();
+
+            let approve_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Approve {
+                spender: marketplace_address.to_string(),
+                token_id: token_id.to_string(),
+                expires: None,
+            };
+            app.execute_contract(
+                Addr::unchecked(owner),
+                Addr::unchecked(cw2981_address),
+                &approve_msg,
+                &[],
+            )
+            .unwrap();
+        }
+
+        pub(super) fn owner_of(app: &App, cw2981_address: &str, token_id: &str) -> String {
+            let res: cw721::OwnerOfResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    cw2981_address,
+                    &Cw721QueryMsg::OwnerOf {
+                        token_id: token_id.to_string(),
+                        include_expired: None,
+                    },
+                )
+                .unwrap();
+            res.owner
+        }
+
+        pub(super) fn native_balance(app: &App, address: &str) -> Uint128 {
+            let res: BankBalanceResponse = app
+                .wrap()
+                .query(&QueryRequest::Bank(BankQuery::Balance {
+                    address: address.to_string(),
+                    denom: NATIVE_DENOM.to_string(),
+                }))
+                .unwrap();
+            res.amount.amount
+        }
+    }
+
     #[test]
     fn proper_initialization() {
         let deps = mock_deps();
@@ -249,7 +337,9 @@ mod tests {
                     },
                     start_time,
                     end_time,
+                    price_schedule: vec![],
                 },
+                reserved_for: None,
             };
             let info = mock_info(sender, &coins(1000, "uaura"));
             execute(deps, mock_env(), info, msg)
@@ -296,7 +386,9 @@ mod tests {
                     },
                     start_time: None,
                     end_time: None,
+                    price_schedule: vec![],
                 },
+                reserved_for: None,
             };
             let info = mock_info("another_user", &[]);
             let response = execute(deps.as_mut(), mock_env(), info, msg);
@@ -364,8 +456,10 @@ mod tests {
             let listing = contract()
                 .query_listing(
                     deps.as_ref(),
+                    mock_env(),
                     Addr::unchecked(MOCK_CW2981_ADDR),
                     "1".to_string(),
+                    None,
                 )
                 .unwrap();
             assert_eq!(listing.token_id, "1");
@@ -384,8 +478,10 @@ mod tests {
             // assert error on load listing
             let res = contract().query_listing(
                 deps.as_ref(),
+                mock_env(),
                 Addr::unchecked(MOCK_CW2981_ADDR),
                 "1".to_string(),
+                None,
             );
             println!("Response: {:?}", &res);
             assert!(res.is_err());
@@ -485,6 +581,7 @@ mod tests {
             let query_res = contract()
                 .query_listings_by_contract_address(
                     deps.as_ref(),
+                    mock_env(),
                     ListingStatus::Ongoing {}.name(),
                     Addr::unchecked(MOCK_CW2981_ADDR),
                     Some("".to_string()),
@@ -519,6 +616,116 @@ mod tests {
             assert_eq!(query_res.listings.len(), 4);
         }
 
+        #[test]
+        fn only_reserved_buyer_can_buy_private_listing() {
+            let mut deps = mock_deps();
+
+            let msg = ExecuteMsg::ListNft {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "2".to_string(),
+                auction_config: AuctionConfig::FixedPrice {
+                    price: Coin {
+                        denom: "uaura".to_string(),
+                        amount: Uint128::from(100u128),
+                    },
+                    start_time: None,
+                    end_time: None,
+                    price_schedule: vec![],
+                },
+                reserved_for: Some("buyer".to_string()),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+            // the private listing is hidden from the public query
+            let query_msg = QueryMsg::ListingsByContractAddress {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                start_after: None,
+                limit: None,
+            };
+            let query_res = from_binary::<ListingsResponse>(
+                &query(deps.as_ref(), mock_env(), query_msg).unwrap(),
+            )
+            .unwrap();
+            assert!(query_res.listings.is_empty());
+
+            // but visible to the seller and the reserved buyer
+            let query_msg = QueryMsg::PrivateListingsBySeller {
+                seller: "owner".to_string(),
+                start_after: None,
+                limit: None,
+            };
+            let query_res = from_binary::<ListingsResponse>(
+                &query(deps.as_ref(), mock_env(), query_msg).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(query_res.listings.len(), 1);
+
+            let query_msg = QueryMsg::ReservedListings {
+                buyer: "buyer".to_string(),
+                start_after: None,
+                limit: None,
+            };
+            let query_res = from_binary::<ListingsResponse>(
+                &query(deps.as_ref(), mock_env(), query_msg).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(query_res.listings.len(), 1);
+            assert_eq!(
+                query_res.listings[0].reserved_for,
+                Some(Addr::unchecked("buyer"))
+            );
+
+            // the private listing is returned by its key only to the seller and the reserved buyer
+            let listing_query = |viewer: Option<&str>| {
+                query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::Listing {
+                        contract_address: MOCK_CW2981_ADDR.to_string(),
+                        token_id: "2".to_string(),
+                        viewer: viewer.map(str::to_string),
+                    },
+                )
+            };
+            for viewer in [None, Some("other")] {
+                assert_eq!(
+                    listing_query(viewer).unwrap_err(),
+                    StdError::not_found("Listing")
+                );
+            }
+            for viewer in ["owner", "buyer"] {
+                let listing =
+                    from_binary::<Listing>(&listing_query(Some(viewer)).unwrap()).unwrap();
+                assert_eq!(listing.reserved_for, Some(Addr::unchecked("buyer")));
+            }
+
+            // other users cannot buy the private listing
+            let msg = ExecuteMsg::Buy {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "2".to_string(),
+                referrer: None,
+            };
+            let response = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("other", &coins(100, "uaura")),
+                msg.clone(),
+            );
+            assert_eq!(
+                response.unwrap_err().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+
+            // the reserved buyer can buy it
+            let response = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("buyer", &coins(100, "uaura")),
+                msg,
+            );
+            assert!(response.is_ok());
+        }
+
         #[test]
         fn cannot_buy_non_existent_listing() {
             let mut deps = mock_deps();
@@ -526,6 +733,7 @@ mod tests {
             let msg = ExecuteMsg::Buy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "1".to_string(),
+                referrer: None,
             };
 
             let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
@@ -564,6 +772,7 @@ mod tests {
             let msg = ExecuteMsg::Buy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "1".to_string(),
+                referrer: None,
             };
 
             let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
@@ -590,13 +799,14 @@ mod tests {
             let msg = ExecuteMsg::Buy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "1".to_string(),
+                referrer: None,
             };
             let mock_info_wrong_sender = mock_info("owner", &coins(100, "uaura"));
 
             let response = execute(deps.as_mut(), mock_env(), mock_info_wrong_sender, msg);
             match response {
                 Ok(_) => panic!("Expected error"),
-                Err(ContractError::CustomError { .. }) => {}
+                Err(ContractError::OwnerCannotBuy {}) => {}
                 Err(e) => panic!("Unexpected error: {}", e),
             }
         }
@@ -619,6 +829,7 @@ mod tests {
             let msg = ExecuteMsg::Buy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "1".to_string(),
+                referrer: None,
             };
             let mock_info_buyer = mock_info("buyer", &coins(99, "uaura"));
 
@@ -649,6 +860,7 @@ mod tests {
             let msg = ExecuteMsg::Buy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "1".to_string(),
+                referrer: None,
             };
             let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
 
@@ -687,41 +899,90 @@ mod tests {
         }
 
         #[test]
-        fn cannot_buy_listing_before_start_time() {
+        fn buy_listing_with_pull_payments() {
             let mut deps = mock_deps();
 
+            // only the owner can enable pull payments
+            let msg = ExecuteMsg::EditPullPayments { enabled: true };
+            assert!(execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("other", &[]),
+                msg.clone()
+            )
+            .is_err());
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
             create_listing(
                 deps.as_mut(),
                 "owner",
                 Addr::unchecked(MOCK_CW2981_ADDR),
                 "1",
-                Some(Cw721Expiration::AtTime(Timestamp::from_nanos(
-                    1_600_000_001,
-                ))),
+                None,
                 None,
             )
             .unwrap();
 
-            // try buy before start time
             let msg = ExecuteMsg::Buy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "1".to_string(),
+                referrer: None,
             };
             let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
 
-            let mut env = mock_env();
-            env.block.time = Timestamp::from_nanos(1_600_000_000);
-            let response = execute(deps.as_mut(), env, mock_info_buyer, msg);
-            println!("Response: {:?}", &response);
-            match response {
-                Ok(_) => panic!("Expected error"),
-                Err(ContractError::CustomError { .. }) => {}
-                Err(e) => panic!("Unexpected error: {}", e),
-            }
+            // the funds are kept by the contract, only the nft is transferred
+            let response = execute(deps.as_mut(), mock_env(), mock_info_buyer, msg).unwrap();
+            assert_eq!(1, response.messages.len());
+
+            let query_msg = QueryMsg::PendingBalance {
+                address: "creator".to_string(),
+            };
+            let res: PendingBalanceResponse =
+                from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            assert_eq!(
+                res.balances,
+                vec![PaymentAsset::Native {
+                    denom: "uaura".to_string(),
+                    amount: 10
+                }]
+            );
+
+            // the creator withdraws the royalty
+            let response = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::Withdraw {
+                    asset: PaymentAssetInfo::Native {
+                        denom: "uaura".to_string(),
+                    },
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                response.messages,
+                vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".to_string(),
+                    amount: vec![cosmwasm_std::coin(10, "uaura")],
+                }))]
+            );
+
+            // nothing is left to withdraw
+            let response = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::Withdraw {
+                    asset: PaymentAssetInfo::Native {
+                        denom: "uaura".to_string(),
+                    },
+                },
+            );
+            assert!(response.is_err());
         }
 
         #[test]
-        fn cannot_buy_listing_after_end_time() {
+        fn simulate_buy_returns_transfers_and_failure() {
             let mut deps = mock_deps();
 
             create_listing(
@@ -729,96 +990,434 @@ mod tests {
                 "owner",
                 Addr::unchecked(MOCK_CW2981_ADDR),
                 "1",
-                None,
                 Some(Cw721Expiration::AtTime(Timestamp::from_nanos(
-                    1_600_000_000,
+                    1_600_000_001,
                 ))),
+                None,
             )
             .unwrap();
 
-            // try buy after end time
-            let msg = ExecuteMsg::Buy {
+            let query_msg = QueryMsg::SimulateBuy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "1".to_string(),
+                buyer: None,
+                referrer: None,
             };
-            let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
+            let expected_transfers = vec![
+                SimulatedTransfer {
+                    kind: TransferKind::Nft,
+                    recipient: None,
+                    asset: Asset::Nft(NFT {
+                        contract_address: Addr::unchecked(MOCK_CW2981_ADDR),
+                        token_id: Some("1".to_string()),
+                    }),
+                },
+                SimulatedTransfer {
+                    kind: TransferKind::Royalty,
+                    recipient: Some(Addr::unchecked("creator")),
+                    asset: Asset::Native(NATIVE {
+                        denom: "uaura".to_string(),
+                        amount: 10,
+                    }),
+                },
+                SimulatedTransfer {
+                    kind: TransferKind::SellerProceeds,
+                    recipient: Some(Addr::unchecked("owner")),
+                    asset: Asset::Native(NATIVE {
+                        denom: "uaura".to_string(),
+                        amount: 90,
+                    }),
+                },
+            ];
 
+            // the listing cannot be bought before its start time
             let mut env = mock_env();
-            env.block.time = Timestamp::from_nanos(1_600_000_001);
-            let response = execute(deps.as_mut(), env, mock_info_buyer, msg);
-            println!("Response: {:?}", &response);
-            match response {
-                Ok(_) => panic!("Expected error"),
-                Err(ContractError::CustomError { .. }) => {}
-                Err(e) => panic!("Unexpected error: {}", e),
-            }
+            env.block.time = Timestamp::from_nanos(1_600_000_000);
+            let res: SimulationResponse =
+                from_binary(&query(deps.as_ref(), env, query_msg.clone()).unwrap()).unwrap();
+            assert_eq!(res.transfers, expected_transfers);
+            assert_eq!(
+                res.error,
+                Some(
+                    ContractError::AuctionNotStarted {
+                        start_time: Cw721Expiration::AtTime(Timestamp::from_nanos(1_600_000_001))
+                    }
+                    .to_string()
+                )
+            );
+
+            // the listing can be bought after its start time
+            let res: SimulationResponse =
+                from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            assert_eq!(res.transfers, expected_transfers);
+            assert_eq!(res.error, None);
+
+            // a missing listing cannot be bought
+            let query_msg = QueryMsg::SimulateBuy {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "2".to_string(),
+                buyer: None,
+                referrer: None,
+            };
+            let res: SimulationResponse =
+                from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            assert!(res.transfers.is_empty());
+            assert!(res.error.is_some());
         }
 
         #[test]
-        fn can_buy_listing_with_0_royalty() {
+        fn query_listings_with_nft_info_and_royalty() {
             let mut deps = mock_deps();
 
-            create_listing(
-                deps.as_mut(),
-                "owner",
-                Addr::unchecked(MOCK_CW2981_ADDR),
-                "2",
-                None,
-                None,
-            )
-            .unwrap();
+            for token_id in ["1", "2"] {
+                create_listing(
+                    deps.as_mut(),
+                    "owner",
+                    Addr::unchecked(MOCK_CW2981_ADDR),
+                    token_id,
+                    None,
+                    None,
+                )
+                .unwrap();
+            }
 
-            // buyer try to buy
-            let msg = ExecuteMsg::Buy {
+            let query_msg = QueryMsg::ListingsWithInfoByContractAddress {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
-                token_id: "2".to_string(),
+                start_after: None,
+                limit: None,
             };
-            let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
+            let res: ListingsWithInfoResponse =
+                from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            assert_eq!(res.listings.len(), 2);
 
-            let response = execute(deps.as_mut(), mock_env(), mock_info_buyer, msg).unwrap();
-            assert_eq!(2, response.messages.len());
-            println!("Response: {:?}", &response);
+            // the royalty is computed at the listed price
+            let info = &res.listings[0];
+            assert_eq!(info.listing.token_id, "1");
             assert_eq!(
-                response.messages[0],
-                SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: MOCK_CW2981_ADDR.to_string(),
-                    funds: vec![],
-                    msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
-                        recipient: "buyer".to_string(),
-                        token_id: "2".to_string(),
-                    })
-                    .unwrap(),
-                })),
-                "should transfer nft to buyer"
+                info.nft_info.as_ref().unwrap().token_uri,
+                Some("ipfs://1".to_string())
             );
             assert_eq!(
-                response.messages[1],
-                SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-                    to_address: "owner".to_string(),
-                    amount: vec![cosmwasm_std::coin(100, "uaura")],
-                })),
-                "should transfer all funds to owner"
+                info.royalties,
+                vec![RoyaltiesInfoResponse {
+                    address: "creator".to_string(),
+                    royalty_amount: Uint128::from(10u128),
+                }]
             );
+
+            let query_msg = QueryMsg::ListingWithInfo {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "2".to_string(),
+                viewer: None,
+            };
+            let info: ListingInfo =
+                from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            assert_eq!(info.listing.token_id, "2");
+            assert!(info.royalties.is_empty());
         }
 
         #[test]
-        fn can_buy_listing_without_royalty() {
+        fn delegate_can_list_and_cancel_for_owner() {
             let mut deps = mock_deps();
 
+            // the bot cannot list the nft of the owner without permission
+            let response = create_listing(
+                deps.as_mut(),
+                "bot",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "1",
+                None,
+                None,
+            );
+            assert_eq!(
+                response.unwrap_err().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+
+            // the owner allows the bot to list and cancel the nfts of the collection
+            let msg = ExecuteMsg::GrantPermission {
+                delegate: "bot".to_string(),
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                actions: vec![DelegatedAction::List, DelegatedAction::Cancel],
+                spending_cap: None,
+                expires: Cw721Expiration::Never {},
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+            // the listing created by the bot is sold by the owner
             create_listing(
                 deps.as_mut(),
-                "owner",
+                "bot",
                 Addr::unchecked(MOCK_CW2981_ADDR),
-                "2",
+                "1",
                 None,
                 None,
             )
             .unwrap();
-
-            // buyer try to buy
-            let msg = ExecuteMsg::Buy {
+            let query_msg = QueryMsg::Listing {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
-                token_id: "2".to_string(),
+                token_id: "1".to_string(),
+                viewer: None,
+            };
+            let listing: Listing =
+                from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            assert_eq!(listing.seller, Addr::unchecked("owner"));
+
+            // the bot cancels the listing
+            let msg = ExecuteMsg::Cancel {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "1".to_string(),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), msg).unwrap();
+
+            // the bot cannot list anymore once the permission is revoked
+            let msg = ExecuteMsg::RevokePermission {
+                delegate: "bot".to_string(),
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+            let response = create_listing(
+                deps.as_mut(),
+                "bot",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "1",
+                None,
+                None,
+            );
+            assert!(response.is_err());
+        }
+
+        #[test]
+        fn sweep_collection_buys_cheapest_listings() {
+            let mut deps = mock_deps();
+
+            // list 3 nfts with different prices
+            for (token_id, price) in [("1", 300u128), ("2", 100u128), ("3", 200u128)] {
+                let msg = ExecuteMsg::ListNft {
+                    contract_address: MOCK_CW2981_ADDR.to_string(),
+                    token_id: token_id.to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: cosmwasm_std::coin(price, "uaura"),
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                };
+                execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+            }
+
+            // a cheaper listing priced in another denom is not swept
+            let msg = ExecuteMsg::ListNft {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "4".to_string(),
+                auction_config: AuctionConfig::FixedPrice {
+                    price: cosmwasm_std::coin(50, "ustake"),
+                    start_time: None,
+                    end_time: None,
+                    price_schedule: vec![],
+                },
+                reserved_for: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+            // the buyer sweeps at most 3 nfts priced at most 250
+            let msg = ExecuteMsg::SweepCollection {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                max_items: 3,
+                max_price_per_item: Uint128::from(250u128),
+            };
+            let response = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("buyer", &coins(1000, "uaura")),
+                msg,
+            )
+            .unwrap();
+
+            // the 2 cheapest nfts are bought and the unspent funds are refunded
+            let token_ids: Vec<_> = response
+                .attributes
+                .iter()
+                .filter(|attr| attr.key == "token_id")
+                .map(|attr| attr.value.as_str())
+                .collect();
+            assert_eq!(token_ids, vec!["2", "3"]);
+            assert_eq!(
+                response.messages.last().unwrap(),
+                &SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "buyer".to_string(),
+                    amount: vec![cosmwasm_std::coin(700, "uaura")],
+                }))
+            );
+
+            // only the most expensive listing and the listing in another denom are left
+            let query_msg = QueryMsg::ListingsByContractAddress {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                start_after: None,
+                limit: None,
+            };
+            let res: ListingsResponse =
+                from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            let token_ids: Vec<_> = res
+                .listings
+                .iter()
+                .map(|listing| listing.token_id.as_str())
+                .collect();
+            assert_eq!(token_ids, vec!["1", "4"]);
+
+            // nothing is left to sweep under the max price
+            let msg = ExecuteMsg::SweepCollection {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                max_items: 3,
+                max_price_per_item: Uint128::from(250u128),
+            };
+            let response = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("buyer", &coins(1000, "uaura")),
+                msg,
+            );
+            assert!(response.is_err());
+        }
+
+        #[test]
+        fn cannot_buy_listing_before_start_time() {
+            let mut deps = mock_deps();
+
+            create_listing(
+                deps.as_mut(),
+                "owner",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "1",
+                Some(Cw721Expiration::AtTime(Timestamp::from_nanos(
+                    1_600_000_001,
+                ))),
+                None,
+            )
+            .unwrap();
+
+            // try buy before start time
+            let msg = ExecuteMsg::Buy {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "1".to_string(),
+                referrer: None,
+            };
+            let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
+
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_nanos(1_600_000_000);
+            let response = execute(deps.as_mut(), env, mock_info_buyer, msg);
+            println!("Response: {:?}", &response);
+            match response {
+                Ok(_) => panic!("Expected error"),
+                Err(ContractError::AuctionNotStarted { .. }) => {}
+                Err(e) => panic!("Unexpected error: {}", e),
+            }
+        }
+
+        #[test]
+        fn cannot_buy_listing_after_end_time() {
+            let mut deps = mock_deps();
+
+            create_listing(
+                deps.as_mut(),
+                "owner",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "1",
+                None,
+                Some(Cw721Expiration::AtTime(Timestamp::from_nanos(
+                    1_600_000_000,
+                ))),
+            )
+            .unwrap();
+
+            // try buy after end time
+            let msg = ExecuteMsg::Buy {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "1".to_string(),
+                referrer: None,
+            };
+            let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
+
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_nanos(1_600_000_001);
+            let response = execute(deps.as_mut(), env, mock_info_buyer, msg);
+            println!("Response: {:?}", &response);
+            match response {
+                Ok(_) => panic!("Expected error"),
+                Err(ContractError::AuctionEnded { .. }) => {}
+                Err(e) => panic!("Unexpected error: {}", e),
+            }
+        }
+
+        #[test]
+        fn can_buy_listing_with_0_royalty() {
+            let mut deps = mock_deps();
+
+            create_listing(
+                deps.as_mut(),
+                "owner",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "2",
+                None,
+                None,
+            )
+            .unwrap();
+
+            // buyer try to buy
+            let msg = ExecuteMsg::Buy {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "2".to_string(),
+                referrer: None,
+            };
+            let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
+
+            let response = execute(deps.as_mut(), mock_env(), mock_info_buyer, msg).unwrap();
+            assert_eq!(2, response.messages.len());
+            println!("Response: {:?}", &response);
+            assert_eq!(
+                response.messages[0],
+                SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: MOCK_CW2981_ADDR.to_string(),
+                    funds: vec![],
+                    msg: to_binary(&Cw2981ExecuteMsg::TransferNft {
+                        recipient: "buyer".to_string(),
+                        token_id: "2".to_string(),
+                    })
+                    .unwrap(),
+                })),
+                "should transfer nft to buyer"
+            );
+            assert_eq!(
+                response.messages[1],
+                SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "owner".to_string(),
+                    amount: vec![cosmwasm_std::coin(100, "uaura")],
+                })),
+                "should transfer all funds to owner"
+            );
+        }
+
+        #[test]
+        fn can_buy_listing_without_royalty() {
+            let mut deps = mock_deps();
+
+            create_listing(
+                deps.as_mut(),
+                "owner",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "2",
+                None,
+                None,
+            )
+            .unwrap();
+
+            // buyer try to buy
+            let msg = ExecuteMsg::Buy {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "2".to_string(),
+                referrer: None,
             };
             let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
 
@@ -866,6 +1465,7 @@ mod tests {
             let msg = ExecuteMsg::Buy {
                 contract_address: MOCK_CW2981_ADDR.to_string(),
                 token_id: "3".to_string(),
+                referrer: None,
             };
             let mock_info_buyer = mock_info("buyer", &coins(100, "uaura"));
 
@@ -894,6 +1494,101 @@ mod tests {
                 "should transfer all funds to owner"
             );
         }
+
+        #[test]
+        fn increment_counter_invalidates_listings() {
+            let mut deps = mock_deps();
+
+            create_listing(
+                deps.as_mut(),
+                "owner",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "1",
+                None,
+                None,
+            )
+            .unwrap();
+
+            // owner increments the counter
+            let msg = ExecuteMsg::IncrementCounter {};
+            execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+
+            let counter: u64 = from_binary(
+                &query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::Counter {
+                        user: "owner".to_string(),
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(counter, 1);
+
+            // the listing is hidden from the queries
+            let res = query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Listing {
+                    contract_address: MOCK_CW2981_ADDR.to_string(),
+                    token_id: "1".to_string(),
+                    viewer: None,
+                },
+            );
+            assert!(res.is_err());
+
+            let query_res = from_binary::<ListingsResponse>(
+                &query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::ListingsByContractAddress {
+                        contract_address: MOCK_CW2981_ADDR.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(query_res.listings.len(), 0);
+
+            // the listing cannot be bought
+            let msg = ExecuteMsg::Buy {
+                contract_address: MOCK_CW2981_ADDR.to_string(),
+                token_id: "1".to_string(),
+                referrer: None,
+            };
+            let response = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("buyer", &coins(100, "uaura")),
+                msg.clone(),
+            );
+            match response {
+                Ok(_) => panic!("Expected error"),
+                Err(ContractError::OrderInvalidated {}) => {}
+                Err(e) => panic!("Unexpected error: {}", e),
+            }
+
+            // a new listing uses the new counter
+            create_listing(
+                deps.as_mut(),
+                "owner",
+                Addr::unchecked(MOCK_CW2981_ADDR),
+                "1",
+                None,
+                None,
+            )
+            .unwrap();
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("buyer", &coins(100, "uaura")),
+                msg,
+            )
+            .unwrap();
+        }
     }
 
     // fn create_offer(
@@ -1157,7 +1852,7 @@ mod tests {
             );
             assert_eq!(
                 response.err().unwrap().source().unwrap().to_string(),
-                ContractError::Unauthorized {}.to_string()
+                cw20_base::ContractError::Unauthorized {}.to_string()
             );
 
             // query balance of USER_1 in twilight token
@@ -1324,6 +2019,7 @@ mod tests {
     mod accept_offer {
         use crate::integration_tests::env::NATIVE_DENOM;
 
+        use super::helpers::{mint_and_approve, owner_of};
         use super::*;
         use cw20::{BalanceResponse, Cw20QueryMsg};
         use cw2981_royalties::{Metadata, MintMsg};
@@ -1407,6 +2103,7 @@ mod tests {
                 },
                 funds_amount: MOCK_OFFER_CW20_PRICE,
                 end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                offerer: None,
             };
 
             // offerer (USER_1) creates offer
@@ -1454,12 +2151,9 @@ mod tests {
 
             // prepare accept offer message
             let accept_offer_msg = ExecuteMsg::AcceptNftOffer {
-                offerer: USER_1.to_string(),
-                nft: NFT {
-                    contract_address: Addr::unchecked(cw2981_address.clone()),
-                    token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
-                },
+                order_id: 1,
                 funds_amount: MOCK_OFFER_CW20_PRICE,
+                referrer: None,
             };
 
             // owner (ADMIN) accepts offer
@@ -1511,5 +2205,4996 @@ mod tests {
 
             assert_eq!(res.balance, Uint128::from(MOCK_OFFER_CW20_PRICE));
         }
+
+        // the offers of a user are invalidated when the user increments the counter
+        #[test]
+        fn increment_counter_invalidates_offers() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            let mint_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Mint(MintMsg {
+                token_id: MOCK_OFFER_NFT_TOKEN_ID_1.to_string(),
+                owner: ADMIN.to_string(),
+                token_uri: None,
+                extension: Metadata::default(),
+            });
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(cw2981_address.clone()),
+                &mint_msg,
+                &[],
+            )
+            .unwrap();
+
+            let offer_nft_msg = ExecuteMsg::OfferNft {
+                nft: NFT {
+                    contract_address: Addr::unchecked(cw2981_address.clone()),
+                    token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                },
+                funds_amount: MOCK_OFFER_CW20_PRICE,
+                end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                offerer: None,
+            };
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &offer_nft_msg,
+                &[],
+            )
+            .unwrap();
+
+            // USER_1 invalidates all of their offers
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::IncrementCounter {},
+                &[],
+            )
+            .unwrap();
+
+            let res: OffersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::UserOffers {
+                        offerer: USER_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.offers.len(), 0);
+
+            // the offer cannot be accepted
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::AcceptNftOffer {
+                    order_id: 1,
+                    funds_amount: MOCK_OFFER_CW20_PRICE,
+                    referrer: None,
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::OrderInvalidated {}.to_string()
+            );
+        }
+
+        // user can create multiple offers on the same nft, each with its own order id
+        #[test]
+        fn user_can_create_multiple_offers_on_same_nft() {
+            // get integration test app and contracts
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            // mint cw2981 token to ADMIN
+            let mint_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Mint(MintMsg {
+                token_id: MOCK_OFFER_NFT_TOKEN_ID_1.to_string(),
+                owner: ADMIN.to_string(),
+                token_uri: None,
+                extension: Metadata::default(),
+            });
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(cw2981_address.clone()),
+                &mint_msg,
+                &[],
+            )
+            .unwrap();
+
+            // USER_1 creates two offers with different prices
+            for price in [MOCK_OFFER_CW20_PRICE, MOCK_OFFER_CW20_PRICE * 2] {
+                let offer_nft_msg = ExecuteMsg::OfferNft {
+                    nft: NFT {
+                        contract_address: Addr::unchecked(cw2981_address.clone()),
+                        token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                    },
+                    funds_amount: price,
+                    end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                    offerer: None,
+                };
+                app.execute_contract(
+                    Addr::unchecked(USER_1),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &offer_nft_msg,
+                    &[],
+                )
+                .unwrap();
+            }
+
+            // both offers are stored
+            let res: OffersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::NftOffers {
+                        contract_address: cw2981_address.clone(),
+                        token_id: MOCK_OFFER_NFT_TOKEN_ID_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.offers.len(), 2);
+            assert_eq!(res.offers[0].order_id, 1);
+            assert_eq!(res.offers[1].order_id, 2);
+
+            // the owner of the nft cannot cancel the offer of USER_1
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CancelOffer { order_ids: vec![1] },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+
+            // USER_1 cancels the first offer only
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CancelOffer { order_ids: vec![1] },
+                &[],
+            )
+            .unwrap();
+
+            let res: OffersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::UserOffers {
+                        offerer: USER_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.offers.len(), 1);
+            assert_eq!(res.offers[0].order_id, 2);
+        }
+
+        // offers at or above the auto accept price of the owner are accepted immediately
+        #[test]
+        fn offer_above_auto_accept_price_is_accepted() {
+            // get integration test app and contracts
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let cw20_address = contracts[2].contract_addr.clone();
+
+            // mint cw2981 token to ADMIN and approve marketplace
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                MOCK_OFFER_NFT_TOKEN_ID_1,
+                ADMIN,
+            );
+
+            // USER_1 converts native token to twilight token
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: USER_1.to_string(),
+                    amount: coins(100000000, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw20_address),
+                &cw20::Cw20ExecuteMsg::Mint {
+                    recipient: USER_1.to_string(),
+                    amount: Uint128::from(100000000u128),
+                },
+                &coins(100000000, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // ADMIN accepts automatically the offers for the collection at or above twice the price
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::SetAutoAccept {
+                    contract_address: cw2981_address.clone(),
+                    token_id: None,
+                    min_price: Uint128::from(MOCK_OFFER_CW20_PRICE * 2),
+                },
+                &[],
+            )
+            .unwrap();
+            let res: Option<Uint128> = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::AutoAccept {
+                        owner: ADMIN.to_string(),
+                        contract_address: cw2981_address.clone(),
+                        token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                    },
+                )
+                .unwrap();
+            assert_eq!(res, Some(Uint128::from(MOCK_OFFER_CW20_PRICE * 2)));
+
+            // an offer below the auto accept price is only stored, then one above it is accepted
+            for price in [MOCK_OFFER_CW20_PRICE, MOCK_OFFER_CW20_PRICE * 2] {
+                let offer_nft_msg = ExecuteMsg::OfferNft {
+                    nft: NFT {
+                        contract_address: Addr::unchecked(cw2981_address.clone()),
+                        token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                    },
+                    funds_amount: price,
+                    end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                    offerer: None,
+                };
+                app.execute_contract(
+                    Addr::unchecked(USER_1),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &offer_nft_msg,
+                    &[],
+                )
+                .unwrap();
+
+                let expected_owner = if price < MOCK_OFFER_CW20_PRICE * 2 {
+                    ADMIN
+                } else {
+                    USER_1
+                };
+                assert_eq!(
+                    owner_of(&app, &cw2981_address, MOCK_OFFER_NFT_TOKEN_ID_1),
+                    expected_owner
+                );
+            }
+
+            // the accepted offer is removed, the other one is kept
+            let res: OffersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::NftOffers {
+                        contract_address: cw2981_address,
+                        token_id: MOCK_OFFER_NFT_TOKEN_ID_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.offers.len(), 1);
+            assert_eq!(res.offers[0].order_id, 1);
+        }
+        // the simulation of accepting an offer returns the reason it would fail
+        #[test]
+        fn simulate_accept_offer_returns_transfers_and_failure() {
+            // get integration test app and contracts
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let cw20_address = contracts[2].contract_addr.clone();
+
+            // mint cw2981 token to ADMIN without approving marketplace
+            let mint_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Mint(MintMsg {
+                token_id: MOCK_OFFER_NFT_TOKEN_ID_1.to_string(),
+                owner: ADMIN.to_string(),
+                token_uri: None,
+                extension: Metadata::default(),
+            });
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(cw2981_address.clone()),
+                &mint_msg,
+                &[],
+            )
+            .unwrap();
+
+            // USER_1 offers without holding twilight token
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::OfferNft {
+                    nft: NFT {
+                        contract_address: Addr::unchecked(cw2981_address.clone()),
+                        token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                    },
+                    funds_amount: MOCK_OFFER_CW20_PRICE,
+                    end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                    offerer: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            let simulate = |app: &cw_multi_test::App| -> SimulationResponse {
+                app.wrap()
+                    .query_wasm_smart(
+                        marketplace_address.clone(),
+                        &QueryMsg::SimulateAcceptOffer { order_id: 1 },
+                    )
+                    .unwrap()
+            };
+
+            // the creator is the owner, so the whole amount is paid to the owner
+            let res = simulate(&app);
+            assert_eq!(
+                res.transfers,
+                vec![
+                    SimulatedTransfer {
+                        kind: TransferKind::Nft,
+                        recipient: Some(Addr::unchecked(USER_1)),
+                        asset: Asset::Nft(NFT {
+                            contract_address: Addr::unchecked(cw2981_address.clone()),
+                            token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                        }),
+                    },
+                    SimulatedTransfer {
+                        kind: TransferKind::SellerProceeds,
+                        recipient: Some(Addr::unchecked(ADMIN)),
+                        asset: Asset::Cw20(CW20 {
+                            contract_address: Addr::unchecked(cw20_address.clone()),
+                            amount: MOCK_OFFER_CW20_PRICE,
+                        }),
+                    },
+                ]
+            );
+            assert_eq!(
+                res.error,
+                Some(ContractError::InsufficientBalance {}.to_string())
+            );
+
+            // USER_1 converts native token to twilight token
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: USER_1.to_string(),
+                    amount: coins(100000000, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw20_address),
+                &cw20::Cw20ExecuteMsg::Mint {
+                    recipient: USER_1.to_string(),
+                    amount: Uint128::from(100000000u128),
+                },
+                &coins(100000000, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // the marketplace is not approved to transfer the nft
+            assert_eq!(
+                simulate(&app).error,
+                Some(ContractError::ApprovalMustNeverExpire {}.to_string())
+            );
+
+            let approve_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Approve {
+                spender: marketplace_address.clone(),
+                token_id: MOCK_OFFER_NFT_TOKEN_ID_1.to_string(),
+                expires: None,
+            };
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(cw2981_address),
+                &approve_msg,
+                &[],
+            )
+            .unwrap();
+            assert_eq!(simulate(&app).error, None);
+        }
+
+        // a delegate can offer on behalf of a user within the spending cap
+        #[test]
+        fn delegate_offers_are_limited_by_spending_cap() {
+            // get integration test app and contracts
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            // mint cw2981 token to ADMIN
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                MOCK_OFFER_NFT_TOKEN_ID_1,
+                ADMIN,
+            );
+
+            // USER_1 allows the bot to offer up to 1.5 times the offer price
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::GrantPermission {
+                    delegate: "bot".to_string(),
+                    contract_address: cw2981_address.clone(),
+                    actions: vec![DelegatedAction::Offer],
+                    spending_cap: Some(Uint128::from(MOCK_OFFER_CW20_PRICE * 3 / 2)),
+                    expires: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                },
+                &[],
+            )
+            .unwrap();
+
+            let offer_nft_msg = ExecuteMsg::OfferNft {
+                nft: NFT {
+                    contract_address: Addr::unchecked(cw2981_address.clone()),
+                    token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                },
+                funds_amount: MOCK_OFFER_CW20_PRICE,
+                end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                offerer: Some(USER_1.to_string()),
+            };
+
+            // the offer of the bot is made by USER_1
+            app.execute_contract(
+                Addr::unchecked("bot"),
+                Addr::unchecked(marketplace_address.clone()),
+                &offer_nft_msg,
+                &[],
+            )
+            .unwrap();
+            let res: OrderComponents = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::Offer { order_id: 1 },
+                )
+                .unwrap();
+            assert_eq!(res.offerer, Addr::unchecked(USER_1));
+
+            // the second offer exceeds the spending cap
+            let res = app.execute_contract(
+                Addr::unchecked("bot"),
+                Addr::unchecked(marketplace_address.clone()),
+                &offer_nft_msg,
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::SpendingCapExceeded {
+                    spending_cap: Uint128::from(MOCK_OFFER_CW20_PRICE * 3 / 2)
+                }
+                .to_string()
+            );
+
+            // another user cannot be used as offerer
+            let res = app.execute_contract(
+                Addr::unchecked("bot"),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::OfferNft {
+                    nft: NFT {
+                        contract_address: Addr::unchecked(cw2981_address),
+                        token_id: Some(MOCK_OFFER_NFT_TOKEN_ID_1.to_string()),
+                    },
+                    funds_amount: MOCK_OFFER_CW20_PRICE,
+                    end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                    offerer: Some(ADMIN.to_string()),
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+        }
+    }
+
+    mod order {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::msg::OrdersResponse;
+        use crate::order_state::{
+            Asset, ConsiderationItem, ItemType, OfferItem, OrderComponents, CW20, NATIVE,
+        };
+
+        const TOKEN_ID_1: &str = "1";
+        const TOKEN_ID_2: &str = "2";
+        const BUYER: &str = "buyer";
+
+        fn nft_item(cw2981_address: &str, token_id: &str) -> Asset {
+            Asset::Nft(NFT {
+                contract_address: Addr::unchecked(cw2981_address),
+                token_id: Some(token_id.to_string()),
+            })
+        }
+
+        // users can swap a nft for another nft
+        #[test]
+        fn can_swap_nft_for_nft() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                TOKEN_ID_1,
+                ADMIN,
+            );
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                TOKEN_ID_2,
+                USER_1,
+            );
+
+            // ADMIN offers token 1 for token 2
+            let create_order_msg = ExecuteMsg::CreateOrder {
+                offer: vec![OfferItem {
+                    item_type: ItemType::CW721,
+                    item: nft_item(&cw2981_address, TOKEN_ID_1),
+                    start_amount: 1,
+                    end_amount: 1,
+                }],
+                consideration: vec![ConsiderationItem {
+                    item_type: ItemType::CW721,
+                    item: nft_item(&cw2981_address, TOKEN_ID_2),
+                    start_amount: 1,
+                    end_amount: 1,
+                    recipient: Addr::unchecked(ADMIN),
+                }],
+                start_time: None,
+                end_time: None,
+            };
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &create_order_msg,
+                &[],
+            )
+            .unwrap();
+
+            // the order can be queried by the offered nft and by the offerer
+            let res: OrdersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::NftOrders {
+                        contract_address: cw2981_address.clone(),
+                        token_id: TOKEN_ID_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.orders.len(), 1);
+
+            let res: OrdersResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::UserOrders {
+                        offerer: ADMIN.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.orders.len(), 1);
+
+            // the offerer cannot fulfill the order
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::FulfillOrder { order_id: 1 },
+                &[],
+            );
+            assert!(res.is_err());
+
+            // USER_1 fulfills the order
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::FulfillOrder { order_id: 1 },
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(owner_of(&app, &cw2981_address, TOKEN_ID_1), USER_1);
+            assert_eq!(owner_of(&app, &cw2981_address, TOKEN_ID_2), ADMIN);
+
+            // the order is removed
+            let res: StdResult<OrderComponents> = app
+                .wrap()
+                .query_wasm_smart(marketplace_address, &QueryMsg::Order { order_id: 1 });
+            assert!(res.is_err());
+        }
+
+        // users can sell a bundle of nft and native tokens for cw20 tokens
+        #[test]
+        fn can_trade_nft_and_native_bundle_for_cw20() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let cw20_address = contracts[2].contract_addr.clone();
+
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                TOKEN_ID_1,
+                ADMIN,
+            );
+
+            // USER_1 gets some cw20 tokens
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: USER_1.to_string(),
+                    amount: coins(MOCK_OFFER_CW20_PRICE, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw20_address.clone()),
+                &cw20::Cw20ExecuteMsg::Mint {
+                    recipient: USER_1.to_string(),
+                    amount: Uint128::from(MOCK_OFFER_CW20_PRICE),
+                },
+                &coins(MOCK_OFFER_CW20_PRICE, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // ADMIN offers token 1 and 100 native tokens for cw20 tokens
+            let create_order_msg = ExecuteMsg::CreateOrder {
+                offer: vec![
+                    OfferItem {
+                        item_type: ItemType::CW721,
+                        item: nft_item(&cw2981_address, TOKEN_ID_1),
+                        start_amount: 1,
+                        end_amount: 1,
+                    },
+                    OfferItem {
+                        item_type: ItemType::NATIVE,
+                        item: Asset::Native(NATIVE {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: 100,
+                        }),
+                        start_amount: 100,
+                        end_amount: 100,
+                    },
+                ],
+                consideration: vec![ConsiderationItem {
+                    item_type: ItemType::CW20,
+                    item: Asset::Cw20(CW20 {
+                        contract_address: Addr::unchecked(cw20_address.clone()),
+                        amount: MOCK_OFFER_CW20_PRICE,
+                    }),
+                    start_amount: MOCK_OFFER_CW20_PRICE,
+                    end_amount: MOCK_OFFER_CW20_PRICE,
+                    recipient: Addr::unchecked(ADMIN),
+                }],
+                start_time: None,
+                end_time: None,
+            };
+
+            // the native tokens must be escrowed
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &create_order_msg,
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InsufficientFunds {}.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &create_order_msg,
+                &coins(100, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // USER_1 fulfills the order
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::FulfillOrder { order_id: 1 },
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(owner_of(&app, &cw2981_address, TOKEN_ID_1), USER_1);
+
+            assert_eq!(native_balance(&app, USER_1), Uint128::from(100u128));
+
+            let res: BalanceResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    cw20_address,
+                    &cw20::Cw20QueryMsg::Balance {
+                        address: ADMIN.to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.balance, Uint128::from(MOCK_OFFER_CW20_PRICE));
+        }
+
+        // the royalties of the nfts are carved out of the tokens paying for them
+        #[test]
+        fn order_selling_nft_pays_royalty() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                TOKEN_ID_1,
+                USER_1,
+            );
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: BUYER.to_string(),
+                    amount: coins(1000, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+
+            // USER_1 sells token 1 for 1000 native tokens
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CreateOrder {
+                    offer: vec![OfferItem {
+                        item_type: ItemType::CW721,
+                        item: nft_item(&cw2981_address, TOKEN_ID_1),
+                        start_amount: 1,
+                        end_amount: 1,
+                    }],
+                    consideration: vec![ConsiderationItem {
+                        item_type: ItemType::NATIVE,
+                        item: Asset::Native(NATIVE {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: 1000,
+                        }),
+                        start_amount: 1000,
+                        end_amount: 1000,
+                        recipient: Addr::unchecked(USER_1),
+                    }],
+                    start_time: None,
+                    end_time: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            let admin_balance = app
+                .wrap()
+                .query_balance(ADMIN, NATIVE_DENOM)
+                .unwrap()
+                .amount;
+            let seller_balance = app
+                .wrap()
+                .query_balance(USER_1, NATIVE_DENOM)
+                .unwrap()
+                .amount;
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::FulfillOrder { order_id: 1 },
+                &coins(1000, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // the royalty of 20% is paid to ADMIN as creator
+            assert_eq!(owner_of(&app, &cw2981_address, TOKEN_ID_1), BUYER);
+            assert_eq!(
+                app.wrap()
+                    .query_balance(ADMIN, NATIVE_DENOM)
+                    .unwrap()
+                    .amount,
+                admin_balance + Uint128::from(200u128)
+            );
+            assert_eq!(
+                app.wrap()
+                    .query_balance(USER_1, NATIVE_DENOM)
+                    .unwrap()
+                    .amount,
+                seller_balance + Uint128::from(800u128)
+            );
+        }
+
+        // an order cannot be fulfilled with a nft of another owner
+        #[test]
+        fn cannot_fulfill_order_with_nft_of_other() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                TOKEN_ID_1,
+                ADMIN,
+            );
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                TOKEN_ID_2,
+                ADMIN,
+            );
+
+            // ADMIN asks for token 2 which ADMIN also owns and approved
+            let create_order_msg = ExecuteMsg::CreateOrder {
+                offer: vec![OfferItem {
+                    item_type: ItemType::NATIVE,
+                    item: Asset::Native(NATIVE {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: 100,
+                    }),
+                    start_amount: 100,
+                    end_amount: 100,
+                }],
+                consideration: vec![ConsiderationItem {
+                    item_type: ItemType::CW721,
+                    item: nft_item(&cw2981_address, TOKEN_ID_2),
+                    start_amount: 1,
+                    end_amount: 1,
+                    recipient: Addr::unchecked(USER_1),
+                }],
+                start_time: None,
+                end_time: None,
+            };
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &create_order_msg,
+                &coins(100, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // USER_1 does not own token 2, so the order cannot be fulfilled
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::FulfillOrder { order_id: 1 },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+            assert_eq!(owner_of(&app, &cw2981_address, TOKEN_ID_2), ADMIN);
+
+            // ADMIN cancels the order and gets the escrowed tokens back
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CancelOrder { order_id: 1 },
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(native_balance(&app, &marketplace_address), Uint128::zero());
+        }
+    }
+
+    mod signed_order {
+        use super::*;
+        use crate::integration_tests::env::{instantiate_contracts_with_api, SignatureApi};
+        use crate::order_state::{
+            Asset, ConsiderationItem, ItemType, OfferItem, OrderComponents, OrderType,
+            SignedOrderPayload, NATIVE,
+        };
+        use cosmwasm_std::{to_vec, Binary};
+        use cw2981_royalties::{Metadata, MintMsg};
+        use cw721_base::msg::ExecuteMsg as Cw721ExecuteMsg;
+        use k256::ecdsa::signature::Signer;
+        use k256::ecdsa::{Signature, SigningKey};
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+        use ripemd::Ripemd160;
+        use sha2::{Digest, Sha256};
+
+        const TOKEN_ID: &str = "1";
+        const PRICE: u128 = 100;
+
+        // returns the compressed public key and the address of a signing key
+        fn key_address(signing_key: &SigningKey) -> (Vec<u8>, String) {
+            let public_key = signing_key
+                .verifying_key()
+                .to_encoded_point(true)
+                .as_bytes()
+                .to_vec();
+            let address = hex::encode(Ripemd160::digest(Sha256::digest(&public_key)));
+            (public_key, address)
+        }
+
+        fn listing_order(offerer: &str, cw2981_address: &str, salt: u64) -> OrderComponents {
+            OrderComponents {
+                order_type: OrderType::LISTING,
+                order_id: salt,
+                offerer: Addr::unchecked(offerer),
+                offer: vec![OfferItem {
+                    item_type: ItemType::CW721,
+                    item: Asset::Nft(NFT {
+                        contract_address: Addr::unchecked(cw2981_address),
+                        token_id: Some(TOKEN_ID.to_string()),
+                    }),
+                    start_amount: 1,
+                    end_amount: 1,
+                }],
+                consideration: vec![ConsiderationItem {
+                    item_type: ItemType::NATIVE,
+                    item: Asset::Native(NATIVE {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: PRICE,
+                    }),
+                    start_amount: PRICE,
+                    end_amount: PRICE,
+                    recipient: Addr::unchecked(offerer),
+                }],
+                start_time: None,
+                end_time: None,
+                counter: 0,
+            }
+        }
+
+        fn sign_order(
+            signing_key: &SigningKey,
+            chain_id: &str,
+            marketplace_address: &str,
+            order: &OrderComponents,
+        ) -> Binary {
+            let payload = to_vec(&SignedOrderPayload {
+                chain_id: chain_id.to_string(),
+                contract_address: Addr::unchecked(marketplace_address),
+                order: order.clone(),
+            })
+            .unwrap();
+            let signature: Signature = signing_key.sign(&payload);
+            Binary::from(signature.as_ref())
+        }
+
+        // anyone can fulfill an order signed off-chain, but only once
+        #[test]
+        fn can_fulfill_signed_order_once() {
+            let (mut app, contracts) = instantiate_contracts_with_api(SignatureApi::default());
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let chain_id = app.block_info().chain_id;
+
+            let signing_key = SigningKey::from_bytes(&[1u8; 32]).unwrap();
+            let (public_key, offerer) = key_address(&signing_key);
+
+            // mint the nft to the offerer, who approves the marketplace to transfer it
+            let mint_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Mint(MintMsg {
+                token_id: TOKEN_ID.to_string(),
+                owner: offerer.clone(),
+                token_uri: None,
+                extension: Metadata::default(),
+            });
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(cw2981_address.clone()),
+                &mint_msg,
+                &[],
+            )
+            .unwrap();
+            let approve_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::Approve {
+                spender: marketplace_address.clone(),
+                token_id: TOKEN_ID.to_string(),
+                expires: None,
+            };
+            app.execute_contract(
+                Addr::unchecked(offerer.clone()),
+                Addr::unchecked(cw2981_address.clone()),
+                &approve_msg,
+                &[],
+            )
+            .unwrap();
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: USER_1.to_string(),
+                    amount: coins(PRICE * 2, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+
+            let order = listing_order(&offerer, &cw2981_address, 1);
+            let signature = sign_order(&signing_key, &chain_id, &marketplace_address, &order);
+
+            // the order hash is the sha256 of the signed payload
+            let order_hash: Binary = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::SignedOrderHash {
+                        order: order.clone(),
+                    },
+                )
+                .unwrap();
+            let payload = to_vec(&SignedOrderPayload {
+                chain_id: chain_id.clone(),
+                contract_address: Addr::unchecked(marketplace_address.clone()),
+                order: order.clone(),
+            })
+            .unwrap();
+            assert_eq!(order_hash.to_vec(), Sha256::digest(payload).to_vec());
+
+            // the order signed by another key is rejected
+            let other_key = SigningKey::from_bytes(&[2u8; 32]).unwrap();
+            let (other_public_key, _) = key_address(&other_key);
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::FulfillSignedOrder {
+                    order: order.clone(),
+                    signature: sign_order(&other_key, &chain_id, &marketplace_address, &order),
+                    public_key: Binary::from(other_public_key),
+                },
+                &coins(PRICE, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InvalidSignature {}.to_string()
+            );
+
+            // a tampered order is rejected
+            let mut tampered_order = order.clone();
+            tampered_order.consideration[0].recipient = Addr::unchecked(USER_1);
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::FulfillSignedOrder {
+                    order: tampered_order,
+                    signature: signature.clone(),
+                    public_key: Binary::from(public_key.clone()),
+                },
+                &coins(PRICE, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InvalidSignature {}.to_string()
+            );
+
+            // USER_1 fulfills the order
+            let fulfill_msg = ExecuteMsg::FulfillSignedOrder {
+                order,
+                signature,
+                public_key: Binary::from(public_key),
+            };
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &fulfill_msg,
+                &coins(PRICE, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            let res: cw721::OwnerOfResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    cw2981_address,
+                    &Cw721QueryMsg::OwnerOf {
+                        token_id: TOKEN_ID.to_string(),
+                        include_expired: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.owner, USER_1);
+
+            // the royalty of 20% is paid to the creator of the nft
+            assert_eq!(
+                app.wrap()
+                    .query_balance(offerer, NATIVE_DENOM)
+                    .unwrap()
+                    .amount,
+                Uint128::from(PRICE * 4 / 5)
+            );
+
+            // the order cannot be replayed
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &fulfill_msg,
+                &coins(PRICE, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::OrderAlreadyUsed {}.to_string()
+            );
+
+            let status: Option<SignedOrderStatus> = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::SignedOrderStatus { order_hash },
+                )
+                .unwrap();
+            assert_eq!(status, Some(SignedOrderStatus::Filled {}));
+        }
+
+        // a cancelled signed order cannot be fulfilled
+        #[test]
+        fn cannot_fulfill_cancelled_signed_order() {
+            let (mut app, contracts) = instantiate_contracts_with_api(SignatureApi::default());
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let chain_id = app.block_info().chain_id;
+
+            let signing_key = SigningKey::from_bytes(&[1u8; 32]).unwrap();
+            let (public_key, offerer) = key_address(&signing_key);
+
+            let order = listing_order(&offerer, &cw2981_address, 2);
+            let signature = sign_order(&signing_key, &chain_id, &marketplace_address, &order);
+
+            // only the offerer can cancel the order
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CancelSignedOrders {
+                    orders: vec![order.clone()],
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(offerer),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CancelSignedOrders {
+                    orders: vec![order.clone()],
+                },
+                &[],
+            )
+            .unwrap();
+
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::FulfillSignedOrder {
+                    order,
+                    signature,
+                    public_key: Binary::from(public_key),
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::OrderAlreadyUsed {}.to_string()
+            );
+        }
+    }
+
+    mod bundle {
+        use super::helpers::{mint_and_approve, native_balance};
+        use super::*;
+        use crate::msg::BundlesResponse;
+        use crate::state::Bundle;
+        use cw2981_royalties::msg::InstantiateMsg as Cw2981InstantiateMsg;
+        use cw2981_royalties::Metadata;
+        use cw721_base::msg::ExecuteMsg as Cw721ExecuteMsg;
+        use cw_multi_test::App;
+
+        const CREATOR_1: &str = "creator1";
+        const CREATOR_2: &str = "creator2";
+        const REFERRER: &str = "referrer";
+
+        // instantiate a new collection which pays the royalty to the creator
+        fn instantiate_collection(
+            app: &mut App,
+            code_id: u64,
+            royalty_percentage: u64,
+            creator: &str,
+        ) -> String {
+            let msg = Cw2981InstantiateMsg {
+                name: creator.to_string(),
+                symbol: "NFT".to_string(),
+                minter: ADMIN.to_string(),
+                royalty_percentage: Some(royalty_percentage),
+                royalty_payment_address: Some(creator.to_string()),
+            };
+            app.instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], creator, None)
+                .unwrap()
+                .to_string()
+        }
+
+        fn bundle_nfts(items: &[(&str, &str)]) -> Vec<NFT> {
+            items
+                .iter()
+                .map(|(cw2981_address, token_id)| NFT {
+                    contract_address: Addr::unchecked(*cw2981_address),
+                    token_id: Some(token_id.to_string()),
+                })
+                .collect()
+        }
+
+        fn list_bundle_msg(items: &[(&str, &str)], price: u128) -> ExecuteMsg {
+            ExecuteMsg::ListBundle {
+                nfts: bundle_nfts(items),
+                auction_config: AuctionConfig::FixedPrice {
+                    price: Coin {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: Uint128::from(price),
+                    },
+                    start_time: None,
+                    end_time: None,
+                    price_schedule: vec![],
+                },
+            }
+        }
+
+        // a bundle is bought with a single message and the price is split between its collections
+        #[test]
+        fn can_buy_bundle_with_royalties() {
+            let (mut app, contracts) = instantiate_contracts();
+            let code_id = contracts[0].contract_code_id;
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            // the nfts belong to 2 collections with different royalties
+            let collection_1 = instantiate_collection(&mut app, code_id, 10, CREATOR_1);
+            let collection_2 = instantiate_collection(&mut app, code_id, 20, CREATOR_2);
+            for (cw2981_address, token_id) in [
+                (&collection_1, "1"),
+                (&collection_1, "2"),
+                (&collection_2, "1"),
+            ] {
+                mint_and_approve(
+                    &mut app,
+                    cw2981_address,
+                    &marketplace_address,
+                    token_id,
+                    USER_1,
+                );
+            }
+            let items = [
+                (collection_1.as_str(), "1"),
+                (collection_1.as_str(), "2"),
+                (collection_2.as_str(), "1"),
+            ];
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditReferralFee {
+                    referral_fee_bps: 250,
+                },
+                &[],
+            )
+            .unwrap();
+
+            // USER_1 cannot list a bundle with a duplicated nft
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &list_bundle_msg(&[items[0], items[0]], 1000),
+                &[],
+            );
+            assert!(res.is_err());
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &list_bundle_msg(&items, 1200),
+                &[],
+            )
+            .unwrap();
+
+            // the bundle can be queried by id and by seller
+            let bundle: Bundle = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::Bundle { bundle_id: 1 },
+                )
+                .unwrap();
+            assert_eq!(bundle.nfts, bundle_nfts(&items));
+
+            let res: BundlesResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::BundlesBySeller {
+                        seller: USER_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.bundles, vec![bundle]);
+
+            // ADMIN cannot buy the bundle without the exact price
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::BuyBundle {
+                    bundle_id: 1,
+                    referrer: None,
+                },
+                &coins(1199, NATIVE_DENOM),
+            );
+            assert!(res.is_err());
+
+            let seller_balance = native_balance(&app, USER_1);
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::BuyBundle {
+                    bundle_id: 1,
+                    referrer: Some(REFERRER.to_string()),
+                },
+                &coins(1200, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // ADMIN owns all nfts
+            for (cw2981_address, token_id) in items {
+                let res: OwnerOfResponse = app
+                    .wrap()
+                    .query_wasm_smart(
+                        cw2981_address,
+                        &Cw721QueryMsg::OwnerOf {
+                            token_id: token_id.to_string(),
+                            include_expired: None,
+                        },
+                    )
+                    .unwrap();
+                assert_eq!(res.owner, ADMIN);
+            }
+
+            // collection 1 accounts for 800 of the price and collection 2 for 400,
+            // the referral fee is carved out of the proceeds of the seller
+            assert_eq!(native_balance(&app, CREATOR_1), Uint128::from(80u128));
+            assert_eq!(native_balance(&app, CREATOR_2), Uint128::from(80u128));
+            assert_eq!(native_balance(&app, REFERRER), Uint128::from(30u128));
+            assert_eq!(
+                native_balance(&app, USER_1),
+                seller_balance + Uint128::from(1010u128)
+            );
+
+            // the bundle is removed
+            let res: StdResult<Bundle> = app
+                .wrap()
+                .query_wasm_smart(marketplace_address, &QueryMsg::Bundle { bundle_id: 1 });
+            assert!(res.is_err());
+        }
+
+        // moving a nft of a bundle away invalidates the bundle
+        #[test]
+        fn moving_nft_invalidates_bundle() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            for token_id in ["1", "2"] {
+                mint_and_approve(
+                    &mut app,
+                    &cw2981_address,
+                    &marketplace_address,
+                    token_id,
+                    USER_1,
+                );
+            }
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &list_bundle_msg(&[(&cw2981_address, "1"), (&cw2981_address, "2")], 1000),
+                &[],
+            )
+            .unwrap();
+
+            // USER_1 transfers nft 2 away
+            let transfer_msg: Cw721ExecuteMsg<Metadata, Metadata> = Cw721ExecuteMsg::TransferNft {
+                recipient: "other".to_string(),
+                token_id: "2".to_string(),
+            };
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw2981_address),
+                &transfer_msg,
+                &[],
+            )
+            .unwrap();
+
+            // the bundle is hidden from queries
+            let res: StdResult<Bundle> = app.wrap().query_wasm_smart(
+                marketplace_address.clone(),
+                &QueryMsg::Bundle { bundle_id: 1 },
+            );
+            assert!(res.is_err());
+
+            let res: BundlesResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::BundlesBySeller {
+                        seller: USER_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert!(res.bundles.is_empty());
+
+            // and cannot be bought
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::BuyBundle {
+                    bundle_id: 1,
+                    referrer: None,
+                },
+                &coins(1000, NATIVE_DENOM),
+            );
+            assert!(res.is_err());
+        }
+    }
+
+    mod reverse_auction {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::state::{Ask, ReverseAuction};
+        use cw_multi_test::App;
+
+        fn submit_ask(
+            app: &mut App,
+            marketplace_address: &str,
+            seller: &str,
+            token_id: &str,
+            price: u128,
+        ) -> bool {
+            app.execute_contract(
+                Addr::unchecked(seller),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::SubmitAsk {
+                    auction_id: 1,
+                    token_id: token_id.to_string(),
+                    price: Uint128::from(price),
+                },
+                &[],
+            )
+            .is_ok()
+        }
+
+        // the lowest ask wins, it is paid with royalties and the rest is refunded to the buyer
+        #[test]
+        fn lowest_ask_wins_reverse_auction() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            for token_id in ["1", "2"] {
+                mint_and_approve(
+                    &mut app,
+                    &cw2981_address,
+                    &marketplace_address,
+                    token_id,
+                    USER_1,
+                );
+            }
+
+            // ADMIN wants any nft of the collection for at most 1000
+            let end_time = app.block_info().time.plus_seconds(100);
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CreateReverseAuction {
+                    nft: NFT {
+                        contract_address: Addr::unchecked(cw2981_address.clone()),
+                        token_id: None,
+                    },
+                    end_time: Cw721Expiration::AtTime(end_time),
+                },
+                &coins(1000, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // asks must not exceed the max price and must decrease
+            assert!(!submit_ask(
+                &mut app,
+                &marketplace_address,
+                USER_1,
+                "1",
+                1001
+            ));
+            assert!(submit_ask(&mut app, &marketplace_address, USER_1, "1", 900));
+            assert!(!submit_ask(
+                &mut app,
+                &marketplace_address,
+                USER_1,
+                "2",
+                900
+            ));
+            assert!(submit_ask(&mut app, &marketplace_address, USER_1, "2", 800));
+
+            // only the owner of the nft can ask
+            assert!(!submit_ask(&mut app, &marketplace_address, ADMIN, "1", 700));
+
+            let auction: ReverseAuction = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::ReverseAuction { auction_id: 1 },
+                )
+                .unwrap();
+            assert_eq!(
+                auction.best_ask,
+                Some(Ask {
+                    seller: Addr::unchecked(USER_1),
+                    token_id: "2".to_string(),
+                    price: Uint128::from(800u128),
+                })
+            );
+
+            // the auction cannot be settled before the end time
+            let settle_msg = ExecuteMsg::SettleReverseAuction { auction_id: 1 };
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &settle_msg,
+                &[],
+            );
+            assert!(res.is_err());
+
+            app.update_block(|block| block.time = end_time.plus_seconds(1));
+
+            let admin_balance = native_balance(&app, ADMIN);
+            let seller_balance = native_balance(&app, USER_1);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &settle_msg,
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(owner_of(&app, &cw2981_address, "2"), ADMIN);
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+
+            // the royalty of 20% is paid to ADMIN, who is also refunded 200
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                admin_balance + Uint128::from(360u128)
+            );
+            assert_eq!(
+                native_balance(&app, USER_1),
+                seller_balance + Uint128::from(640u128)
+            );
+            assert_eq!(native_balance(&app, &marketplace_address), Uint128::zero());
+        }
+
+        fn revoke(app: &mut App, cw2981_address: &str, marketplace_address: &str, token_id: &str) {
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw2981_address),
+                &Cw2981ExecuteMsg::Revoke {
+                    spender: marketplace_address.to_string(),
+                    token_id: token_id.to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        // creates a reverse auction for 1000 where the ask of 800 for nft "1" is no longer approved
+        fn auction_with_revoked_ask(
+            app: &mut App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+        ) {
+            for token_id in ["1", "2"] {
+                mint_and_approve(app, cw2981_address, marketplace_address, token_id, USER_1);
+            }
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::CreateReverseAuction {
+                    nft: NFT {
+                        contract_address: Addr::unchecked(cw2981_address),
+                        token_id: None,
+                    },
+                    end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(100)),
+                },
+                &coins(1000, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert!(submit_ask(app, marketplace_address, USER_1, "1", 800));
+            revoke(app, cw2981_address, marketplace_address, "1");
+        }
+
+        // an ask that can no longer be transferred is replaced by any valid ask
+        // and does not prevent the buyer from cancelling the auction
+        #[test]
+        fn unsettleable_ask_can_be_replaced_or_cancelled() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            auction_with_revoked_ask(&mut app, &cw2981_address, &marketplace_address);
+
+            // a higher ask replaces the revoked one
+            assert!(submit_ask(&mut app, &marketplace_address, USER_1, "2", 900));
+
+            // the buyer cannot cancel while the best ask is valid
+            let cancel_msg = ExecuteMsg::CancelReverseAuction { auction_id: 1 };
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &cancel_msg,
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::AuctionHasAsk {}.to_string()
+            );
+
+            revoke(&mut app, &cw2981_address, &marketplace_address, "2");
+
+            let admin_balance = native_balance(&app, ADMIN);
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &cancel_msg,
+                &[],
+            )
+            .unwrap();
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                admin_balance + Uint128::from(1000u128)
+            );
+        }
+
+        // with pull payments, the refund of a cancelled auction is credited to the buyer
+        #[test]
+        fn cancel_credits_refund_with_pull_payments() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditPullPayments { enabled: true },
+                &[],
+            )
+            .unwrap();
+            auction_with_revoked_ask(&mut app, &cw2981_address, &marketplace_address);
+
+            let admin_balance = native_balance(&app, ADMIN);
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::CancelReverseAuction { auction_id: 1 },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, ADMIN), admin_balance);
+
+            let res: PendingBalanceResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::PendingBalance {
+                        address: ADMIN.to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                res.balances,
+                vec![PaymentAsset::Native {
+                    denom: NATIVE_DENOM.to_string(),
+                    amount: 1000
+                }]
+            );
+
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::Withdraw {
+                    asset: PaymentAssetInfo::Native {
+                        denom: NATIVE_DENOM.to_string(),
+                    },
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                admin_balance + Uint128::from(1000u128)
+            );
+        }
+
+        // settling an auction whose best ask can no longer be transferred refunds the buyer
+        #[test]
+        fn settle_drops_unsettleable_ask() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            auction_with_revoked_ask(&mut app, &cw2981_address, &marketplace_address);
+
+            app.update_block(|block| block.time = block.time.plus_seconds(101));
+
+            let admin_balance = native_balance(&app, ADMIN);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::SettleReverseAuction { auction_id: 1 },
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                admin_balance + Uint128::from(1000u128)
+            );
+            assert_eq!(native_balance(&app, &marketplace_address), Uint128::zero());
+        }
+    }
+
+    mod sealed_bid {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::execute::MAX_SEALED_BIDS;
+        use crate::msg::SealedBidsResponse;
+        use cosmwasm_std::Binary;
+        use cw_multi_test::App;
+
+        const BIDDER_2: &str = "bidder2";
+        const BIDDER_3: &str = "bidder3";
+
+        fn commit_bid(
+            app: &mut App,
+            marketplace_address: &str,
+            cw2981_address: &str,
+            bidder: &str,
+            amount: u128,
+            deposit: u128,
+        ) {
+            let commitment: Binary = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::SealedBidCommitment {
+                        bidder: bidder.to_string(),
+                        amount: Uint128::from(amount),
+                        salt: format!("salt of {}", bidder),
+                    },
+                )
+                .unwrap();
+            app.execute_contract(
+                Addr::unchecked(bidder),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::CommitBid {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    commitment,
+                },
+                &coins(deposit, NATIVE_DENOM),
+            )
+            .unwrap();
+        }
+
+        fn reveal_bid(
+            app: &mut App,
+            marketplace_address: &str,
+            cw2981_address: &str,
+            bidder: &str,
+            amount: u128,
+            salt: &str,
+        ) -> bool {
+            app.execute_contract(
+                Addr::unchecked(bidder),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::RevealBid {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    amount: Uint128::from(amount),
+                    salt: salt.to_string(),
+                },
+                &[],
+            )
+            .is_ok()
+        }
+
+        // withdraw the deposit credited to the bidder by the settlement
+        fn withdraw(app: &mut App, marketplace_address: &str, bidder: &str) {
+            app.execute_contract(
+                Addr::unchecked(bidder),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::Withdraw {
+                    asset: PaymentAssetInfo::Native {
+                        denom: NATIVE_DENOM.to_string(),
+                    },
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        // the highest bidder wins at the second highest price and unrevealed deposits are slashed
+        #[test]
+        fn highest_bidder_wins_at_second_price() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+            for bidder in [BIDDER_2, BIDDER_3] {
+                app.send_tokens(
+                    Addr::unchecked(ADMIN),
+                    Addr::unchecked(bidder),
+                    &coins(1000, NATIVE_DENOM),
+                )
+                .unwrap();
+            }
+
+            let now = app.block_info().time;
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::SealedBid {
+                        min_price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(100u128),
+                        },
+                        bidding_end: Cw721Expiration::AtTime(now.plus_seconds(100)),
+                        reveal_end: Cw721Expiration::AtTime(now.plus_seconds(200)),
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            // a sealed bid auction cannot be bought directly
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    referrer: None,
+                },
+                &coins(100, NATIVE_DENOM),
+            );
+            assert!(res.is_err());
+
+            // the deposit of ADMIN hides the bid of 500
+            commit_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                ADMIN,
+                500,
+                600,
+            );
+            commit_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                BIDDER_2,
+                300,
+                300,
+            );
+            commit_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                BIDDER_3,
+                400,
+                400,
+            );
+
+            let res: SealedBidsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::SealedBids {
+                        contract_address: cw2981_address.clone(),
+                        token_id: "1".to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.bids.len(), 3);
+            assert!(res.bids.iter().all(|bid| bid.revealed_amount.is_none()));
+
+            // bids cannot be revealed during the bidding phase
+            assert!(!reveal_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                ADMIN,
+                500,
+                &format!("salt of {}", ADMIN)
+            ));
+
+            app.update_block(|block| block.time = now.plus_seconds(150));
+
+            // bids must match the commitment
+            assert!(!reveal_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                ADMIN,
+                300,
+                &format!("salt of {}", ADMIN)
+            ));
+            assert!(reveal_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                ADMIN,
+                500,
+                &format!("salt of {}", ADMIN)
+            ));
+            assert!(reveal_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                BIDDER_2,
+                300,
+                &format!("salt of {}", BIDDER_2)
+            ));
+
+            // the auction cannot be settled during the reveal phase
+            let settle_msg = ExecuteMsg::SettleSealedBid {
+                contract_address: cw2981_address.clone(),
+                token_id: "1".to_string(),
+            };
+            let res = app.execute_contract(
+                Addr::unchecked(BIDDER_2),
+                Addr::unchecked(marketplace_address.clone()),
+                &settle_msg,
+                &[],
+            );
+            assert!(res.is_err());
+
+            app.update_block(|block| block.time = now.plus_seconds(250));
+
+            let admin_balance = native_balance(&app, ADMIN);
+            let seller_balance = native_balance(&app, USER_1);
+            app.execute_contract(
+                Addr::unchecked(BIDDER_2),
+                Addr::unchecked(marketplace_address.clone()),
+                &settle_msg,
+                &[],
+            )
+            .unwrap();
+
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), ADMIN);
+
+            // ADMIN pays 300, gets back 300 of the deposit and the royalty of 20% as creator
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                admin_balance + Uint128::from(360u128)
+            );
+            // the seller gets the rest of the price and the deposit of BIDDER_3
+            assert_eq!(
+                native_balance(&app, USER_1),
+                seller_balance + Uint128::from(640u128)
+            );
+            assert_eq!(native_balance(&app, BIDDER_3), Uint128::from(600u128));
+
+            // the losing bidder withdraws its deposit
+            assert_eq!(native_balance(&app, BIDDER_2), Uint128::from(700u128));
+            withdraw(&mut app, &marketplace_address, BIDDER_2);
+            assert_eq!(native_balance(&app, BIDDER_2), Uint128::from(1000u128));
+            assert_eq!(native_balance(&app, &marketplace_address), Uint128::zero());
+        }
+
+        // if the seller revokes the approval, settling closes the auction and refunds every deposit
+        #[test]
+        fn settle_refunds_all_deposits_if_nft_not_transferable() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+            for bidder in [BIDDER_2, BIDDER_3] {
+                app.send_tokens(
+                    Addr::unchecked(ADMIN),
+                    Addr::unchecked(bidder),
+                    &coins(1000, NATIVE_DENOM),
+                )
+                .unwrap();
+            }
+
+            let now = app.block_info().time;
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::SealedBid {
+                        min_price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(100u128),
+                        },
+                        bidding_end: Cw721Expiration::AtTime(now.plus_seconds(100)),
+                        reveal_end: Cw721Expiration::AtTime(now.plus_seconds(200)),
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            commit_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                BIDDER_2,
+                300,
+                300,
+            );
+            commit_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                BIDDER_3,
+                400,
+                400,
+            );
+
+            app.update_block(|block| block.time = now.plus_seconds(150));
+            assert!(reveal_bid(
+                &mut app,
+                &marketplace_address,
+                &cw2981_address,
+                BIDDER_2,
+                300,
+                &format!("salt of {}", BIDDER_2)
+            ));
+
+            // the seller revokes the approval of the marketplace
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw2981_address.clone()),
+                &Cw2981ExecuteMsg::Revoke {
+                    spender: marketplace_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            app.update_block(|block| block.time = now.plus_seconds(250));
+            let seller_balance = native_balance(&app, USER_1);
+            app.execute_contract(
+                Addr::unchecked(BIDDER_2),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::SettleSealedBid {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            // the nft stays with the seller, the unrevealed deposit is not slashed
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+            assert_eq!(native_balance(&app, USER_1), seller_balance);
+            for bidder in [BIDDER_2, BIDDER_3] {
+                withdraw(&mut app, &marketplace_address, bidder);
+                assert_eq!(native_balance(&app, bidder), Uint128::from(1000u128));
+            }
+            assert_eq!(native_balance(&app, &marketplace_address), Uint128::zero());
+
+            // the auction is closed
+            let res: StdResult<Listing> = app.wrap().query_wasm_smart(
+                marketplace_address,
+                &QueryMsg::Listing {
+                    contract_address: cw2981_address,
+                    token_id: "1".to_string(),
+                    viewer: None,
+                },
+            );
+            assert!(res.is_err());
+        }
+
+        // the number of bids is capped, so that the settlement cannot run out of gas
+        #[test]
+        fn commit_bid_fails_above_max_bids() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+            let now = app.block_info().time;
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::SealedBid {
+                        min_price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(100u128),
+                        },
+                        bidding_end: Cw721Expiration::AtTime(now.plus_seconds(100)),
+                        reveal_end: Cw721Expiration::AtTime(now.plus_seconds(200)),
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            for index in 0..=MAX_SEALED_BIDS {
+                let bidder = format!("bidder{}", index);
+                app.sudo(cw_multi_test::SudoMsg::Bank(
+                    cw_multi_test::BankSudo::Mint {
+                        to_address: bidder.clone(),
+                        amount: coins(100, NATIVE_DENOM),
+                    },
+                ))
+                .unwrap();
+                if index < MAX_SEALED_BIDS {
+                    commit_bid(
+                        &mut app,
+                        &marketplace_address,
+                        &cw2981_address,
+                        &bidder,
+                        100,
+                        100,
+                    );
+                    continue;
+                }
+
+                let res = app.execute_contract(
+                    Addr::unchecked(bidder),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &ExecuteMsg::CommitBid {
+                        contract_address: cw2981_address.clone(),
+                        token_id: "1".to_string(),
+                        commitment: Binary::from(vec![0u8; 32]),
+                    },
+                    &coins(100, NATIVE_DENOM),
+                );
+                assert_eq!(
+                    res.unwrap_err().source().unwrap().to_string(),
+                    ContractError::TooManySealedBids {
+                        max_bids: MAX_SEALED_BIDS
+                    }
+                    .to_string()
+                );
+            }
+        }
+    }
+
+    mod non_compliant {
+        use super::helpers::native_balance;
+        use super::*;
+        use crate::integration_tests::env::{
+            instantiate_broken_contract, BrokenBehavior, BrokenExecuteMsg,
+        };
+        use cw_multi_test::App;
+
+        const TOKEN_ID: &str = "1";
+        const PRICE: u128 = 1000;
+
+        // convert native tokens of the user to vaura, so the user can make offers
+        fn mint_vaura(app: &mut App, cw20_address: &str, user: &str, amount: u128) {
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: user.to_string(),
+                    amount: coins(amount, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(user),
+                Addr::unchecked(cw20_address),
+                &cw20::Cw20ExecuteMsg::Mint {
+                    recipient: user.to_string(),
+                    amount: Uint128::from(amount),
+                },
+                &coins(amount, NATIVE_DENOM),
+            )
+            .unwrap();
+        }
+
+        fn list_msg(nft_address: &str) -> ExecuteMsg {
+            ExecuteMsg::ListNft {
+                contract_address: nft_address.to_string(),
+                token_id: TOKEN_ID.to_string(),
+                auction_config: AuctionConfig::FixedPrice {
+                    price: Coin {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: Uint128::from(PRICE),
+                    },
+                    start_time: None,
+                    end_time: None,
+                    price_schedule: vec![],
+                },
+                reserved_for: None,
+            }
+        }
+
+        fn buy_msg(nft_address: &str) -> ExecuteMsg {
+            ExecuteMsg::Buy {
+                contract_address: nft_address.to_string(),
+                token_id: TOKEN_ID.to_string(),
+                referrer: None,
+            }
+        }
+
+        fn offer_msg(app: &App, nft_address: &str) -> ExecuteMsg {
+            ExecuteMsg::OfferNft {
+                nft: NFT {
+                    contract_address: Addr::unchecked(nft_address),
+                    token_id: Some(TOKEN_ID.to_string()),
+                },
+                funds_amount: MOCK_OFFER_CW20_PRICE,
+                end_time: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                offerer: None,
+            }
+        }
+
+        // an invalid royalty address is ignored, the seller gets the whole price
+        #[test]
+        fn invalid_royalty_address_is_ignored() {
+            let (mut app, contracts) = instantiate_contracts();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let nft_address = instantiate_broken_contract(
+                &mut app,
+                BrokenBehavior::Royalty {
+                    owner: USER_1.to_string(),
+                    address: "Invalid Address".to_string(),
+                    percentage: 10,
+                },
+            );
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &list_msg(&nft_address),
+                &[],
+            )
+            .unwrap();
+
+            let seller_balance = native_balance(&app, USER_1);
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &buy_msg(&nft_address),
+                &coins(PRICE, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(
+                native_balance(&app, USER_1),
+                seller_balance + Uint128::from(PRICE)
+            );
+        }
+
+        // a royalty greater than the price makes the purchase fail
+        #[test]
+        fn royalty_above_price_is_rejected() {
+            let (mut app, contracts) = instantiate_contracts();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let nft_address = instantiate_broken_contract(
+                &mut app,
+                BrokenBehavior::Royalty {
+                    owner: USER_1.to_string(),
+                    address: "creator".to_string(),
+                    percentage: 200,
+                },
+            );
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &list_msg(&nft_address),
+                &[],
+            )
+            .unwrap();
+
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &buy_msg(&nft_address),
+                &coins(PRICE, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::RoyaltyExceedsPrice {
+                    royalty: Uint128::from(2 * PRICE),
+                    price: Uint128::from(PRICE),
+                }
+                .to_string()
+            );
+        }
+
+        // nfts of a contract which does not answer cannot be listed or offered
+        #[test]
+        fn cannot_trade_nft_of_broken_contract() {
+            let (mut app, contracts) = instantiate_contracts();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let cw20_address = contracts[2].contract_addr.clone();
+            mint_vaura(&mut app, &cw20_address, USER_1, MOCK_OFFER_CW20_PRICE);
+
+            for behavior in [
+                BrokenBehavior::QueryError {},
+                BrokenBehavior::WrongResponse {},
+            ] {
+                let nft_address = instantiate_broken_contract(&mut app, behavior);
+
+                let res = app.execute_contract(
+                    Addr::unchecked(USER_1),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &list_msg(&nft_address),
+                    &[],
+                );
+                assert_eq!(
+                    res.unwrap_err().source().unwrap().to_string(),
+                    ContractError::Unauthorized {}.to_string()
+                );
+
+                let res = app.execute_contract(
+                    Addr::unchecked(USER_1),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &offer_msg(&app, &nft_address),
+                    &[],
+                );
+                assert_eq!(
+                    res.unwrap_err().source().unwrap().to_string(),
+                    ContractError::NftNotFound {}.to_string()
+                );
+            }
+        }
+
+        // an offer cannot be accepted once the nft contract stops answering
+        #[test]
+        fn cannot_accept_offer_when_nft_contract_breaks() {
+            let (mut app, contracts) = instantiate_contracts();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let cw20_address = contracts[2].contract_addr.clone();
+            mint_vaura(&mut app, &cw20_address, USER_1, MOCK_OFFER_CW20_PRICE);
+            let nft_address = instantiate_broken_contract(
+                &mut app,
+                BrokenBehavior::Royalty {
+                    owner: ADMIN.to_string(),
+                    address: ADMIN.to_string(),
+                    percentage: 10,
+                },
+            );
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &offer_msg(&app, &nft_address),
+                &[],
+            )
+            .unwrap();
+
+            for behavior in [
+                BrokenBehavior::QueryError {},
+                BrokenBehavior::WrongResponse {},
+            ] {
+                app.execute_contract(
+                    Addr::unchecked(ADMIN),
+                    Addr::unchecked(nft_address.clone()),
+                    &BrokenExecuteMsg::SetBehavior { behavior },
+                    &[],
+                )
+                .unwrap();
+
+                let res = app.execute_contract(
+                    Addr::unchecked(ADMIN),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &ExecuteMsg::AcceptNftOffer {
+                        order_id: 1,
+                        funds_amount: MOCK_OFFER_CW20_PRICE,
+                        referrer: None,
+                    },
+                    &[],
+                );
+                assert_eq!(
+                    res.unwrap_err().source().unwrap().to_string(),
+                    ContractError::NftNotFound {}.to_string()
+                );
+            }
+        }
+
+        // offers fail with an error when the vaura contract does not answer the allowance query
+        #[test]
+        fn cannot_offer_when_token_contract_is_broken() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            for behavior in [
+                BrokenBehavior::QueryError {},
+                BrokenBehavior::WrongResponse {},
+            ] {
+                let token_address = instantiate_broken_contract(&mut app, behavior);
+                app.execute_contract(
+                    Addr::unchecked(ADMIN),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &ExecuteMsg::EditVauraToken { token_address },
+                    &[],
+                )
+                .unwrap();
+
+                let res = app.execute_contract(
+                    Addr::unchecked(USER_1),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &offer_msg(&app, &cw2981_address),
+                    &[],
+                );
+                assert!(res.is_err());
+            }
+        }
+
+        // a nft cannot be converted to a payment asset
+        #[test]
+        fn nft_is_not_payment_asset() {
+            let nft = Asset::Nft(NFT {
+                contract_address: Addr::unchecked(MOCK_CW2981_ADDR),
+                token_id: Some(TOKEN_ID.to_string()),
+            });
+            assert_eq!(
+                PaymentAsset::try_from(nft).unwrap_err().to_string(),
+                ContractError::NotPaymentAsset {}.to_string()
+            );
+        }
+    }
+
+    mod royalty_registry {
+        use super::helpers::native_balance;
+        use super::*;
+        use crate::state::RoyaltyRecipient;
+        use cosmwasm_std::Empty;
+        use cw721_base::msg::{
+            ExecuteMsg as Cw721ExecuteMsg, InstantiateMsg as Cw721InstantiateMsg, MintMsg,
+        };
+        use cw_multi_test::{App, ContractWrapper};
+
+        const MINTER: &str = "minter";
+        const CREATOR_1: &str = "creator1";
+        const CREATOR_2: &str = "creator2";
+        const BUYER: &str = "buyer";
+        const TOKEN_ID: &str = "1";
+
+        // instantiate a plain cw721 collection, which does not support cw2981
+        fn instantiate_cw721(app: &mut App) -> String {
+            let code_id = app.store_code(Box::new(ContractWrapper::new(
+                cw721_base::entry::execute,
+                cw721_base::entry::instantiate,
+                cw721_base::entry::query,
+            )));
+            app.instantiate_contract(
+                code_id,
+                Addr::unchecked(MINTER),
+                &Cw721InstantiateMsg {
+                    name: "NFT_B".to_string(),
+                    symbol: "NFT".to_string(),
+                    minter: MINTER.to_string(),
+                },
+                &[],
+                "test instantiate cw721 contract",
+                None,
+            )
+            .unwrap()
+            .to_string()
+        }
+
+        fn set_royalty_msg(cw721_address: &str, rates: &[(&str, u64)]) -> ExecuteMsg {
+            ExecuteMsg::SetCollectionRoyalty {
+                contract_address: cw721_address.to_string(),
+                recipients: rates
+                    .iter()
+                    .map(|(address, bps)| RoyaltyRecipient {
+                        address: Addr::unchecked(*address),
+                        bps: *bps,
+                    })
+                    .collect(),
+            }
+        }
+
+        // the royalties registered by the minter are paid for a collection without cw2981
+        #[test]
+        fn registered_royalties_are_paid_for_plain_cw721() {
+            let (mut app, contracts) = instantiate_contracts();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let cw721_address = instantiate_cw721(&mut app);
+
+            // only the minter of the collection or the owner of the marketplace can register royalties
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &set_royalty_msg(&cw721_address, &[(CREATOR_1, 500)]),
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+
+            let res = app.execute_contract(
+                Addr::unchecked(MINTER),
+                Addr::unchecked(marketplace_address.clone()),
+                &set_royalty_msg(&cw721_address, &[(CREATOR_1, 5000), (CREATOR_2, 5001)]),
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InvalidRoyaltyRates {}.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(MINTER),
+                Addr::unchecked(marketplace_address.clone()),
+                &set_royalty_msg(&cw721_address, &[(CREATOR_1, 500), (CREATOR_2, 1000)]),
+                &[],
+            )
+            .unwrap();
+
+            let royalty: Option<Vec<RoyaltyRecipient>> = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::CollectionRoyalty {
+                        contract_address: cw721_address.clone(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(royalty.unwrap().len(), 2);
+
+            // USER_1 lists a nft of the collection and BUYER buys it
+            let mint_msg: Cw721ExecuteMsg<Empty, Empty> = Cw721ExecuteMsg::Mint(MintMsg {
+                token_id: TOKEN_ID.to_string(),
+                owner: USER_1.to_string(),
+                token_uri: None,
+                extension: Empty {},
+            });
+            app.execute_contract(
+                Addr::unchecked(MINTER),
+                Addr::unchecked(cw721_address.clone()),
+                &mint_msg,
+                &[],
+            )
+            .unwrap();
+            let approve_msg: Cw721ExecuteMsg<Empty, Empty> = Cw721ExecuteMsg::Approve {
+                spender: marketplace_address.clone(),
+                token_id: TOKEN_ID.to_string(),
+                expires: None,
+            };
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw721_address.clone()),
+                &approve_msg,
+                &[],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw721_address.clone(),
+                    token_id: TOKEN_ID.to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(1000u128),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            // the registered royalties are returned with the listing
+            let info: ListingInfo = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::ListingWithInfo {
+                        contract_address: cw721_address.clone(),
+                        token_id: TOKEN_ID.to_string(),
+                        viewer: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                info.royalties,
+                vec![
+                    RoyaltiesInfoResponse {
+                        address: CREATOR_1.to_string(),
+                        royalty_amount: Uint128::from(50u128),
+                    },
+                    RoyaltiesInfoResponse {
+                        address: CREATOR_2.to_string(),
+                        royalty_amount: Uint128::from(100u128),
+                    },
+                ]
+            );
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: BUYER.to_string(),
+                    amount: coins(1000, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Buy {
+                    contract_address: cw721_address.clone(),
+                    token_id: TOKEN_ID.to_string(),
+                    referrer: None,
+                },
+                &coins(1000, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            assert_eq!(native_balance(&app, CREATOR_1), Uint128::from(50u128));
+            assert_eq!(native_balance(&app, CREATOR_2), Uint128::from(100u128));
+
+            // the owner of the marketplace can remove the royalties
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::RemoveCollectionRoyalty {
+                    contract_address: cw721_address.clone(),
+                },
+                &[],
+            )
+            .unwrap();
+            let royalty: Option<Vec<RoyaltyRecipient>> = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::CollectionRoyalty {
+                        contract_address: cw721_address,
+                    },
+                )
+                .unwrap();
+            assert_eq!(royalty, None);
+        }
+    }
+
+    mod hooks {
+        use super::helpers::mint_and_approve;
+        use super::*;
+        use crate::integration_tests::env::{instantiate_hook_receiver, HookReceiverQueryMsg};
+        use crate::msg::{HookAction, HookExecuteMsg, ListingHookMsg, SaleHookMsg};
+
+        const TOKEN_ID: &str = "1";
+
+        // the hooks are notified of the listing and the sale, a failing hook does not block the trade
+        #[test]
+        fn hooks_are_notified_of_trades() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let recorder = instantiate_hook_receiver(&mut app, false);
+            let failing = instantiate_hook_receiver(&mut app, true);
+
+            // only the owner can add hooks
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::AddHook {
+                    contract_address: recorder.clone(),
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+
+            for hook in [&recorder, &failing] {
+                app.execute_contract(
+                    Addr::unchecked(ADMIN),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &ExecuteMsg::AddHook {
+                        contract_address: hook.clone(),
+                    },
+                    &[],
+                )
+                .unwrap();
+            }
+            let hooks: Vec<Addr> = app
+                .wrap()
+                .query_wasm_smart(marketplace_address.clone(), &QueryMsg::Hooks {})
+                .unwrap();
+            assert_eq!(
+                hooks,
+                vec![Addr::unchecked(&recorder), Addr::unchecked(&failing)]
+            );
+
+            mint_and_approve(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                TOKEN_ID,
+                USER_1,
+            );
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: TOKEN_ID.to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(1000u128),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            let res = app
+                .execute_contract(
+                    Addr::unchecked(ADMIN),
+                    Addr::unchecked(marketplace_address.clone()),
+                    &ExecuteMsg::Buy {
+                        contract_address: cw2981_address.clone(),
+                        token_id: TOKEN_ID.to_string(),
+                        referrer: None,
+                    },
+                    &coins(1000, NATIVE_DENOM),
+                )
+                .unwrap();
+            assert!(res.events.iter().any(|event| event
+                .attributes
+                .iter()
+                .any(|attr| attr.key == "method" && attr.value == "hook_failed")));
+
+            let messages: Vec<HookExecuteMsg> = app
+                .wrap()
+                .query_wasm_smart(recorder, &HookReceiverQueryMsg::Messages {})
+                .unwrap();
+            assert_eq!(
+                messages,
+                vec![
+                    HookExecuteMsg::ListingHook(ListingHookMsg {
+                        action: HookAction::Created,
+                        contract_address: Addr::unchecked(&cw2981_address),
+                        token_id: TOKEN_ID.to_string(),
+                        seller: Addr::unchecked(USER_1),
+                    }),
+                    HookExecuteMsg::SaleHook(SaleHookMsg {
+                        contract_address: Addr::unchecked(&cw2981_address),
+                        token_id: TOKEN_ID.to_string(),
+                        seller: Addr::unchecked(USER_1),
+                        buyer: Addr::unchecked(ADMIN),
+                        price: PaymentAsset::Native {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: 1000,
+                        },
+                    }),
+                ]
+            );
+
+            // a hook can only be removed once
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::RemoveHook {
+                    contract_address: failing.clone(),
+                },
+                &[],
+            )
+            .unwrap();
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::RemoveHook {
+                    contract_address: failing,
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::HookNotFound {}.to_string()
+            );
+        }
+
+        // each nft of a bundle is notified as a sale at its share of the price
+        #[test]
+        fn hooks_are_notified_of_bundle_sales() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let recorder = instantiate_hook_receiver(&mut app, false);
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::AddHook {
+                    contract_address: recorder.clone(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            for token_id in ["1", "2"] {
+                mint_and_approve(
+                    &mut app,
+                    &cw2981_address,
+                    &marketplace_address,
+                    token_id,
+                    USER_1,
+                );
+            }
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListBundle {
+                    nfts: ["1", "2"]
+                        .iter()
+                        .map(|token_id| NFT {
+                            contract_address: Addr::unchecked(&cw2981_address),
+                            token_id: Some(token_id.to_string()),
+                        })
+                        .collect(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(1000u128),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                },
+                &[],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::BuyBundle {
+                    bundle_id: 1,
+                    referrer: None,
+                },
+                &coins(1000, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            let messages: Vec<HookExecuteMsg> = app
+                .wrap()
+                .query_wasm_smart(recorder, &HookReceiverQueryMsg::Messages {})
+                .unwrap();
+            let sales: Vec<HookExecuteMsg> = ["1", "2"]
+                .iter()
+                .map(|token_id| {
+                    HookExecuteMsg::SaleHook(SaleHookMsg {
+                        contract_address: Addr::unchecked(&cw2981_address),
+                        token_id: token_id.to_string(),
+                        seller: Addr::unchecked(USER_1),
+                        buyer: Addr::unchecked(ADMIN),
+                        price: PaymentAsset::Native {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: 500,
+                        },
+                    })
+                })
+                .collect();
+            assert_eq!(messages, sales);
+        }
+    }
+
+    mod rewards {
+        use super::helpers::mint_and_approve;
+        use super::*;
+        use crate::msg::{EpochRewards, UserRewardsResponse};
+        use crate::state::{RewardAsset, RewardDistribution};
+        use cw20::MinterResponse;
+        use cw20_base::msg::InstantiateMsg as Cw20InstantiateMsg;
+        use cw_multi_test::{App, ContractWrapper};
+
+        const BUYER: &str = "buyer";
+        const EPOCH_DURATION: u64 = 100;
+
+        fn list_and_buy(
+            app: &mut App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+            token_id: &str,
+            buyer: &str,
+            price: u128,
+            denom: &str,
+        ) {
+            mint_and_approve(app, cw2981_address, marketplace_address, token_id, USER_1);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: token_id.to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: denom.to_string(),
+                            amount: Uint128::from(price),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: buyer.to_string(),
+                    amount: coins(price, denom),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(buyer),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: token_id.to_string(),
+                    referrer: None,
+                },
+                &coins(price, denom),
+            )
+            .unwrap();
+        }
+
+        // the volume in the native denom gives its full points
+        fn eligible_native_assets() -> Vec<RewardAsset> {
+            vec![RewardAsset {
+                asset: PaymentAssetInfo::Native {
+                    denom: NATIVE_DENOM.to_string(),
+                },
+                weight_bps: 10_000,
+            }]
+        }
+
+        // instantiate a reward token minted by the marketplace
+        fn instantiate_reward_token(app: &mut App, marketplace_address: &str) -> String {
+            let code_id = app.store_code(Box::new(ContractWrapper::new(
+                cw20_base::contract::execute,
+                cw20_base::contract::instantiate,
+                cw20_base::contract::query,
+            )));
+            app.instantiate_contract(
+                code_id,
+                Addr::unchecked(ADMIN),
+                &Cw20InstantiateMsg {
+                    name: "Reward".to_string(),
+                    symbol: "RWD".to_string(),
+                    decimals: 6,
+                    initial_balances: vec![],
+                    mint: Some(MinterResponse {
+                        minter: marketplace_address.to_string(),
+                        cap: None,
+                    }),
+                    marketing: None,
+                },
+                &[],
+                "test instantiate reward token",
+                None,
+            )
+            .unwrap()
+            .to_string()
+        }
+
+        fn user_rewards(app: &App, marketplace_address: &str, user: &str) -> Vec<EpochRewards> {
+            let res: UserRewardsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::UserRewards {
+                        user: user.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            res.rewards
+        }
+
+        // the rewards of an epoch are shared in proportion to the volume times the collection multiplier
+        #[test]
+        fn traders_claim_rewards_of_ended_epoch() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let token_address = instantiate_reward_token(&mut app, &marketplace_address);
+
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditRewardsConfig {
+                    token: token_address.clone(),
+                    epoch_duration: EPOCH_DURATION,
+                    rewards_per_epoch: Uint128::from(1000u128),
+                    distribution: RewardDistribution::Mint,
+                    eligible_assets: eligible_native_assets(),
+                },
+                &[],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::SetRewardMultiplier {
+                    contract_address: cw2981_address.clone(),
+                    multiplier: 20_000,
+                },
+                &[],
+            )
+            .unwrap();
+
+            // USER_1 sells to ADMIN and BUYER, the points are 2000 and 4000 for each side
+            list_and_buy(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                "1",
+                ADMIN,
+                1000,
+                NATIVE_DENOM,
+            );
+            list_and_buy(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                "2",
+                BUYER,
+                2000,
+                NATIVE_DENOM,
+            );
+
+            assert_eq!(
+                user_rewards(&app, &marketplace_address, USER_1),
+                vec![EpochRewards {
+                    epoch: 0,
+                    points: Uint128::from(6000u128),
+                    pending: Uint128::from(500u128),
+                    claimed: Uint128::zero(),
+                }]
+            );
+            assert_eq!(
+                user_rewards(&app, &marketplace_address, BUYER)[0].pending,
+                Uint128::from(333u128)
+            );
+
+            // the rewards can only be claimed after the epoch
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ClaimRewards { epoch: 0 },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::EpochNotEnded { epoch: 0 }.to_string()
+            );
+
+            app.update_block(|block| block.time = block.time.plus_seconds(EPOCH_DURATION));
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ClaimRewards { epoch: 0 },
+                &[],
+            )
+            .unwrap();
+
+            let balance: BalanceResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    token_address,
+                    &cw20::Cw20QueryMsg::Balance {
+                        address: USER_1.to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(balance.balance, Uint128::from(500u128));
+            assert_eq!(
+                user_rewards(&app, &marketplace_address, USER_1),
+                vec![EpochRewards {
+                    epoch: 0,
+                    points: Uint128::from(6000u128),
+                    pending: Uint128::zero(),
+                    claimed: Uint128::from(500u128),
+                }]
+            );
+
+            // the rewards can only be claimed once
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ClaimRewards { epoch: 0 },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::NoRewardsToClaim {}.to_string()
+            );
+        }
+
+        fn edit_rewards_per_epoch(
+            app: &mut App,
+            marketplace_address: &str,
+            token_address: &str,
+            rewards_per_epoch: u128,
+        ) {
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::EditRewardsConfig {
+                    token: token_address.to_string(),
+                    epoch_duration: EPOCH_DURATION,
+                    rewards_per_epoch: Uint128::from(rewards_per_epoch),
+                    distribution: RewardDistribution::Mint,
+                    eligible_assets: eligible_native_assets(),
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        // editing the rewards per epoch does not change the rewards of the epochs with points
+        #[test]
+        fn rewards_edit_is_not_retroactive() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let token_address = instantiate_reward_token(&mut app, &marketplace_address);
+
+            edit_rewards_per_epoch(&mut app, &marketplace_address, &token_address, 1000);
+            list_and_buy(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                "1",
+                BUYER,
+                1000,
+                NATIVE_DENOM,
+            );
+            edit_rewards_per_epoch(&mut app, &marketplace_address, &token_address, 5000);
+
+            app.update_block(|block| block.time = block.time.plus_seconds(EPOCH_DURATION));
+            list_and_buy(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                "2",
+                BUYER,
+                1000,
+                NATIVE_DENOM,
+            );
+
+            let pending: Vec<_> = user_rewards(&app, &marketplace_address, BUYER)
+                .into_iter()
+                .map(|rewards| rewards.pending)
+                .collect();
+            assert_eq!(
+                pending,
+                vec![Uint128::from(500u128), Uint128::from(2500u128)]
+            );
+        }
+
+        // a wash trade paid in an asset which is not eligible gives no points
+        #[test]
+        fn wash_trade_in_ineligible_asset_earns_nothing() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let token_address = instantiate_reward_token(&mut app, &marketplace_address);
+
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditRewardsConfig {
+                    token: token_address,
+                    epoch_duration: EPOCH_DURATION,
+                    rewards_per_epoch: Uint128::from(1000u128),
+                    distribution: RewardDistribution::Mint,
+                    eligible_assets: vec![RewardAsset {
+                        asset: PaymentAssetInfo::Native {
+                            denom: NATIVE_DENOM.to_string(),
+                        },
+                        weight_bps: 5_000,
+                    }],
+                },
+                &[],
+            )
+            .unwrap();
+
+            list_and_buy(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                "1",
+                BUYER,
+                1_000_000,
+                NATIVE_DENOM_2,
+            );
+            assert!(user_rewards(&app, &marketplace_address, BUYER).is_empty());
+            assert!(user_rewards(&app, &marketplace_address, USER_1).is_empty());
+
+            // the eligible volume is weighted by its asset
+            list_and_buy(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                "2",
+                BUYER,
+                1000,
+                NATIVE_DENOM,
+            );
+            let rewards = user_rewards(&app, &marketplace_address, BUYER);
+            assert_eq!(rewards.len(), 1);
+            assert_eq!(rewards[0].points, Uint128::from(500u128));
+        }
+    }
+
+    mod referral {
+        use super::helpers::{mint_and_approve, native_balance};
+        use super::*;
+        use crate::msg::ReferralEarningsResponse;
+        use crate::order_state::PaymentAsset;
+        use cw_multi_test::App;
+
+        const BUYER: &str = "buyer";
+        const REFERRER: &str = "referrer";
+        const PRICE: u128 = 1000;
+
+        fn list(app: &mut App, cw2981_address: &str, marketplace_address: &str, token_id: &str) {
+            mint_and_approve(app, cw2981_address, marketplace_address, token_id, USER_1);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: token_id.to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(PRICE),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: BUYER.to_string(),
+                    amount: coins(PRICE, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+        }
+
+        // the nft pays a royalty, so the referral fee is carved out of the marketplace cut
+        #[test]
+        fn referrer_earns_share_of_sale() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            // only the owner can edit the referral fee
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditReferralFee {
+                    referral_fee_bps: 250,
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditReferralFee {
+                    referral_fee_bps: 10_001,
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InvalidReferralFee {}.to_string()
+            );
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditReferralFee {
+                    referral_fee_bps: 250,
+                },
+                &[],
+            )
+            .unwrap();
+
+            list(&mut app, &cw2981_address, &marketplace_address, "1");
+            let creator_balance = native_balance(&app, ADMIN);
+            let seller_balance = native_balance(&app, USER_1);
+
+            // the buyer cannot refer their own purchase
+            let res = app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    referrer: Some(BUYER.to_string()),
+                },
+                &coins(PRICE, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InvalidReferrer {}.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address,
+                    token_id: "1".to_string(),
+                    referrer: Some(REFERRER.to_string()),
+                },
+                &coins(PRICE, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // the fee is carved out of the proceeds of the seller, after the royalty of the creator
+            assert_eq!(native_balance(&app, REFERRER), Uint128::from(25u128));
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                creator_balance + Uint128::from(200u128)
+            );
+            assert_eq!(
+                native_balance(&app, USER_1),
+                seller_balance + Uint128::from(775u128)
+            );
+
+            let res: ReferralEarningsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::ReferralEarnings {
+                        referrer: REFERRER.to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                res.earnings,
+                vec![PaymentAsset::Native {
+                    denom: NATIVE_DENOM.to_string(),
+                    amount: 25,
+                }]
+            );
+        }
+
+        fn simulate_buy(
+            app: &App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+            buyer: &str,
+            referrer: &str,
+        ) -> SimulationResponse {
+            app.wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::SimulateBuy {
+                        contract_address: cw2981_address.to_string(),
+                        token_id: "1".to_string(),
+                        buyer: Some(buyer.to_string()),
+                        referrer: Some(referrer.to_string()),
+                    },
+                )
+                .unwrap()
+        }
+
+        // the simulation of a referred purchase includes the referral fee and the checks of the buyer
+        #[test]
+        fn simulate_referred_buy() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::EditReferralFee {
+                    referral_fee_bps: 250,
+                },
+                &[],
+            )
+            .unwrap();
+            list(&mut app, &cw2981_address, &marketplace_address, "1");
+
+            let res = simulate_buy(&app, &cw2981_address, &marketplace_address, BUYER, REFERRER);
+            assert_eq!(res.error, None);
+            assert_eq!(
+                res.transfers.last().unwrap(),
+                &SimulatedTransfer {
+                    kind: TransferKind::ReferralFee,
+                    recipient: Some(Addr::unchecked(REFERRER)),
+                    asset: Asset::Native(NATIVE {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: 25,
+                    }),
+                }
+            );
+
+            let res = simulate_buy(
+                &app,
+                &cw2981_address,
+                &marketplace_address,
+                USER_1,
+                REFERRER,
+            );
+            assert_eq!(
+                res.error,
+                Some(ContractError::OwnerCannotBuy {}.to_string())
+            );
+            let res = simulate_buy(&app, &cw2981_address, &marketplace_address, BUYER, BUYER);
+            assert_eq!(
+                res.error,
+                Some(ContractError::InvalidReferrer {}.to_string())
+            );
+        }
+    }
+
+    mod rentals {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::execute::MAX_RENTAL_DAYS;
+        use crate::msg::RentalUserResponse;
+        use crate::state::ListingStatus;
+        use cw_multi_test::App;
+
+        const RENTER: &str = "renter";
+        const PRICE_PER_DAY: u128 = 100;
+
+        fn rental_user(
+            app: &App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+        ) -> RentalUserResponse {
+            app.wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::RentalUser {
+                        contract_address: cw2981_address.to_string(),
+                        token_id: "1".to_string(),
+                    },
+                )
+                .unwrap()
+        }
+
+        // the renter is the user of the nft until the rental expires, then the owner reclaims it
+        #[test]
+        fn owner_reclaims_nft_after_rental_expires() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+
+            // a rental cannot be listed as a regular listing
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::Rental {
+                        price_per_day: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(PRICE_PER_DAY),
+                        },
+                        max_days: 7,
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InvalidAuctionConfig {}.to_string()
+            );
+
+            // the nft is escrowed in the marketplace
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListForRent {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    price_per_day: Coin {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: Uint128::from(PRICE_PER_DAY),
+                    },
+                    max_days: 7,
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), marketplace_address);
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: RENTER.to_string(),
+                    amount: coins(PRICE_PER_DAY * 10, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+
+            // the rental cannot exceed the max days
+            let res = app.execute_contract(
+                Addr::unchecked(RENTER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Rent {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    days: 8,
+                },
+                &coins(PRICE_PER_DAY * 8, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InvalidRentalDuration {
+                    days: 8,
+                    max_days: 7
+                }
+                .to_string()
+            );
+
+            let creator_balance = native_balance(&app, ADMIN);
+            let owner_balance = native_balance(&app, USER_1);
+            app.execute_contract(
+                Addr::unchecked(RENTER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Rent {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    days: 3,
+                },
+                &coins(PRICE_PER_DAY * 3, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            // the nft pays a royalty of 20% to the creator, the rest of the rent is paid to its owner
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                creator_balance + Uint128::from(60u128)
+            );
+            assert_eq!(
+                native_balance(&app, USER_1),
+                owner_balance + Uint128::from(240u128)
+            );
+
+            let expires = app.block_info().time.plus_seconds(3 * 86_400);
+            assert_eq!(
+                rental_user(&app, &cw2981_address, &marketplace_address),
+                RentalUserResponse {
+                    user: Some(Addr::unchecked(RENTER)),
+                    expires: Some(expires),
+                }
+            );
+            let rental: Listing = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::Rental {
+                        contract_address: cw2981_address.clone(),
+                        token_id: "1".to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                rental.status,
+                ListingStatus::Rented {
+                    user: Addr::unchecked(RENTER),
+                    expires,
+                }
+            );
+
+            // the nft cannot be rented or reclaimed during the rental
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Rent {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    days: 1,
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::NftRented { expires }.to_string()
+            );
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ReclaimRental {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::NftRented { expires }.to_string()
+            );
+
+            // the user rights end with the rental
+            app.update_block(|block| block.time = block.time.plus_seconds(3 * 86_400));
+            assert_eq!(
+                rental_user(&app, &cw2981_address, &marketplace_address),
+                RentalUserResponse {
+                    user: None,
+                    expires: None,
+                }
+            );
+
+            // only the owner can reclaim the nft
+            let res = app.execute_contract(
+                Addr::unchecked(RENTER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ReclaimRental {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ReclaimRental {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+        }
+
+        fn list_for_rent(
+            app: &mut App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+            max_days: u64,
+        ) -> Result<cw_multi_test::AppResponse, String> {
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ListForRent {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    price_per_day: Coin {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: Uint128::from(PRICE_PER_DAY),
+                    },
+                    max_days,
+                },
+                &[],
+            )
+            .map_err(|err| err.source().unwrap().to_string())
+        }
+
+        // the rental duration is bounded and the rentals are invalidated by the counter of the owner
+        #[test]
+        fn cannot_rent_too_long_or_invalidated_rental() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+
+            let res = list_for_rent(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                MAX_RENTAL_DAYS + 1,
+            );
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::InvalidAuctionConfig {}.to_string()
+            );
+            list_for_rent(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                MAX_RENTAL_DAYS,
+            )
+            .unwrap();
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::IncrementCounter {},
+                &[],
+            )
+            .unwrap();
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: RENTER.to_string(),
+                    amount: coins(PRICE_PER_DAY, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            let res = app.execute_contract(
+                Addr::unchecked(RENTER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::Rent {
+                    contract_address: cw2981_address,
+                    token_id: "1".to_string(),
+                    days: 1,
+                },
+                &coins(PRICE_PER_DAY, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::OrderInvalidated {}.to_string()
+            );
+        }
+
+        // the listing of a nft is removed when it is escrowed for rent, so the rented nft cannot be bought
+        #[test]
+        fn cannot_buy_rented_nft() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(PRICE_PER_DAY),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+            list_for_rent(&mut app, &cw2981_address, &marketplace_address, 7).unwrap();
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: RENTER.to_string(),
+                    amount: coins(PRICE_PER_DAY * 2, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(RENTER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Rent {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    days: 1,
+                },
+                &coins(PRICE_PER_DAY, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            let res = app.execute_contract(
+                Addr::unchecked(RENTER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    referrer: None,
+                },
+                &coins(PRICE_PER_DAY, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                StdError::not_found("nft_marketplace::state::Listing").to_string()
+            );
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), marketplace_address);
+
+            // the owner reclaims the nft once the rental expires
+            app.update_block(|block| block.time = block.time.plus_seconds(86_400));
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ReclaimRental {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+        }
+    }
+
+    mod loans {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::msg::LoansResponse;
+        use crate::order_state::{PaymentAsset, NFT};
+        use crate::state::{Loan, LoanStatus};
+        use cw_multi_test::App;
+
+        const LENDER: &str = "lender";
+        const PRINCIPAL: u128 = 1000;
+        const REPAYMENT: u128 = 1100;
+        const DURATION: u64 = 1000;
+
+        fn request_loan(app: &mut App, cw2981_address: &str, marketplace_address: &str) {
+            mint_and_approve(app, cw2981_address, marketplace_address, "1", USER_1);
+            request_and_fund_loan(app, cw2981_address, marketplace_address);
+        }
+
+        // escrow the nft "1" of the borrower and fund the loan
+        fn request_and_fund_loan(app: &mut App, cw2981_address: &str, marketplace_address: &str) {
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::RequestLoan {
+                    nft: NFT {
+                        contract_address: Addr::unchecked(cw2981_address),
+                        token_id: Some("1".to_string()),
+                    },
+                    principal: PaymentAsset::Native {
+                        denom: NATIVE_DENOM.to_string(),
+                        amount: PRINCIPAL,
+                    },
+                    repayment: REPAYMENT,
+                    duration: DURATION,
+                },
+                &[],
+            )
+            .unwrap();
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: LENDER.to_string(),
+                    amount: coins(PRINCIPAL, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::FundLoan { loan_id: 1 },
+                &coins(PRINCIPAL, NATIVE_DENOM),
+            )
+            .unwrap();
+        }
+
+        // the borrower repays the principal with the interest before the due time to get the nft back
+        #[test]
+        fn borrower_repays_loan() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            request_loan(&mut app, &cw2981_address, &marketplace_address);
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), marketplace_address);
+            assert_eq!(native_balance(&app, USER_1), Uint128::from(PRINCIPAL));
+
+            let loan: Loan = app
+                .wrap()
+                .query_wasm_smart(marketplace_address.clone(), &QueryMsg::Loan { loan_id: 1 })
+                .unwrap();
+            assert_eq!(loan.order.offerer, Addr::unchecked(USER_1));
+            assert_eq!(loan.order.consideration[0].start_amount, PRINCIPAL);
+            assert_eq!(loan.order.consideration[0].end_amount, REPAYMENT);
+            assert_eq!(
+                loan.status,
+                LoanStatus::Active {
+                    lender: Addr::unchecked(LENDER),
+                    due: app.block_info().time.plus_seconds(DURATION),
+                }
+            );
+
+            // the loan is listed by borrower, lender and collection
+            for query_msg in [
+                QueryMsg::LoansByBorrower {
+                    borrower: USER_1.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+                QueryMsg::LoansByLender {
+                    lender: LENDER.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+                QueryMsg::LoansByCollection {
+                    contract_address: cw2981_address.clone(),
+                    start_after: None,
+                    limit: None,
+                },
+            ] {
+                let res: LoansResponse = app
+                    .wrap()
+                    .query_wasm_smart(marketplace_address.clone(), &query_msg)
+                    .unwrap();
+                assert_eq!(res.loans, vec![loan.clone()]);
+            }
+
+            // the lender cannot foreclose before the due time
+            let res = app.execute_contract(
+                Addr::unchecked(LENDER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ForecloseLoan { loan_id: 1 },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::LoanNotDue {
+                    due: app.block_info().time.plus_seconds(DURATION)
+                }
+                .to_string()
+            );
+
+            // the repayment includes the interest
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: USER_1.to_string(),
+                    amount: coins(REPAYMENT - PRINCIPAL, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::RepayLoan { loan_id: 1 },
+                &coins(PRINCIPAL, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InsufficientFunds {}.to_string()
+            );
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::RepayLoan { loan_id: 1 },
+                &coins(REPAYMENT, NATIVE_DENOM),
+            )
+            .unwrap();
+
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+            assert_eq!(native_balance(&app, LENDER), Uint128::from(REPAYMENT));
+            let res: LoansResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::LoansByBorrower {
+                        borrower: USER_1.to_string(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert!(res.loans.is_empty());
+        }
+
+        // the lender takes the nft when the loan is not repaid at the due time
+        #[test]
+        fn lender_forecloses_overdue_loan() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            request_loan(&mut app, &cw2981_address, &marketplace_address);
+            let due = app.block_info().time.plus_seconds(DURATION);
+            app.update_block(|block| block.time = block.time.plus_seconds(DURATION));
+
+            // the borrower cannot repay once the loan is due
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: USER_1.to_string(),
+                    amount: coins(REPAYMENT - PRINCIPAL, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::RepayLoan { loan_id: 1 },
+                &coins(REPAYMENT, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::LoanOverdue { due }.to_string()
+            );
+
+            // only the lender can foreclose
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ForecloseLoan { loan_id: 1 },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ForecloseLoan { loan_id: 1 },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), LENDER);
+        }
+
+        // the listing of the collateral is removed when the nft is escrowed, so it cannot be bought
+        #[test]
+        fn cannot_buy_escrowed_collateral() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(PRINCIPAL),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+            request_and_fund_loan(&mut app, &cw2981_address, &marketplace_address);
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: ADMIN.to_string(),
+                    amount: coins(PRINCIPAL, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    referrer: None,
+                },
+                &coins(PRINCIPAL, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                StdError::not_found("nft_marketplace::state::Listing").to_string()
+            );
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), marketplace_address);
+        }
+
+        // a listing cannot be bought once its seller does not own the nft anymore
+        #[test]
+        fn cannot_buy_listing_of_transferred_nft() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(PRINCIPAL),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            // the new owner approves the marketplace for their own listings
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(cw2981_address.clone()),
+                &Cw2981ExecuteMsg::TransferNft {
+                    recipient: LENDER.to_string(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(LENDER),
+                Addr::unchecked(cw2981_address.clone()),
+                &Cw2981ExecuteMsg::Approve {
+                    spender: marketplace_address.clone(),
+                    token_id: "1".to_string(),
+                    expires: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: ADMIN.to_string(),
+                    amount: coins(PRINCIPAL, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            let res = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    referrer: None,
+                },
+                &coins(PRINCIPAL, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::Unauthorized {}.to_string()
+            );
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), LENDER);
+            assert_eq!(native_balance(&app, USER_1), Uint128::zero());
+        }
+    }
+
+    mod group_buy {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::msg::{GroupBuyContribution, GroupBuyContributionsResponse};
+        use crate::state::{GroupBuy, GroupBuyStatus};
+        use cw_multi_test::App;
+
+        const CONTRIBUTOR_1: &str = "contributor1";
+        const CONTRIBUTOR_2: &str = "contributor2";
+        const VAULT: &str = "vault";
+        const PRICE: u128 = 1000;
+
+        // list a nft and create a group buy for it, the contributors get the funds to contribute
+        fn create_group_buy(app: &mut App, cw2981_address: &str, marketplace_address: &str) {
+            mint_and_approve(app, cw2981_address, marketplace_address, "1", USER_1);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(PRICE),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(CONTRIBUTOR_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::CreateGroupBuy {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    vault: VAULT.to_string(),
+                    deadline: Cw721Expiration::AtTime(app.block_info().time.plus_seconds(1000)),
+                },
+                &[],
+            )
+            .unwrap();
+            for contributor in [CONTRIBUTOR_1, CONTRIBUTOR_2] {
+                app.sudo(cw_multi_test::SudoMsg::Bank(
+                    cw_multi_test::BankSudo::Mint {
+                        to_address: contributor.to_string(),
+                        amount: coins(PRICE, NATIVE_DENOM),
+                    },
+                ))
+                .unwrap();
+            }
+        }
+
+        fn contribute(
+            app: &mut App,
+            marketplace_address: &str,
+            contributor: &str,
+            amount: u128,
+        ) -> Result<cw_multi_test::AppResponse, String> {
+            app.execute_contract(
+                Addr::unchecked(contributor),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ContributeGroupBuy { group_buy_id: 1 },
+                &coins(amount, NATIVE_DENOM),
+            )
+            .map_err(|err| err.source().unwrap().to_string())
+        }
+
+        // the contribution which raises the price buys the nft for the vault
+        #[test]
+        fn contributions_buy_listing_for_vault() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            create_group_buy(&mut app, &cw2981_address, &marketplace_address);
+            let creator_balance = native_balance(&app, ADMIN);
+            let seller_balance = native_balance(&app, USER_1);
+
+            contribute(&mut app, &marketplace_address, CONTRIBUTOR_1, 600).unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+
+            // the contribution above the remaining price is refunded
+            contribute(&mut app, &marketplace_address, CONTRIBUTOR_2, 600).unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), VAULT);
+            assert_eq!(native_balance(&app, CONTRIBUTOR_2), Uint128::from(600u128));
+
+            // the nft pays a royalty of 20% to the creator, the rest of the price is paid to the seller
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                creator_balance + Uint128::from(200u128)
+            );
+            assert_eq!(
+                native_balance(&app, USER_1),
+                seller_balance + Uint128::from(800u128)
+            );
+
+            let group_buy: GroupBuy = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::GroupBuy { group_buy_id: 1 },
+                )
+                .unwrap();
+            assert_eq!(group_buy.status, GroupBuyStatus::Completed {});
+            assert_eq!(group_buy.raised, Uint128::from(PRICE));
+
+            let res: GroupBuyContributionsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::GroupBuyContributions {
+                        group_buy_id: 1,
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(
+                res.contributions,
+                vec![
+                    GroupBuyContribution {
+                        contributor: Addr::unchecked(CONTRIBUTOR_1),
+                        amount: Uint128::from(600u128),
+                    },
+                    GroupBuyContribution {
+                        contributor: Addr::unchecked(CONTRIBUTOR_2),
+                        amount: Uint128::from(400u128),
+                    },
+                ]
+            );
+
+            // a completed group buy takes no more contributions
+            let res = contribute(&mut app, &marketplace_address, CONTRIBUTOR_1, 100);
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::GroupBuyNotOpen {}.to_string()
+            );
+        }
+
+        // the contributors are refunded once the listing is cancelled
+        #[test]
+        fn contributors_refunded_when_listing_disappears() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            create_group_buy(&mut app, &cw2981_address, &marketplace_address);
+
+            contribute(&mut app, &marketplace_address, CONTRIBUTOR_1, 300).unwrap();
+            contribute(&mut app, &marketplace_address, CONTRIBUTOR_2, 200).unwrap();
+
+            // the group buy cannot be refunded while the listing can be bought before the deadline
+            let res = app.execute_contract(
+                Addr::unchecked(CONTRIBUTOR_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::RefundGroupBuy { group_buy_id: 1 },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::GroupBuyNotRefundable {}.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Cancel {
+                    contract_address: cw2981_address,
+                    token_id: "1".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+
+            // the contributions cannot complete a listing which is gone
+            let res = contribute(&mut app, &marketplace_address, CONTRIBUTOR_1, 500);
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::ListingNotActive {}.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(CONTRIBUTOR_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::RefundGroupBuy { group_buy_id: 1 },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(native_balance(&app, CONTRIBUTOR_1), Uint128::from(PRICE));
+            assert_eq!(native_balance(&app, CONTRIBUTOR_2), Uint128::from(PRICE));
+
+            let group_buy: GroupBuy = app
+                .wrap()
+                .query_wasm_smart(marketplace_address, &QueryMsg::GroupBuy { group_buy_id: 1 })
+                .unwrap();
+            assert_eq!(group_buy.status, GroupBuyStatus::Refunded {});
+        }
+    }
+
+    mod installments {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::state::InstallmentPlan;
+        use cw_multi_test::App;
+
+        const BUYER: &str = "buyer";
+        const PRICE: u128 = 1000;
+        const DEPOSIT: u128 = 400;
+        const INTERVAL: u64 = 100;
+
+        // list a nft paid in 3 installments of 200 after the deposit
+        fn list_with_installments(app: &mut App, cw2981_address: &str, marketplace_address: &str) {
+            mint_and_approve(app, cw2981_address, marketplace_address, "1", USER_1);
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::Installments {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(PRICE),
+                        },
+                        deposit: Uint128::from(DEPOSIT),
+                        installments: 3,
+                        interval: INTERVAL,
+                        forfeit_bps: 5000,
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        // list a nft paid in installments and buy it
+        fn buy_with_installments(app: &mut App, cw2981_address: &str, marketplace_address: &str) {
+            list_with_installments(app, cw2981_address, marketplace_address);
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: BUYER.to_string(),
+                    amount: coins(PRICE, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    referrer: None,
+                },
+                &coins(DEPOSIT, NATIVE_DENOM),
+            )
+            .unwrap();
+        }
+
+        fn pay_installment(
+            app: &mut App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+            amount: u128,
+        ) -> Result<cw_multi_test::AppResponse, String> {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::PayInstallment {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                },
+                &coins(amount, NATIVE_DENOM),
+            )
+            .map_err(|err| err.source().unwrap().to_string())
+        }
+
+        // buying in installments escrows the nft and the deposit in the marketplace
+        #[test]
+        fn simulate_buy_with_installments() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            list_with_installments(&mut app, &cw2981_address, &marketplace_address);
+
+            let res: SimulationResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::SimulateBuy {
+                        contract_address: cw2981_address.clone(),
+                        token_id: "1".to_string(),
+                        buyer: Some(BUYER.to_string()),
+                        referrer: None,
+                    },
+                )
+                .unwrap();
+            assert_eq!(res.error, None);
+            assert_eq!(
+                res.transfers,
+                vec![
+                    SimulatedTransfer {
+                        kind: TransferKind::Nft,
+                        recipient: Some(Addr::unchecked(marketplace_address.clone())),
+                        asset: Asset::Nft(NFT {
+                            contract_address: Addr::unchecked(cw2981_address),
+                            token_id: Some("1".to_string()),
+                        }),
+                    },
+                    SimulatedTransfer {
+                        kind: TransferKind::Escrow,
+                        recipient: Some(Addr::unchecked(marketplace_address)),
+                        asset: Asset::Native(NATIVE {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: DEPOSIT,
+                        }),
+                    },
+                ]
+            );
+        }
+
+        // the nft is escrowed until the final installment, then the total is paid out
+        #[test]
+        fn buyer_receives_nft_on_final_installment() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            let creator_balance = native_balance(&app, ADMIN);
+            let seller_balance = native_balance(&app, USER_1);
+
+            buy_with_installments(&mut app, &cw2981_address, &marketplace_address);
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), marketplace_address);
+
+            let res = pay_installment(&mut app, &cw2981_address, &marketplace_address, 100);
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::InsufficientFunds {}.to_string()
+            );
+            pay_installment(&mut app, &cw2981_address, &marketplace_address, 200).unwrap();
+            app.update_block(|block| block.time = block.time.plus_seconds(INTERVAL));
+            pay_installment(&mut app, &cw2981_address, &marketplace_address, 200).unwrap();
+
+            let plan: InstallmentPlan = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::InstallmentPlan {
+                        contract_address: cw2981_address.clone(),
+                        token_id: "1".to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(plan.paid, Uint128::from(800u128));
+            assert_eq!(plan.installments_paid, 2);
+            assert_eq!(
+                plan.next_due,
+                app.block_info().time.plus_seconds(2 * INTERVAL)
+            );
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), marketplace_address);
+
+            let res =
+                pay_installment(&mut app, &cw2981_address, &marketplace_address, 200).unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), BUYER);
+
+            // the final installment has the attributes of the purchase
+            let wasm = res.events.iter().find(|event| event.ty == "wasm").unwrap();
+            let attribute = |key: &str| {
+                wasm.attributes
+                    .iter()
+                    .filter(|attr| attr.key == key)
+                    .map(|attr| attr.value.clone())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(attribute("method"), vec!["pay_installment"]);
+            assert_eq!(attribute("buyer"), vec![BUYER]);
+
+            // the nft pays a royalty of 20% to the creator, the rest of the total is paid to the seller
+            assert_eq!(
+                native_balance(&app, ADMIN),
+                creator_balance + Uint128::from(200u128)
+            );
+            assert_eq!(
+                native_balance(&app, USER_1),
+                seller_balance + Uint128::from(800u128)
+            );
+        }
+
+        // a missed installment forfeits a share of the paid funds to the seller
+        #[test]
+        fn missed_installment_returns_nft_to_seller() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+
+            buy_with_installments(&mut app, &cw2981_address, &marketplace_address);
+            let due = app.block_info().time.plus_seconds(INTERVAL);
+
+            let res = app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ForfeitInstallments {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InstallmentNotOverdue { due }.to_string()
+            );
+
+            app.update_block(|block| block.time = block.time.plus_seconds(INTERVAL));
+            let res = pay_installment(&mut app, &cw2981_address, &marketplace_address, 200);
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::InstallmentOverdue { due }.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ForfeitInstallments {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+            assert_eq!(native_balance(&app, USER_1), Uint128::from(DEPOSIT / 2));
+            assert_eq!(
+                native_balance(&app, BUYER),
+                Uint128::from(PRICE - DEPOSIT / 2)
+            );
+        }
+    }
+
+    mod price_schedule {
+        use super::helpers::{mint_and_approve, native_balance, owner_of};
+        use super::*;
+        use crate::state::PriceStep;
+        use cw_multi_test::App;
+
+        const BUYER: &str = "buyer";
+
+        fn list(
+            app: &mut App,
+            cw2981_address: &str,
+            marketplace_address: &str,
+            end_time: Option<Cw721Expiration>,
+            price_schedule: Vec<PriceStep>,
+        ) -> Result<cw_multi_test::AppResponse, String> {
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.to_string(),
+                    token_id: "1".to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(1000u128),
+                        },
+                        start_time: None,
+                        end_time,
+                        price_schedule,
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .map_err(|err| err.source().unwrap().to_string())
+        }
+
+        fn listed_price(app: &App, cw2981_address: &str, marketplace_address: &str) -> Uint128 {
+            let listing: Listing = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address,
+                    &QueryMsg::Listing {
+                        contract_address: cw2981_address.to_string(),
+                        token_id: "1".to_string(),
+                        viewer: None,
+                    },
+                )
+                .unwrap();
+            match listing.auction_config {
+                AuctionConfig::FixedPrice { price, .. } => price.amount,
+                _ => panic!("not a fixed price listing"),
+            }
+        }
+
+        // the steps must have increasing times within the listing period
+        #[test]
+        fn schedule_must_be_monotone_within_period() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+            let now = app.block_info().time;
+
+            let res = list(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                None,
+                vec![
+                    PriceStep {
+                        time: now.plus_seconds(200),
+                        price: Uint128::from(800u128),
+                    },
+                    PriceStep {
+                        time: now.plus_seconds(100),
+                        price: Uint128::from(500u128),
+                    },
+                ],
+            );
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::InvalidAuctionConfig {}.to_string()
+            );
+
+            let res = list(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                Some(Cw721Expiration::AtTime(now.plus_seconds(100))),
+                vec![PriceStep {
+                    time: now.plus_seconds(100),
+                    price: Uint128::from(800u128),
+                }],
+            );
+            assert_eq!(
+                res.unwrap_err(),
+                ContractError::InvalidAuctionConfig {}.to_string()
+            );
+        }
+
+        // the listing is bought at the price of the last step reached
+        #[test]
+        fn buy_at_scheduled_price() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            mint_and_approve(&mut app, &cw2981_address, &marketplace_address, "1", USER_1);
+            let now = app.block_info().time;
+
+            list(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                None,
+                vec![
+                    PriceStep {
+                        time: now.plus_seconds(100),
+                        price: Uint128::from(800u128),
+                    },
+                    PriceStep {
+                        time: now.plus_seconds(200),
+                        price: Uint128::from(500u128),
+                    },
+                ],
+            )
+            .unwrap();
+            assert_eq!(
+                listed_price(&app, &cw2981_address, &marketplace_address),
+                Uint128::from(1000u128)
+            );
+
+            app.update_block(|block| block.time = block.time.plus_seconds(150));
+            assert_eq!(
+                listed_price(&app, &cw2981_address, &marketplace_address),
+                Uint128::from(800u128)
+            );
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: BUYER.to_string(),
+                    amount: coins(1000, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+
+            // the listed price is no longer accepted
+            let res = app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    referrer: None,
+                },
+                &coins(1000, NATIVE_DENOM),
+            );
+            assert_eq!(
+                res.unwrap_err().source().unwrap().to_string(),
+                ContractError::InsufficientFunds {}.to_string()
+            );
+
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::Buy {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "1".to_string(),
+                    referrer: None,
+                },
+                &coins(800, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), BUYER);
+        }
+
+        // the listings are swept by the price reached by their schedule
+        #[test]
+        fn sweep_at_scheduled_price() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            for token_id in ["1", "2"] {
+                mint_and_approve(
+                    &mut app,
+                    &cw2981_address,
+                    &marketplace_address,
+                    token_id,
+                    USER_1,
+                );
+            }
+            let now = app.block_info().time;
+
+            // "1" is listed at 1000 and drops to 300, "2" is listed at 500
+            list(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                None,
+                vec![PriceStep {
+                    time: now.plus_seconds(100),
+                    price: Uint128::from(300u128),
+                }],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "2".to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(500u128),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            app.update_block(|block| block.time = block.time.plus_seconds(150));
+
+            // the listings of the collection are returned at their scheduled price
+            let res: ListingsResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    marketplace_address.clone(),
+                    &QueryMsg::ListingsByContractAddress {
+                        contract_address: cw2981_address.clone(),
+                        start_after: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            assert!(matches!(
+                &res.listings[0].auction_config,
+                AuctionConfig::FixedPrice { price, .. } if price.amount == Uint128::from(300u128)
+            ));
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: BUYER.to_string(),
+                    amount: coins(1000, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+
+            // the cheapest listing is "1", although its listed price is above the max price
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::SweepCollection {
+                    contract_address: cw2981_address.clone(),
+                    max_items: 1,
+                    max_price_per_item: Uint128::from(600u128),
+                },
+                &coins(1000, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), BUYER);
+            assert_eq!(owner_of(&app, &cw2981_address, "2"), USER_1);
+        }
+
+        // a listing indexed by a scheduled price it has not reached yet is skipped by the sweep
+        #[test]
+        fn sweep_skips_listing_before_its_scheduled_price() {
+            let (mut app, contracts) = instantiate_contracts();
+            let cw2981_address = contracts[0].contract_addr.clone();
+            let marketplace_address = contracts[1].contract_addr.clone();
+            for token_id in ["1", "2"] {
+                mint_and_approve(
+                    &mut app,
+                    &cw2981_address,
+                    &marketplace_address,
+                    token_id,
+                    USER_1,
+                );
+            }
+            let now = app.block_info().time;
+
+            // "1" is indexed at 300 but is still sold at 1000, "2" is listed at 500
+            list(
+                &mut app,
+                &cw2981_address,
+                &marketplace_address,
+                None,
+                vec![PriceStep {
+                    time: now.plus_seconds(100),
+                    price: Uint128::from(300u128),
+                }],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(USER_1),
+                Addr::unchecked(marketplace_address.clone()),
+                &ExecuteMsg::ListNft {
+                    contract_address: cw2981_address.clone(),
+                    token_id: "2".to_string(),
+                    auction_config: AuctionConfig::FixedPrice {
+                        price: Coin {
+                            denom: NATIVE_DENOM.to_string(),
+                            amount: Uint128::from(500u128),
+                        },
+                        start_time: None,
+                        end_time: None,
+                        price_schedule: vec![],
+                    },
+                    reserved_for: None,
+                },
+                &[],
+            )
+            .unwrap();
+
+            app.sudo(cw_multi_test::SudoMsg::Bank(
+                cw_multi_test::BankSudo::Mint {
+                    to_address: BUYER.to_string(),
+                    amount: coins(1000, NATIVE_DENOM),
+                },
+            ))
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                Addr::unchecked(marketplace_address),
+                &ExecuteMsg::SweepCollection {
+                    contract_address: cw2981_address.clone(),
+                    max_items: 2,
+                    max_price_per_item: Uint128::from(600u128),
+                },
+                &coins(1000, NATIVE_DENOM),
+            )
+            .unwrap();
+            assert_eq!(owner_of(&app, &cw2981_address, "1"), USER_1);
+            assert_eq!(owner_of(&app, &cw2981_address, "2"), BUYER);
+            assert_eq!(native_balance(&app, BUYER), Uint128::from(500u128));
+        }
     }
 }
@@ -1,25 +1,35 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
 use thiserror::Error;
 
+// each error message starts with a stable code, so that clients can match on it
+// the codes are shared with nft-marketplace for the same errors
+// the codes of existing errors must not change, new errors take new codes
 #[derive(Error, Debug)]
 pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
-    #[error("Unauthorized")]
+    #[error("E100: Unauthorized")]
     Unauthorized {},
 
-    #[error("Already Exists")]
+    #[error("E101: Already Exists")]
     AlreadyExists {},
 
-    #[error("Listing Not Active")]
+    #[error("E102: Insufficient Funds")]
+    InsufficientFunds {},
+
+    #[error("E104: Require never expired approval")]
+    ApprovalMustNeverExpire {},
+
+    #[error("E107: Royalty {royalty} exceeds the price {price}")]
+    RoyaltyExceedsPrice { royalty: Uint128, price: Uint128 },
+
+    #[error("E200: Listing Not Active")]
     ListingNotActive {},
 
-    #[error("Insufficient Funds")]
-    InsufficientFunds {},
+    #[error("E201: Invalid auction config")]
+    InvalidAuctionConfig {},
 
-    #[error("Custom Error val: {val:?}")]
-    CustomError { val: String },
-    // Add any other custom errors you like here.
-    // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
+    #[error("E202: Owner cannot buy")]
+    OwnerCannotBuy {},
 }
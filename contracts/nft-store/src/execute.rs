@@ -106,16 +106,12 @@ impl StoreContract<'static> {
                 _ => return Err(ContractError::Unauthorized {}),
             },
             Err(_) => {
-                return Err(ContractError::CustomError {
-                    val: "Require never expired approval".to_string(),
-                });
+                return Err(ContractError::ApprovalMustNeverExpire {});
             }
         }
 
         if !self.validate_auction_config(&auction_config) {
-            return Err(ContractError::CustomError {
-                val: "Invalid auction config".to_string(),
-            });
+            return Err(ContractError::InvalidAuctionConfig {});
         }
 
         // add a nft to listings
@@ -143,9 +139,7 @@ impl StoreContract<'static> {
                 .add_attribute("contract_address", contract_address)
                 .add_attribute("token_id", token_id)
                 .add_attribute("auction_config", auction_config_str)),
-            Err(_) => Err(ContractError::CustomError {
-                val: ("Auction Config Error".to_string()),
-            }),
+            Err(_) => Err(ContractError::InvalidAuctionConfig {}),
         }
     }
 
@@ -171,9 +165,7 @@ impl StoreContract<'static> {
 
         // check if buyer is the same as seller
         if info.sender == config.owner {
-            return Err(ContractError::CustomError {
-                val: ("Owner cannot buy".to_string()),
-            });
+            return Err(ContractError::OwnerCannotBuy {});
         }
 
         // remove the listing
@@ -186,9 +178,7 @@ impl StoreContract<'static> {
             _ => {
                 // TODO where should we store auction_contract? in auction_config or as in a list
                 // get auction contract and validate bid
-                Err(ContractError::CustomError {
-                    val: ("Invalid Auction Config".to_string()),
-                })
+                Err(ContractError::InvalidAuctionConfig {})
             }
         }
     }
@@ -258,11 +248,9 @@ impl StoreContract<'static> {
             res = res.add_message(transfer_token_msg);
         } else if let (Some(royalty_amount), Some(creator)) = (royalty_amount, creator) {
             if royalty_amount > price.amount {
-                return Err(ContractError::CustomError {
-                    val: (format!(
-                        "Royalty amount is greater than price: {} {}",
-                        royalty_amount, price
-                    )),
+                return Err(ContractError::RoyaltyExceedsPrice {
+                    royalty: royalty_amount,
+                    price: price.amount,
                 });
             }
 
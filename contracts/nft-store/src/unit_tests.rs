@@ -476,7 +476,7 @@ mod tests {
         let response = execute(deps.as_mut(), mock_env(), mock_info_wrong_sender, msg);
         match response {
             Ok(_) => panic!("Expected error"),
-            Err(ContractError::CustomError { .. }) => {}
+            Err(ContractError::OwnerCannotBuy {}) => {}
             Err(e) => panic!("Unexpected error: {}", e),
         }
     }